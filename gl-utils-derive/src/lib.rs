@@ -7,7 +7,7 @@ extern crate quote;
 use proc_macro::TokenStream;
 
 #[allow(non_snake_case)]
-#[proc_macro_derive(VertexAttribFields)]
+#[proc_macro_derive(VertexAttribFields, attributes(vertex))]
 pub fn derive_VertexAttribFields(input: TokenStream) -> TokenStream
 {
     let s   = input.to_string();
@@ -16,22 +16,237 @@ pub fn derive_VertexAttribFields(input: TokenStream) -> TokenStream
     gen.parse().unwrap()
 }
 
+#[allow(non_snake_case)]
+#[proc_macro_derive(Uniforms, attributes(uniform))]
+pub fn derive_Uniforms(input: TokenStream) -> TokenStream
+{
+    let s   = input.to_string();
+    let ast = syn::parse_macro_input(&s).unwrap();
+    let gen = impl_Uniforms(&ast);
+    gen.parse().unwrap()
+}
+
+#[allow(non_snake_case)]
+#[proc_macro_derive(Std140, attributes(std140))]
+pub fn derive_Std140(input: TokenStream) -> TokenStream
+{
+    let s   = input.to_string();
+    let ast = syn::parse_macro_input(&s).unwrap();
+    let gen = impl_block_layout(&ast, "std140");
+    gen.parse().unwrap()
+}
+
+#[allow(non_snake_case)]
+#[proc_macro_derive(Std430, attributes(std430))]
+pub fn derive_Std430(input: TokenStream) -> TokenStream
+{
+    let s   = input.to_string();
+    let ast = syn::parse_macro_input(&s).unwrap();
+    let gen = impl_block_layout(&ast, "std430");
+    gen.parse().unwrap()
+}
+
+/// The options a field's `#[vertex(...)]` attributes set.
+struct FieldOptions {
+    name:       Option<String>,
+    normalized: bool,
+    integer:    bool,
+    float:      bool,
+    location:   Option<u64>,
+    skip:       bool,
+}
+
+fn field_options(field: &syn::Field) -> FieldOptions
+{
+    use syn::{Lit, MetaItem, NestedMetaItem};
+    let field_name = field.ident.as_ref().map_or("", |i| i.as_ref());
+    let mut options = FieldOptions {
+        name:       None,
+        normalized: false,
+        integer:    false,
+        float:      false,
+        location:   None,
+        skip:       false,
+    };
+    for attr in &field.attrs {
+        let items = match attr.value {
+            MetaItem::List(ref ident, ref items) if ident == "vertex" => items,
+            _ => continue,
+        };
+        for item in items {
+            match *item {
+                NestedMetaItem::MetaItem(MetaItem::Word(ref w)) if w == "normalized" =>
+                    options.normalized = true,
+                NestedMetaItem::MetaItem(MetaItem::Word(ref w)) if w == "integer" =>
+                    options.integer = true,
+                NestedMetaItem::MetaItem(MetaItem::Word(ref w)) if w == "float" =>
+                    options.float = true,
+                NestedMetaItem::MetaItem(MetaItem::Word(ref w)) if w == "skip" =>
+                    options.skip = true,
+                NestedMetaItem::MetaItem(MetaItem::NameValue(ref n, Lit::Str(ref v, _)))
+                    if n == "name" => options.name = Some(v.clone()),
+                NestedMetaItem::MetaItem(MetaItem::NameValue(ref n, Lit::Int(v, _)))
+                    if n == "location" => options.location = Some(v),
+                _ => panic!("unsupported #[vertex] option on field `{}`", field_name),
+            }
+        }
+    }
+    if options.normalized && options.integer {
+        panic!("field `{}` cannot be both normalized and integer", field_name);
+    }
+    if options.float && options.integer {
+        panic!("field `{}` cannot be both float and integer", field_name);
+    }
+    options
+}
+
 #[allow(non_snake_case)]
 fn impl_VertexAttribFields(ast: &syn::MacroInput) -> quote::Tokens
 {
     use syn::Body::*;
     let ident = &ast.ident;
 
-    match &ast.body {
-        &Enum(..)        => panic!("enums are not supported."),
-        &Struct(ref var) => {
-            let fields      = var.fields();
-            let field_ident = &fields.iter()
-                .filter_map(|f| f.clone().ident )
-                .collect::<Vec<syn::Ident>>();
+    match ast.body {
+        Enum(..)        => panic!("enums are not supported."),
+        Struct(ref var) => {
+            let fields = var.fields().iter()
+                .map(|f| {
+                    let field_ident = f.ident.clone()
+                        .unwrap_or_else(|| panic!("tuple structs are not supported."));
+                    (field_ident, field_options(f))
+                })
+                .filter(|(_, o)| !o.skip)
+                .map(|(field_ident, o)| {
+                    let name       = o.name.unwrap_or_else(|| field_ident.to_string());
+                    let normalized = o.normalized;
+                    // Normalized integers are converted to floats.
+                    let kind = syn::Ident::new(
+                        if o.integer                   { "Integer" }
+                        else if o.normalized || o.float { "Float" }
+                        else                           { "auto" });
+                    let location = syn::Ident::new(
+                        o.location.map_or("auto".to_string(), |l| l.to_string()));
+                    quote! { #field_ident: #name, #normalized, #kind, #location; }
+                })
+                .collect::<Vec<_>>();
+            if fields.is_empty() {
+                panic!("`{}` has no vertex attributes: every field is skipped.", ident);
+            }
 
             quote! {
-                implement_vertex_attrib!(#ident, #(#field_ident),*);
+                implement_vertex_attrib!(#ident { #(#fields)* });
+            }
+        },
+    }
+}
+
+// The GLSL name a field's `#[uniform(...)]` attributes give it, or None if
+// it is skipped.
+fn uniform_name(field: &syn::Field) -> Option<String>
+{
+    use syn::{Lit, MetaItem, NestedMetaItem};
+    let field_name = field.ident.as_ref().map_or("", |i| i.as_ref());
+    let mut name = Some(field_name.to_string());
+    for attr in &field.attrs {
+        let items = match attr.value {
+            MetaItem::List(ref ident, ref items) if ident == "uniform" => items,
+            _ => continue,
+        };
+        for item in items {
+            match *item {
+                NestedMetaItem::MetaItem(MetaItem::Word(ref w)) if w == "skip" =>
+                    name = None,
+                NestedMetaItem::MetaItem(MetaItem::NameValue(ref n, Lit::Str(ref v, _)))
+                    if n == "name" => name = name.map(|_| v.clone()),
+                _ => panic!("unsupported #[uniform] option on field `{}`", field_name),
+            }
+        }
+    }
+    name
+}
+
+#[allow(non_snake_case)]
+fn impl_Uniforms(ast: &syn::MacroInput) -> quote::Tokens
+{
+    use syn::Body::*;
+    let ident = &ast.ident;
+    if !ast.generics.ty_params.is_empty() {
+        panic!("type parameters are not supported.");
+    }
+    let lifetimes = ast.generics.lifetimes.iter()
+        .map(|l| &l.lifetime)
+        .collect::<Vec<_>>();
+
+    match ast.body {
+        Enum(..)        => panic!("enums are not supported."),
+        Struct(ref var) => {
+            let fields = var.fields().iter()
+                .filter_map(|f| {
+                    let field_ident = f.ident.clone()
+                        .unwrap_or_else(|| panic!("tuple structs are not supported."));
+                    let name        = uniform_name(f)?;
+                    Some(quote! { #field_ident: #name; })
+                })
+                .collect::<Vec<_>>();
+
+            quote! {
+                implement_uniforms!(#ident<#(#lifetimes),*> { #(#fields)* });
+            }
+        },
+    }
+}
+
+// The offset a field's `#[std140(offset = N)]` or `#[std430(offset = N)]`
+// attribute expects, if any.
+fn layout_offset(field: &syn::Field, layout: &str) -> Option<u64>
+{
+    use syn::{Lit, MetaItem, NestedMetaItem};
+    let field_name = field.ident.as_ref().map_or("", |i| i.as_ref());
+    let mut offset = None;
+    for attr in &field.attrs {
+        let items = match attr.value {
+            MetaItem::List(ref ident, ref items) if ident == layout => items,
+            _ => continue,
+        };
+        for item in items {
+            match *item {
+                NestedMetaItem::MetaItem(MetaItem::NameValue(ref n, Lit::Int(v, _)))
+                    if n == "offset" => offset = Some(v),
+                _ => panic!("unsupported #[{}] option on field `{}`", layout, field_name),
+            }
+        }
+    }
+    offset
+}
+
+// Expands to `implement_std140!` or `implement_std430!`, for `layout`.
+fn impl_block_layout(ast: &syn::MacroInput, layout: &str) -> quote::Tokens
+{
+    use syn::Body::*;
+    let ident = &ast.ident;
+    if !ast.generics.ty_params.is_empty() || !ast.generics.lifetimes.is_empty() {
+        panic!("generic structs are not supported.");
+    }
+
+    match ast.body {
+        Enum(..)        => panic!("enums are not supported."),
+        Struct(ref var) => {
+            let fields = var.fields().iter()
+                .map(|f| {
+                    let field_ident = f.ident.clone()
+                        .unwrap_or_else(|| panic!("tuple structs are not supported."));
+                    let offset = syn::Ident::new(
+                        layout_offset(f, layout).map_or("auto".to_string(), |o| o.to_string()));
+                    quote! { #field_ident: #offset; }
+                })
+                .collect::<Vec<_>>();
+            if fields.is_empty() {
+                panic!("empty structs are not supported.");
+            }
+
+            let implement = syn::Ident::new(format!("implement_{}", layout));
+            quote! {
+                #implement!(#ident { #(#fields)* });
             }
         },
     }
@@ -39,7 +254,122 @@ fn impl_VertexAttribFields(ast: &syn::MacroInput) -> quote::Tokens
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    fn expand(input: &str) -> String
+    {
+        impl_VertexAttribFields(&syn::parse_macro_input(input).unwrap()).to_string()
+    }
+
     #[test]
     fn it_works() {
     }
+
+    #[test]
+    fn plain_fields_use_their_names()
+    {
+        assert_eq!(expand("struct V { pos: [f32; 3] }"),
+                   quote! {
+                       implement_vertex_attrib!(V { pos: "pos", false, auto, auto; });
+                   }.to_string());
+    }
+
+    #[test]
+    fn field_options_are_passed_on()
+    {
+        let expanded = expand(r#"
+            struct V {
+                #[vertex(name = "a_position", location = 3)]
+                pos: [f32; 3],
+                #[vertex(normalized)]
+                color: [u8; 4],
+                #[vertex(integer)]
+                joints: [u16; 4],
+                #[vertex(float)]
+                weights: [u8; 4],
+                #[vertex(skip)]
+                padding: u32,
+            }"#);
+        assert_eq!(expanded,
+                   quote! {
+                       implement_vertex_attrib!(V {
+                           pos:    "a_position", false, auto, 3;
+                           color:  "color", true, Float, auto;
+                           joints: "joints", false, Integer, auto;
+                           weights: "weights", false, Float, auto;
+                       });
+                   }.to_string());
+    }
+
+    fn expand_uniforms(input: &str) -> String
+    {
+        impl_Uniforms(&syn::parse_macro_input(input).unwrap()).to_string()
+    }
+
+    #[test]
+    fn uniforms_keep_lifetimes_and_names()
+    {
+        let expanded = expand_uniforms(r#"
+            struct Scene<'a> {
+                #[uniform(name = "u_mvp")]
+                mvp: [[f32; 4]; 4],
+                albedo: TextureRef<'a>,
+                #[uniform(skip)]
+                frame: u64,
+            }"#);
+        assert_eq!(expanded,
+                   quote! {
+                       implement_uniforms!(Scene<'a> {
+                           mvp:    "u_mvp";
+                           albedo: "albedo";
+                       });
+                   }.to_string());
+    }
+
+    #[test]
+    fn layout_offsets_are_passed_on()
+    {
+        let expanded = impl_block_layout(&syn::parse_macro_input(r#"
+            struct Light {
+                color: [f32; 3],
+                #[std140(offset = 12)]
+                intensity: f32,
+            }"#).unwrap(), "std140").to_string();
+        assert_eq!(expanded,
+                   quote! {
+                       implement_std140!(Light { color: auto; intensity: 12; });
+                   }.to_string());
+        let expanded = impl_block_layout(&syn::parse_macro_input(r#"
+            struct Particle {
+                #[std430(offset = 0)]
+                velocity: [f32; 2],
+                #[std140(offset = 16)]
+                age: f32,
+            }"#).unwrap(), "std430").to_string();
+        assert_eq!(expanded,
+                   quote! {
+                       implement_std430!(Particle { velocity: 0; age: auto; });
+                   }.to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "both normalized and integer")]
+    fn normalized_integer_is_rejected()
+    {
+        expand("struct V { #[vertex(normalized, integer)] c: [u8; 4] }");
+    }
+
+    #[test]
+    #[should_panic(expected = "every field is skipped")]
+    fn all_skipped_is_rejected()
+    {
+        expand("struct V { #[vertex(skip)] a: u32, #[vertex(skip)] b: f32 }");
+    }
+
+    #[test]
+    #[should_panic(expected = "tuple structs are not supported")]
+    fn tuple_struct_uniforms_are_rejected()
+    {
+        expand_uniforms("struct Scene(f32, [f32; 4]);");
+    }
 }
@@ -0,0 +1,81 @@
+extern crate gl;
+#[macro_use]
+extern crate gl_utils;
+#[macro_use]
+extern crate gl_utils_derive;
+
+use gl::types::*;
+use gl_utils::std140::{Std140, to_std140_bytes};
+use gl_utils::uniform::{UniformEnum, Uniforms};
+use gl_utils::util::{VertexAttribFields, VertexAttribKind};
+
+#[repr(C)]
+#[derive(Copy,Clone,VertexAttribFields)]
+struct Vertex {
+    #[vertex(name = "a_position", location = 2)]
+    pos:     [GLfloat; 3],
+    #[vertex(normalized)]
+    color:   [u8; 4],
+    #[vertex(integer)]
+    joints:  [u16; 4],
+    #[vertex(float)]
+    weights: [u16; 4],
+    #[vertex(skip)]
+    _pad:    u32,
+}
+
+#[test]
+fn vertex_layout_follows_the_field_options()
+{
+    assert_eq!(Vertex::names(), &["a_position", "color", "joints", "weights"]);
+    assert_eq!(Vertex::locations(), &[Some(2), None, None, None]);
+    assert_eq!(Vertex::kinds(), &[VertexAttribKind::Float, VertexAttribKind::Float,
+                                  VertexAttribKind::Integer, VertexAttribKind::Float]);
+    assert_eq!(Vertex::normalizeds(), &[gl::FALSE, gl::TRUE, gl::FALSE, gl::FALSE]);
+    assert_eq!(Vertex::types(), &[gl::FLOAT, gl::UNSIGNED_BYTE,
+                                  gl::UNSIGNED_SHORT, gl::UNSIGNED_SHORT]);
+    assert_eq!(Vertex::stride(), 36);
+    assert_eq!(Vertex::pointers().iter().map(|&p| p as usize).collect::<Vec<_>>(),
+               vec![0, 12, 16, 24]);
+}
+
+#[derive(Uniforms)]
+struct Scene {
+    #[uniform(name = "u_scale")]
+    scale: [GLfloat; 2],
+    #[uniform(skip)]
+    _frame: u64,
+    tint:  [GLfloat; 4],
+}
+
+#[test]
+fn uniforms_are_visited_by_name()
+{
+    assert_eq!(Scene::uniform_names(), &["u_scale", "tint"]);
+    let scene = Scene { scale: [2.0, 3.0], _frame: 7, tint: [1.0; 4] };
+    let mut visited = Vec::new();
+    scene.visit_uniforms(&mut |u| {
+        visited.push(match u {
+            UniformEnum::Vec2(v) => v[0].to_vec(),
+            UniformEnum::Vec4(v) => v[0].to_vec(),
+            _                    => panic!("unexpected uniform"),
+        });
+        Ok(())
+    }).unwrap();
+    assert_eq!(visited, vec![vec![2.0, 3.0], vec![1.0; 4]]);
+}
+
+#[derive(Std140)]
+struct Light {
+    color:     [GLfloat; 3],
+    #[std140(offset = 12)]
+    intensity: GLfloat,
+}
+
+#[test]
+fn std140_packs_into_the_vec3_padding()
+{
+    assert_eq!(Light::STD140_SIZE, 16);
+    let bytes = to_std140_bytes(&Light { color: [0.0; 3], intensity: 1.0 });
+    assert_eq!(&bytes[12..], &1.0f32.to_ne_bytes());
+}
@@ -0,0 +1,366 @@
+use gl;
+use gl::types::*;
+use context::{Slot, changing};
+use error::{Error, clear_pending_errors, log_pending_errors, report_drop_error};
+
+use std::cell::Cell;
+use std::convert::TryFrom;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use std::rc::Rc;
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub enum BufferTarget {
+    Array             = gl::ARRAY_BUFFER as isize,
+    ElementArray      = gl::ELEMENT_ARRAY_BUFFER as isize,
+    CopyRead          = gl::COPY_READ_BUFFER as isize,
+    CopyWrite         = gl::COPY_WRITE_BUFFER as isize,
+    PixelPack         = gl::PIXEL_PACK_BUFFER as isize,
+    PixelUnpack       = gl::PIXEL_UNPACK_BUFFER as isize,
+    TransformFeedback = gl::TRANSFORM_FEEDBACK_BUFFER as isize,
+    Texture           = gl::TEXTURE_BUFFER as isize,
+    Uniform           = gl::UNIFORM_BUFFER as isize,
+    ShaderStorage     = gl::SHADER_STORAGE_BUFFER as isize,
+    AtomicCounter     = gl::ATOMIC_COUNTER_BUFFER as isize,
+    DrawIndirect      = gl::DRAW_INDIRECT_BUFFER as isize,
+    DispatchIndirect  = gl::DISPATCH_INDIRECT_BUFFER as isize,
+    Query             = gl::QUERY_BUFFER as isize,
+}
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum BufferUsage {
+    StreamDraw  = gl::STREAM_DRAW as isize,
+    StreamRead  = gl::STREAM_READ as isize,
+    StreamCopy  = gl::STREAM_COPY as isize,
+    StaticDraw  = gl::STATIC_DRAW as isize,
+    StaticRead  = gl::STATIC_READ as isize,
+    StaticCopy  = gl::STATIC_COPY as isize,
+    DynamicDraw = gl::DYNAMIC_DRAW as isize,
+    DynamicRead = gl::DYNAMIC_READ as isize,
+    DynamicCopy = gl::DYNAMIC_COPY as isize,
+}
+
+impl BufferTarget {
+    pub fn to_gl(self) -> GLenum
+    {
+        self as GLenum
+    }
+}
+
+impl BufferUsage {
+    pub fn to_gl(self) -> GLenum
+    {
+        self as GLenum
+    }
+}
+
+/// Plain old data, which can be copied to and from buffer memory byte for
+/// byte.
+///
+/// # Safety
+///
+/// Every bit pattern of the type's size must be a valid value, and it must
+/// have no padding. `#[repr(C)]` structs made only of `Pod` fields of the
+/// same alignment qualify.
+pub unsafe trait Pod: Copy + 'static {}
+
+unsafe impl Pod for u8 {}
+unsafe impl Pod for i8 {}
+unsafe impl Pod for u16 {}
+unsafe impl Pod for i16 {}
+unsafe impl Pod for u32 {}
+unsafe impl Pod for i32 {}
+unsafe impl Pod for u64 {}
+unsafe impl Pod for i64 {}
+unsafe impl Pod for f32 {}
+unsafe impl Pod for f64 {}
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+/// A buffer object holding `len()` elements of `T`.
+///
+/// Uploads and readback go through the copy targets, so they leave the
+/// array and element array bindings, and the bound vertex array, alone.
+pub struct Buffer<T: Pod> {
+    pub(crate) handle: Rc<BufferHandle>,
+    target:  BufferTarget,
+    phantom: PhantomData<T>,
+}
+
+#[derive(Debug)]
+pub(crate) struct BufferHandle {
+    pub(crate) id: GLuint,
+    len:   Cell<usize>,
+    usage: Cell<BufferUsage>,
+}
+
+impl BufferHandle {
+    fn new() -> Result<Self, Error>
+    {
+        unsafe {
+            let mut buffer = BufferHandle {
+                id:    0,
+                len:   Cell::new(0),
+                usage: Cell::new(BufferUsage::StaticDraw),
+            };
+            gl::GenBuffers(1, &mut buffer.id);
+            check_error!();
+            Ok(buffer)
+        }
+    }
+
+    fn delete(&mut self) -> Result<(), Error>
+    {
+        if self.id == 0 {
+            return Ok(())
+        }
+        unsafe {
+            clear_pending_errors();
+            gl::DeleteBuffers(1, &self.id);
+            self.id = 0;
+            check_error!();
+            Ok(())
+        }
+    }
+}
+
+impl Drop for BufferHandle {
+    fn drop(&mut self)
+    {
+        log_pending_errors();
+        if let Err(e) = self.delete() {
+            report_drop_error(e);
+        }
+    }
+}
+
+fn byte_len<T>(len: usize) -> Result<GLsizeiptr, Error>
+{
+    len.checked_mul(mem::size_of::<T>())
+        .and_then(|bytes| GLsizeiptr::try_from(bytes).ok())
+        .ok_or_else(|| Error::Validation(format!(
+            "{} elements of {} bytes are too many for a buffer",
+            len, mem::size_of::<T>())))
+}
+
+impl<T: Pod> Buffer<T> {
+    /// Makes an empty buffer that `bind` will bind to `target`.
+    pub fn new(target: BufferTarget) -> Result<Self, Error>
+    {
+        let h = BufferHandle::new()?;
+        Ok(Buffer { handle: Rc::new(h), target, phantom: PhantomData })
+    }
+
+    pub fn new_with_data(target: BufferTarget, data: &[T], usage: BufferUsage)
+        -> Result<Self, Error>
+    {
+        let buffer = Buffer::new(target)?;
+        buffer.set_data(data, usage)?;
+        Ok(buffer)
+    }
+
+    /// Deletes the buffer, returning any error instead of handing it to the
+    /// drop error policy. While other clones are alive this only releases
+    /// this one, and the buffer is deleted when the last clone drops.
+    pub fn delete(self) -> Result<(), Error>
+    {
+        match Rc::try_unwrap(self.handle) {
+            Ok(mut h) => h.delete(),
+            Err(_)    => Ok(()),
+        }
+    }
+
+    pub fn id(&self) -> GLuint
+    {
+        self.handle.id
+    }
+
+    pub fn target(&self) -> BufferTarget
+    {
+        self.target
+    }
+
+    pub fn usage(&self) -> BufferUsage
+    {
+        self.handle.usage.get()
+    }
+
+    /// The number of elements, not bytes.
+    pub fn len(&self) -> usize
+    {
+        self.handle.len.get()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.len() == 0
+    }
+
+    pub fn bind(&self) -> Result<(), Error>
+    {
+        unsafe {
+            changing(Slot::Buffer(self.target));
+            gl::BindBuffer(self.target.to_gl(), self.handle.id);
+            check_error!();
+            Ok(())
+        }
+    }
+
+    // Allocates storage for `len` elements, copying `data` into it unless
+    // it is null.
+    fn allocate(&self, len: usize, data: *const T, usage: BufferUsage) -> Result<(), Error>
+    {
+        let bytes = byte_len::<T>(len)?;
+        unsafe {
+            changing(Slot::Buffer(BufferTarget::CopyWrite));
+            gl::BindBuffer(gl::COPY_WRITE_BUFFER, self.handle.id);
+            check_error!();
+            gl::BufferData(gl::COPY_WRITE_BUFFER, bytes,
+                           data as *const _, usage.to_gl());
+            check_error!();
+        }
+        self.handle.len.set(len);
+        self.handle.usage.set(usage);
+        Ok(())
+    }
+
+    /// Replaces the storage with a copy of `data`.
+    pub fn set_data(&self, data: &[T], usage: BufferUsage) -> Result<(), Error>
+    {
+        self.allocate(data.len(), data.as_ptr(), usage)
+    }
+
+    /// Reallocates the storage to hold `len` elements, keeping the usage.
+    /// The contents are undefined afterwards.
+    pub fn resize(&self, len: usize) -> Result<(), Error>
+    {
+        self.allocate(len, ::std::ptr::null(), self.usage())
+    }
+
+    /// Overwrites the elements from element `offset` on with `data`, which
+    /// must fit within `len()`.
+    pub fn update(&self, offset: usize, data: &[T]) -> Result<(), Error>
+    {
+        if offset.checked_add(data.len()).is_none_or(|end| end > self.len()) {
+            return Err(Error::Validation(format!(
+                "updating {} elements from element {} of a buffer of {}",
+                data.len(), offset, self.len())))
+        }
+        let (start, bytes) = (byte_len::<T>(offset)?, byte_len::<T>(data.len())?);
+        unsafe {
+            changing(Slot::Buffer(BufferTarget::CopyWrite));
+            gl::BindBuffer(gl::COPY_WRITE_BUFFER, self.handle.id);
+            check_error!();
+            gl::BufferSubData(gl::COPY_WRITE_BUFFER, start as GLintptr, bytes,
+                              data.as_ptr() as *const _);
+            check_error!();
+        }
+        Ok(())
+    }
+
+    /// Copies the whole buffer back from GL.
+    pub fn read_back(&self) -> Result<Vec<T>, Error>
+    {
+        let len = self.len();
+        let bytes = byte_len::<T>(len)?;
+        let mut data = Vec::with_capacity(len);
+        unsafe {
+            changing(Slot::Buffer(BufferTarget::CopyRead));
+            gl::BindBuffer(gl::COPY_READ_BUFFER, self.handle.id);
+            check_error!();
+            gl::GetBufferSubData(gl::COPY_READ_BUFFER, 0, bytes,
+                                 data.as_mut_ptr() as *mut _);
+            check_error!();
+            // GL has written every element, and any bit pattern is a `T`.
+            data.set_len(len);
+        }
+        Ok(data)
+    }
+}
+
+impl<T: Pod> Clone for Buffer<T> {
+    fn clone(&self) -> Self
+    {
+        Buffer { handle: self.handle.clone(), target: self.target, phantom: PhantomData }
+    }
+}
+
+impl<T: Pod> fmt::Debug for Buffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        f.debug_struct("Buffer")
+            .field("id", &self.handle.id)
+            .field("target", &self.target)
+            .field("len", &self.len())
+            .field("usage", &self.usage())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::Mock;
+
+    #[test]
+    fn remembers_length_and_usage()
+    {
+        let _mock = Mock::install();
+        let buffer = Buffer::new_with_data(BufferTarget::Array, &[1.0f32, 2.0, 3.0],
+                                           BufferUsage::DynamicDraw).unwrap();
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.usage(), BufferUsage::DynamicDraw);
+        buffer.resize(8).unwrap();
+        assert_eq!(buffer.len(), 8);
+        assert_eq!(buffer.usage(), BufferUsage::DynamicDraw);
+    }
+
+    #[test]
+    fn update_and_read_back()
+    {
+        let mock = Mock::install();
+        let buffer = Buffer::new_with_data(BufferTarget::Array, &[[1u16, 2], [3, 4], [5, 6]],
+                                           BufferUsage::StaticDraw).unwrap();
+        mock.clear_calls();
+        buffer.update(1, &[[7, 8]]).unwrap();
+        assert_eq!(mock.calls(), vec![
+            gl_call!("glBindBuffer", gl::COPY_WRITE_BUFFER, buffer.id()),
+            gl_call!("glBufferSubData", gl::COPY_WRITE_BUFFER, 4, 4, vec![7u8, 0, 8, 0]),
+        ]);
+        assert_eq!(buffer.read_back().unwrap(), vec![[1, 2], [7, 8], [5, 6]]);
+    }
+
+    #[test]
+    fn update_past_the_end_is_rejected()
+    {
+        let mock = Mock::install();
+        let buffer = Buffer::new_with_data(BufferTarget::Array, &[0u32; 4],
+                                           BufferUsage::StaticDraw).unwrap();
+        mock.clear_calls();
+        match buffer.update(3, &[1, 2]) {
+            Err(Error::Validation(_)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        match buffer.update(usize::MAX, &[1]) {
+            Err(Error::Validation(_)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert_eq!(mock.calls(), vec![]);
+    }
+
+    #[test]
+    fn oversized_buffers_are_rejected()
+    {
+        let mock = Mock::install();
+        let buffer = Buffer::<[u32; 4]>::new(BufferTarget::Array).unwrap();
+        mock.clear_calls();
+        for len in [usize::MAX / 4, isize::MAX as usize / 8] {
+            match buffer.resize(len) {
+                Err(Error::Validation(_)) => (),
+                r => panic!("unexpected result: {:?}", r),
+            }
+        }
+        assert_eq!(mock.calls(), vec![]);
+        assert_eq!(buffer.len(), 0);
+    }
+}
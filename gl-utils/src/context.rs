@@ -0,0 +1,353 @@
+use gl::types::*;
+use error::Error;
+use buffer::{Buffer, BufferHandle, BufferTarget, Pod};
+use ebo::{EBORef, IndexType};
+use shader::Program;
+use texture::{self, TextureRef};
+use uniform;
+use util;
+use vao::VAORef;
+use vbo::VBORef;
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// A binding that a `Context` remembers.
+#[derive(Debug,Copy,Clone,PartialEq,Eq,PartialOrd,Ord)]
+pub(crate) enum Slot {
+    Program,
+    VertexArray,
+    Buffer(BufferTarget),
+    ActiveTexture,
+    // Outside a `Context` nothing tracks the active unit, so a texture bind
+    // may have changed any unit.
+    Textures,
+}
+
+// GL contexts are current on one thread, so this is per thread too.
+thread_local! {
+    // How many times the crate has changed each binding, whether through a
+    // `Context` or not.
+    static CHANGES: RefCell<BTreeMap<Slot, u64>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+/// Records that the crate is about to change the binding of `slot`, so
+/// that every `Context` forgets what it remembers of it.
+pub(crate) fn changing(slot: Slot)
+{
+    CHANGES.with(|c| *c.borrow_mut().entry(slot).or_insert(0) += 1);
+}
+
+fn changes(slot: Slot) -> u64
+{
+    CHANGES.with(|c| c.borrow().get(&slot).cloned().unwrap_or(0))
+}
+
+/// Remembers what is bound so that rebinding it makes no GL call.
+///
+/// The cache keeps a reference to everything bound through it, so a bound
+/// object cannot be deleted and have its name reused behind the cache's
+/// back. Binds the rest of the crate makes, e.g. in `Mesh::draw` or
+/// `Buffer::update`, are noticed and make the cache forget the bindings
+/// they change; call `invalidate` after calling GL directly.
+#[derive(Debug)]
+pub struct Context<'a> {
+    program:        Option<Program>,
+    vao:            Option<VAORef>,
+    buffers:        BTreeMap<BufferTarget, Rc<BufferHandle>>,
+    active_texture: Option<GLuint>,
+    // (unit, target) -> texture
+    textures:       BTreeMap<(GLuint, GLenum), TextureRef<'a>>,
+    // The number of changes to each slot after this cache last changed it.
+    seen:           BTreeMap<Slot, u64>,
+}
+
+impl<'a> Default for Context<'a> {
+    fn default() -> Self
+    {
+        Context::new()
+    }
+}
+
+impl<'a> Context<'a> {
+    /// Makes a cache that knows nothing, so the first bind of each kind
+    /// always reaches GL.
+    pub fn new() -> Self
+    {
+        Context {
+            program:        None,
+            vao:            None,
+            buffers:        BTreeMap::new(),
+            active_texture: None,
+            textures:       BTreeMap::new(),
+            seen:           BTreeMap::new(),
+        }
+    }
+
+    // Whether nothing but this cache has changed `slot` since it last did.
+    fn is_current(&self, slot: Slot) -> bool
+    {
+        self.seen.get(&slot) == Some(&changes(slot))
+    }
+
+    fn saw(&mut self, slot: Slot)
+    {
+        self.seen.insert(slot, changes(slot));
+    }
+
+    // Forgets the bindings something else has changed.
+    fn forget_stale(&mut self)
+    {
+        if !self.is_current(Slot::Program) {
+            self.program = None;
+        }
+        if !self.is_current(Slot::VertexArray) {
+            self.vao = None;
+        }
+        let seen = &self.seen;
+        self.buffers.retain(|&target, _| {
+            let slot = Slot::Buffer(target);
+            seen.get(&slot) == Some(&changes(slot))
+        });
+        if !self.is_current(Slot::ActiveTexture) {
+            self.active_texture = None;
+        }
+        if !self.is_current(Slot::Textures) {
+            self.textures.clear();
+        }
+    }
+
+    /// Forgets every binding, and releases the references held for them.
+    pub fn invalidate(&mut self)
+    {
+        *self = Context::new();
+    }
+
+    pub fn use_program(&mut self, program: &Program) -> Result<(), Error>
+    {
+        self.forget_stale();
+        if self.program.as_ref().map(Program::id) != Some(program.id()) {
+            self.program = None;
+            program.use_program()?;
+            self.saw(Slot::Program);
+            self.program = Some(program.clone());
+        }
+        Ok(())
+    }
+
+    pub fn bind_vao(&mut self, vao: &VAORef) -> Result<(), Error>
+    {
+        self.forget_stale();
+        if self.vao.as_ref().map(VAORef::id) != Some(vao.id()) {
+            self.vao = None;
+            vao.bind()?;
+            self.saw(Slot::VertexArray);
+            self.vao = Some(vao.clone());
+            // The element buffer binding belongs to the vertex array.
+            self.buffers.remove(&BufferTarget::ElementArray);
+        }
+        Ok(())
+    }
+
+    pub fn unbind_vao(&mut self) -> Result<(), Error>
+    {
+        self.forget_stale();
+        if self.vao.is_some() {
+            self.vao = None;
+            self.buffers.remove(&BufferTarget::ElementArray);
+            VAORef::unbind()?;
+            self.saw(Slot::VertexArray);
+        }
+        Ok(())
+    }
+
+    pub fn bind_vbo<T: Pod>(&mut self, vbo: &VBORef<T>) -> Result<(), Error>
+    {
+        self.bind_buffer(vbo)
+    }
+
+    pub fn bind_ebo<I: IndexType>(&mut self, ebo: &EBORef<I>) -> Result<(), Error>
+    {
+        self.bind_buffer(ebo)
+    }
+
+    /// Binds `buffer` to its target.
+    pub fn bind_buffer<T: Pod>(&mut self, buffer: &Buffer<T>) -> Result<(), Error>
+    {
+        self.forget_stale();
+        let target = buffer.target();
+        if self.buffers.get(&target).map(|b| b.id) != Some(buffer.id()) {
+            self.buffers.remove(&target);
+            buffer.bind()?;
+            self.saw(Slot::Buffer(target));
+            self.buffers.insert(target, buffer.handle.clone());
+        }
+        Ok(())
+    }
+
+    /// Selects texture unit `unit`, i.e. `gl::TEXTURE0 + unit`.
+    pub fn active_texture(&mut self, unit: GLuint) -> Result<(), Error>
+    {
+        self.forget_stale();
+        if self.active_texture != Some(unit) {
+            self.active_texture = None;
+            texture::active_texture(unit)?;
+            self.saw(Slot::ActiveTexture);
+            self.active_texture = Some(unit);
+        }
+        Ok(())
+    }
+
+    /// Binds `texture` to its target on texture unit `unit`.
+    pub fn bind_texture(&mut self, unit: GLuint, texture: &TextureRef<'a>) -> Result<(), Error>
+    {
+        self.forget_stale();
+        let key = (unit, texture.target());
+        if self.textures.get(&key).map(TextureRef::id) != Some(texture.id()) {
+            self.active_texture(unit)?;
+            self.textures.remove(&key);
+            texture.bind()?;
+            self.saw(Slot::Textures);
+            self.textures.insert(key, texture.clone());
+        }
+        Ok(())
+    }
+
+    /// `util::setup_uniforms`, binding textures through this cache.
+    pub fn setup_uniforms(&mut self, uniforms: &[uniform::Uniform<'a>],
+                          program: &Program) -> Result<(), Error>
+    {
+        util::setup_uniforms_with(uniforms, program, |unit, t| self.bind_texture(unit, t))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gl;
+    use buffer::BufferUsage;
+    use mock::Mock;
+    use shader::ShaderSrc;
+
+    fn names(mock: &Mock) -> Vec<&'static str>
+    {
+        mock.calls().iter().map(|c| c.name).collect()
+    }
+
+    #[test]
+    fn rebinding_makes_no_calls()
+    {
+        let mock = Mock::install();
+        let program = Program::new(ShaderSrc { vertex: "", fragment: "" }).unwrap();
+        let vao = VAORef::new().unwrap();
+        let vbo = VBORef::<f32>::new().unwrap();
+        mock.clear_calls();
+        let mut ctx = Context::new();
+        for _ in 0..3 {
+            ctx.use_program(&program).unwrap();
+            ctx.bind_vao(&vao).unwrap();
+            ctx.bind_vbo(&vbo).unwrap();
+        }
+        assert_eq!(mock.calls(), vec![
+            gl_call!("glUseProgram", program.id()),
+            gl_call!("glBindVertexArray", vao.id()),
+            gl_call!("glBindBuffer", gl::ARRAY_BUFFER, vbo.id()),
+        ]);
+    }
+
+    #[test]
+    fn changing_vao_forgets_element_buffer()
+    {
+        let mock = Mock::install();
+        let (vao1, vao2) = (VAORef::new().unwrap(), VAORef::new().unwrap());
+        let ebo = EBORef::<u32>::new().unwrap();
+        let mut ctx = Context::new();
+        ctx.bind_vao(&vao1).unwrap();
+        ctx.bind_ebo(&ebo).unwrap();
+        ctx.bind_vao(&vao2).unwrap();
+        mock.clear_calls();
+        ctx.bind_ebo(&ebo).unwrap();
+        assert_eq!(names(&mock), vec!["glBindBuffer"]);
+    }
+
+    #[test]
+    fn textures_are_tracked_per_unit()
+    {
+        let mock = Mock::install();
+        let (a, b) = (TextureRef::new(gl::TEXTURE_2D).unwrap(),
+                      TextureRef::new(gl::TEXTURE_2D).unwrap());
+        mock.clear_calls();
+        let mut ctx = Context::new();
+        ctx.bind_texture(0, &a).unwrap();
+        ctx.bind_texture(1, &b).unwrap();
+        ctx.bind_texture(0, &a).unwrap();
+        ctx.bind_texture(1, &b).unwrap();
+        assert_eq!(mock.calls(), vec![
+            gl_call!("glActiveTexture", gl::TEXTURE0),
+            gl_call!("glBindTexture", gl::TEXTURE_2D, a.id()),
+            gl_call!("glActiveTexture", gl::TEXTURE1),
+            gl_call!("glBindTexture", gl::TEXTURE_2D, b.id()),
+        ]);
+    }
+
+    #[test]
+    fn setup_uniforms_skips_bound_textures()
+    {
+        let mock = Mock::install();
+        mock.add_active_uniform("albedo", gl::SAMPLER_2D, 1);
+        let program = Program::new(ShaderSrc { vertex: "", fragment: "" }).unwrap();
+        let albedo = TextureRef::new(gl::TEXTURE_2D).unwrap();
+        let mut ctx = Context::new();
+        ctx.setup_uniforms(&uniforms!{ albedo: albedo, }, &program).unwrap();
+        mock.clear_calls();
+        ctx.setup_uniforms(&uniforms!{ albedo: albedo, }, &program).unwrap();
+        assert_eq!(names(&mock), vec!["glUniform1i"]);
+    }
+
+    #[test]
+    fn binds_elsewhere_in_the_crate_are_noticed()
+    {
+        let mock = Mock::install();
+        let (p1, p2) = (Program::new(ShaderSrc { vertex: "", fragment: "" }).unwrap(),
+                        Program::new(ShaderSrc { vertex: "", fragment: "" }).unwrap());
+        let (vao1, vao2) = (VAORef::new().unwrap(), VAORef::new().unwrap());
+        let vbo = VBORef::new_with_data(&[0.0f32; 4], BufferUsage::StaticDraw).unwrap();
+        let (t1, t2) = (TextureRef::new(gl::TEXTURE_2D).unwrap(),
+                        TextureRef::new(gl::TEXTURE_2D).unwrap());
+        let mut ctx = Context::new();
+        ctx.use_program(&p1).unwrap();
+        ctx.bind_vao(&vao1).unwrap();
+        ctx.bind_vbo(&vbo).unwrap();
+        ctx.bind_texture(0, &t1).unwrap();
+
+        p2.use_program().unwrap();
+        vao2.bind().unwrap();
+        t2.bind().unwrap();
+        // Uploads go through COPY_WRITE_BUFFER, which the cache has not bound.
+        vbo.update(0, &[1.0]).unwrap();
+        mock.clear_calls();
+        ctx.use_program(&p1).unwrap();
+        ctx.bind_vao(&vao1).unwrap();
+        ctx.bind_vbo(&vbo).unwrap();
+        ctx.bind_texture(0, &t1).unwrap();
+        assert_eq!(mock.calls(), vec![
+            gl_call!("glUseProgram", p1.id()),
+            gl_call!("glBindVertexArray", vao1.id()),
+            gl_call!("glBindTexture", gl::TEXTURE_2D, t1.id()),
+        ]);
+    }
+
+    #[test]
+    fn invalidate_rebinds()
+    {
+        let mock = Mock::install();
+        let vao = VAORef::new().unwrap();
+        let mut ctx = Context::new();
+        ctx.bind_vao(&vao).unwrap();
+        ctx.invalidate();
+        mock.clear_calls();
+        ctx.bind_vao(&vao).unwrap();
+        assert_eq!(names(&mock), vec!["glBindVertexArray"]);
+    }
+}
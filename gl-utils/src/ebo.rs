@@ -1,66 +1,120 @@
 use gl;
 use gl::types::*;
-use util::*;
-use std::rc::Rc;
+use buffer::{Buffer, BufferTarget, BufferUsage, Pod};
+use context::{Slot, changing};
+use error::Error;
+use std::fmt;
+use std::ops::Deref;
 
-#[derive(Debug,Clone)]
-pub struct EBORef {
-    handle: Rc<EBOHandle>,
+/// An element type `glDrawElements` accepts.
+pub trait IndexType: Pod {
+    fn get_gl_type() -> GLenum;
 }
 
-#[derive(Debug)]
-struct EBOHandle {
-    id: GLuint,
+impl IndexType for GLubyte {
+    fn get_gl_type() -> GLenum
+    {
+        gl::UNSIGNED_BYTE
+    }
 }
 
-impl EBOHandle {
-    fn new() -> Result<Self, GLenum>
+impl IndexType for GLushort {
+    fn get_gl_type() -> GLenum
     {
-        unsafe {
-            let mut ebo = EBOHandle { id: 0 };
-            gl::GenBuffers(1, &mut ebo.id);
-            check_error!();
-            Ok(ebo)
-        }
+        gl::UNSIGNED_SHORT
     }
 }
 
-impl EBORef {
-    pub fn new() -> Result<Self, GLenum>
+impl IndexType for GLuint {
+    fn get_gl_type() -> GLenum
     {
-        let h = EBOHandle::new()?;
-        Ok(EBORef { handle: Rc::new(h) })
+        gl::UNSIGNED_INT
     }
+}
+
+/// A `Buffer` of indices, bound to `ELEMENT_ARRAY_BUFFER`.
+pub struct EBORef<I: IndexType = u32>(Buffer<I>);
 
-    pub fn bind(&self, target: GLenum) -> Result<(), GLenum>
+impl<I: IndexType> EBORef<I> {
+    pub fn new() -> Result<Self, Error>
     {
-        unsafe {
-            gl::BindBuffer(target, self.handle.id);
-            check_error!();
-            Ok(())
-        }
+        Ok(EBORef(Buffer::new(BufferTarget::ElementArray)?))
+    }
+
+    pub fn new_with_data(indices: &[I], usage: BufferUsage) -> Result<Self, Error>
+    {
+        Ok(EBORef(Buffer::new_with_data(BufferTarget::ElementArray, indices, usage)?))
     }
 
-    pub fn unbind(target: GLenum) -> Result<(), GLenum>
+    /// Deletes the index buffer, as `Buffer::delete` does: not until the
+    /// last clone is released.
+    pub fn delete(self) -> Result<(), Error>
+    {
+        self.0.delete()
+    }
+}
+
+// Not generic, so that `EBORef::unbind()` needs no type annotation.
+impl EBORef {
+    pub fn unbind() -> Result<(), Error>
     {
         unsafe {
-            gl::BindBuffer(target, 0);
+            changing(Slot::Buffer(BufferTarget::ElementArray));
+            gl::BindBuffer(BufferTarget::ElementArray.to_gl(), 0);
             check_error!();
             Ok(())
         }
     }
 }
-impl Drop for EBOHandle {
-    fn drop(&mut self)
+
+impl<I: IndexType> Deref for EBORef<I> {
+    type Target = Buffer<I>;
+
+    fn deref(&self) -> &Buffer<I>
     {
-        unsafe {
-            gl::DeleteBuffers(1, &mut self.id);
-            // We should use check_error here, but the type of drop
-            // won't allow it. We have to panic instead.
-            let err = gl::GetError();
-            if err != gl::NO_ERROR {
-                panic!("EBO DeleteBuffers returned: {}", gl_error_str(err));
-            }
-        }
+        &self.0
+    }
+}
+
+impl<I: IndexType> Clone for EBORef<I> {
+    fn clone(&self) -> Self
+    {
+        EBORef(self.0.clone())
+    }
+}
+
+impl<I: IndexType> fmt::Debug for EBORef<I> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        f.debug_tuple("EBORef").field(&self.0).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::Mock;
+
+    #[test]
+    fn drop_deletes_buffer()
+    {
+        let mock = Mock::install();
+        let id = EBORef::<u32>::new().unwrap().id();
+        assert_eq!(mock.objects(),
+                   vec![("glGenBuffers", id), ("glDeleteBuffers", id)]);
+    }
+
+    #[test]
+    fn binds_to_element_array_buffer()
+    {
+        let mock = Mock::install();
+        let ebo = EBORef::<u32>::new().unwrap();
+        mock.clear_calls();
+        ebo.bind().unwrap();
+        EBORef::unbind().unwrap();
+        assert_eq!(mock.calls(), vec![
+            gl_call!("glBindBuffer", gl::ELEMENT_ARRAY_BUFFER, ebo.id()),
+            gl_call!("glBindBuffer", gl::ELEMENT_ARRAY_BUFFER, 0u32),
+        ]);
     }
 }
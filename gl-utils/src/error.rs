@@ -0,0 +1,264 @@
+use gl;
+use gl::types::*;
+use util::gl_error_str;
+
+use std::cell::{Cell,RefCell};
+use std::error;
+use std::ffi::NulError;
+use std::fmt;
+
+#[derive(Debug,Clone,PartialEq)]
+pub enum Error {
+    /// `glGetError` returned `code` after the call checked at `file:line`.
+    GL { code: GLenum, file: &'static str, line: u32 },
+    /// A shader stage (e.g. `gl::VERTEX_SHADER`) failed to compile.
+    ShaderCompile { stage: GLenum, log: String },
+    /// The program failed to link.
+    Link { log: String },
+    /// A name passed to GL contained an interior NUL byte.
+    Nul(NulError),
+    /// The arguments were rejected before any GL call was made.
+    Validation(String),
+    /// Creating or making current a GL context failed.
+    Context(String),
+}
+
+pub fn shader_stage_str<'a>(stage: GLenum) -> &'a str
+{
+    match stage {
+        gl::VERTEX_SHADER          => "vertex",
+        gl::FRAGMENT_SHADER        => "fragment",
+        gl::GEOMETRY_SHADER        => "geometry",
+        gl::TESS_CONTROL_SHADER    => "tessellation control",
+        gl::TESS_EVALUATION_SHADER => "tessellation evaluation",
+        gl::COMPUTE_SHADER         => "compute",
+        _                          => "unknown"
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match *self {
+            Error::GL { code, file, line } =>
+                write!(f, "{} at {}:{}", gl_error_str(code), file, line),
+            Error::ShaderCompile { stage, ref log } =>
+                write!(f, "{} shader failed to compile: {}",
+                       shader_stage_str(stage), log),
+            Error::Link { ref log } =>
+                write!(f, "program failed to link: {}", log),
+            Error::Nul(ref e) =>
+                write!(f, "name contains a NUL byte: {}", e),
+            Error::Validation(ref msg) =>
+                write!(f, "{}", msg),
+            Error::Context(ref msg) =>
+                write!(f, "GL context: {}", msg),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)>
+    {
+        match *self {
+            Error::Nul(ref e) => Some(e),
+            _                 => None,
+        }
+    }
+}
+
+impl From<NulError> for Error {
+    fn from(e: NulError) -> Self
+    {
+        Error::Nul(e)
+    }
+}
+
+/// What a handle should do when deleting its GL object fails inside
+/// `Drop`. Use the handle's `delete` method to observe the error directly.
+///
+/// Errors that earlier, unchecked calls left pending are not the handle's,
+/// so they never go through the policy: `Drop` logs them unless the policy
+/// is `Ignore`, and `delete` discards them.
+///
+/// Warnings, such as unknown uniforms under `UnknownUniformPolicy::Warn`,
+/// go through the policy too, except that they never panic.
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum DropErrorPolicy {
+    Ignore,
+    /// Print the error to stderr.
+    Log,
+    /// Queue the error; retrieve it with `take_drop_errors`.
+    Collect,
+    /// Panic, unless the thread is already panicking, in which case the
+    /// error is logged instead of aborting the process.
+    Panic,
+}
+
+// GL contexts are current on a single thread, so the policy and the
+// collected errors are per-thread as well.
+thread_local! {
+    static DROP_ERROR_POLICY: Cell<DropErrorPolicy> = const { Cell::new(DropErrorPolicy::Log) };
+    static DROP_ERRORS: RefCell<Vec<Error>> = const { RefCell::new(Vec::new()) };
+}
+
+pub fn set_drop_error_policy(policy: DropErrorPolicy)
+{
+    DROP_ERROR_POLICY.with(|p| p.set(policy));
+}
+
+pub fn drop_error_policy() -> DropErrorPolicy
+{
+    DROP_ERROR_POLICY.with(|p| p.get())
+}
+
+/// Returns and clears the errors queued under `DropErrorPolicy::Collect`.
+pub fn take_drop_errors() -> Vec<Error>
+{
+    DROP_ERRORS.with(|errs| ::std::mem::take(&mut *errs.borrow_mut()))
+}
+
+pub fn report_drop_error(err: Error)
+{
+    use std::thread;
+    match drop_error_policy() {
+        DropErrorPolicy::Ignore  => (),
+        DropErrorPolicy::Collect => DROP_ERRORS.with(|errs| errs.borrow_mut().push(err)),
+        DropErrorPolicy::Panic if !thread::panicking() =>
+            panic!("error while deleting GL object: {}", err),
+        DropErrorPolicy::Log | DropErrorPolicy::Panic =>
+            eprintln!("error while deleting GL object: {}", err),
+    }
+}
+
+/// Reports a problem that does not fail the call through the drop error
+/// policy: ignored, queued, or logged in place of a panic.
+pub(crate) fn report_warning(err: Error)
+{
+    match drop_error_policy() {
+        DropErrorPolicy::Ignore  => (),
+        DropErrorPolicy::Collect => DROP_ERRORS.with(|errs| errs.borrow_mut().push(err)),
+        DropErrorPolicy::Log | DropErrorPolicy::Panic => eprintln!("warning: {}", err),
+    }
+}
+
+/// Discards the errors that earlier, unchecked GL calls left pending, so
+/// that the next check only sees the call it follows.
+pub(crate) fn clear_pending_errors()
+{
+    drain_pending_errors(|_| ());
+}
+
+/// Like `clear_pending_errors`, but logs the errors unless the drop error
+/// policy is `Ignore`, for handles about to delete their object in `Drop`.
+/// They are logged as raised at the caller.
+#[track_caller]
+pub(crate) fn log_pending_errors()
+{
+    let at = ::std::panic::Location::caller();
+    let log = drop_error_policy() != DropErrorPolicy::Ignore;
+    drain_pending_errors(|code| if log {
+        eprintln!("error left pending before deleting GL object: {}",
+                  Error::GL { code, file: at.file(), line: at.line() });
+    });
+}
+
+fn drain_pending_errors<F: FnMut(GLenum)>(mut f: F)
+{
+    // Without a current context glGetError may never return NO_ERROR, so
+    // bound the number of iterations.
+    for _ in 0..32 {
+        let code = unsafe { gl::GetError() };
+        if code == gl::NO_ERROR {
+            break;
+        }
+        f(code);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buffer::{Buffer, BufferTarget};
+    use mock::Mock;
+    use std::panic;
+
+    // Drops a buffer whose glDeleteBuffers fails, under `policy`.
+    fn drop_failing_buffer(mock: &Mock, policy: DropErrorPolicy)
+    {
+        set_drop_error_policy(policy);
+        let buffer = Buffer::<u8>::new(BufferTarget::Array).unwrap();
+        mock.fail_next("glDeleteBuffers", gl::INVALID_VALUE);
+        drop(buffer);
+    }
+
+    fn codes(errors: Vec<Error>) -> Vec<GLenum>
+    {
+        errors.into_iter()
+            .map(|e| match e {
+                Error::GL { code, .. } => code,
+                e => panic!("unexpected error: {:?}", e),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ignore_and_log_neither_collect_nor_panic()
+    {
+        let mock = Mock::install();
+        drop_failing_buffer(&mock, DropErrorPolicy::Ignore);
+        drop_failing_buffer(&mock, DropErrorPolicy::Log);
+        assert_eq!(take_drop_errors(), vec![]);
+        set_drop_error_policy(DropErrorPolicy::Log);
+    }
+
+    #[test]
+    fn collect_queues_only_delete_errors()
+    {
+        let mock = Mock::install();
+        set_drop_error_policy(DropErrorPolicy::Collect);
+        let buffer = Buffer::<u8>::new(BufferTarget::Array).unwrap();
+        mock.set_error(gl::INVALID_ENUM);
+        mock.fail_next("glDeleteBuffers", gl::INVALID_VALUE);
+        drop(buffer);
+        assert_eq!(codes(take_drop_errors()), vec![gl::INVALID_VALUE]);
+        assert_eq!(take_drop_errors(), vec![]);
+        set_drop_error_policy(DropErrorPolicy::Log);
+    }
+
+    #[test]
+    fn pending_errors_neither_panic_nor_fail_deletes()
+    {
+        let mock = Mock::install();
+        set_drop_error_policy(DropErrorPolicy::Panic);
+        let buffer = Buffer::<u8>::new(BufferTarget::Array).unwrap();
+        mock.set_error(gl::INVALID_ENUM);
+        drop(buffer);
+        let buffer = Buffer::<u8>::new(BufferTarget::Array).unwrap();
+        mock.set_error(gl::INVALID_ENUM);
+        assert_eq!(buffer.delete(), Ok(()));
+        set_drop_error_policy(DropErrorPolicy::Log);
+    }
+
+    #[test]
+    fn panic_panics_unless_already_unwinding()
+    {
+        let mock = Mock::install();
+        let r = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            drop_failing_buffer(&mock, DropErrorPolicy::Panic);
+        }));
+        let msg = r.unwrap_err().downcast::<String>().unwrap();
+        assert!(msg.contains("GL_INVALID_VALUE"), "{}", msg);
+
+        // Dropped while unwinding from another panic, the error is logged
+        // rather than aborting the process.
+        let buffer = Buffer::<u8>::new(BufferTarget::Array).unwrap();
+        mock.fail_next("glDeleteBuffers", gl::INVALID_VALUE);
+        let r = panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            let _buffer = buffer;
+            panic!("first");
+        }));
+        assert_eq!(*r.unwrap_err().downcast::<&str>().unwrap(), "first");
+        set_drop_error_policy(DropErrorPolicy::Log);
+    }
+}
@@ -0,0 +1,206 @@
+//! An offscreen GL context for integration tests on machines without a
+//! GPU. It uses Mesa's surfaceless EGL platform (`EGL_MESA_platform_surfaceless`),
+//! which falls back to llvmpipe, and renders into a framebuffer object
+//! whose pixels can be read back.
+//!
+//! Requires the `headless` feature and `libEGL.so.1` at run time.
+
+use gl;
+use gl::types::*;
+use image;
+use khronos_egl as egl;
+use error::Error;
+
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::{Mutex, MutexGuard};
+
+const PLATFORM_SURFACELESS_MESA: egl::Enum = 0x31DD;
+
+/// Width and height of the framebuffer made by `with_headless_context`.
+pub const DEFAULT_SIZE: GLsizei = 64;
+
+// The `gl` function pointers are process wide, so contexts are created and
+// used one at a time.
+static CURRENT: Mutex<()> = Mutex::new(());
+
+// An initialized display and, once created, its context, torn down when
+// dropped, including when creating the `HeadlessContext` fails part way.
+struct Egl {
+    egl:     egl::DynamicInstance<egl::EGL1_5>,
+    display: egl::Display,
+    context: Option<egl::Context>,
+}
+
+impl Drop for Egl {
+    fn drop(&mut self)
+    {
+        // Nothing useful can be done if tearing down EGL fails.
+        let _ = self.egl.make_current(self.display, None, None, None);
+        if let Some(context) = self.context {
+            let _ = self.egl.destroy_context(self.display, context);
+        }
+        let _ = self.egl.terminate(self.display);
+    }
+}
+
+pub struct HeadlessContext {
+    // Only kept to be torn down.
+    _egl:         Egl,
+    framebuffer:  GLuint,
+    renderbuffer: GLuint,
+    width:        GLsizei,
+    height:       GLsizei,
+    // Dropped after `_egl`, so the next context waits for the teardown.
+    _current:     MutexGuard<'static, ()>,
+    // The context is current on the thread that created it.
+    phantom:      PhantomData<*const ()>,
+}
+
+fn egl_error<E: ::std::fmt::Display>(what: &str, e: E) -> Error
+{
+    Error::Context(format!("{}: {}", what, e))
+}
+
+impl HeadlessContext {
+    /// Creates a GL 3.3 core context, makes it current on this thread,
+    /// loads the `gl` function pointers from it and binds a `width` by
+    /// `height` RGBA8 framebuffer. As the function pointers are process
+    /// wide, this blocks while another `HeadlessContext` is alive, so a
+    /// thread must not create a second one before dropping the first.
+    pub fn new(width: GLsizei, height: GLsizei) -> Result<HeadlessContext, Error>
+    {
+        HeadlessContext::with_version(width, height, 3, 3)
+    }
+
+    /// Like `new`, for a `major.minor` core context.
+    pub fn with_version(width: GLsizei, height: GLsizei, major: egl::Int, minor: egl::Int)
+        -> Result<HeadlessContext, Error>
+    {
+        let current = CURRENT.lock().unwrap_or_else(|e| e.into_inner());
+        let egl = unsafe { egl::DynamicInstance::<egl::EGL1_5>::load_required() }
+            .map_err(|e| egl_error("loading libEGL", e))?;
+        let extensions = egl.query_string(None, egl::EXTENSIONS)
+            .map_err(|e| egl_error("querying EGL client extensions", e))?
+            .to_string_lossy()
+            .into_owned();
+        if !extensions.split(' ').any(|e| e == "EGL_MESA_platform_surfaceless") {
+            return Err(Error::Context(
+                "EGL_MESA_platform_surfaceless is not supported".to_string()))
+        }
+        let display = unsafe {
+            egl.get_platform_display(PLATFORM_SURFACELESS_MESA,
+                                     egl::DEFAULT_DISPLAY, &[egl::ATTRIB_NONE])
+        }.map_err(|e| egl_error("eglGetPlatformDisplay", e))?;
+        egl.initialize(display)
+            .map_err(|e| egl_error("eglInitialize", e))?;
+        let mut egl = Egl { egl, display, context: None };
+        egl.egl.bind_api(egl::OPENGL_API)
+            .map_err(|e| egl_error("eglBindAPI", e))?;
+        // Surfaceless configs only advertise pbuffer support, and the
+        // default surface type would ask for a window.
+        let config = egl.egl.choose_first_config(display, &[
+            egl::SURFACE_TYPE,    egl::PBUFFER_BIT,
+            egl::RENDERABLE_TYPE, egl::OPENGL_BIT,
+            egl::NONE,
+        ]).map_err(|e| egl_error("eglChooseConfig", e))?
+            .ok_or_else(|| Error::Context("no EGL config supports OpenGL".to_string()))?;
+        let context = egl.egl.create_context(display, config, None, &[
+            egl::CONTEXT_MAJOR_VERSION, major,
+            egl::CONTEXT_MINOR_VERSION, minor,
+            egl::CONTEXT_OPENGL_PROFILE_MASK, egl::CONTEXT_OPENGL_CORE_PROFILE_BIT,
+            egl::NONE,
+        ]).map_err(|e| egl_error("eglCreateContext", e))?;
+        egl.context = Some(context);
+        egl.egl.make_current(display, None, None, Some(context))
+            .map_err(|e| egl_error("eglMakeCurrent", e))?;
+        gl::load_with(|name| {
+            egl.egl.get_proc_address(name)
+                .map_or(ptr::null(), |f| f as *const _)
+        });
+
+        // From here on, dropping `ctx` also deletes whatever GL objects
+        // were made before a failure.
+        let mut ctx = HeadlessContext {
+            _egl: egl,
+            framebuffer: 0, renderbuffer: 0,
+            width, height,
+            _current: current,
+            phantom: PhantomData,
+        };
+        unsafe {
+            gl::GenRenderbuffers(1, &mut ctx.renderbuffer);
+            check_error!();
+            gl::BindRenderbuffer(gl::RENDERBUFFER, ctx.renderbuffer);
+            check_error!();
+            gl::RenderbufferStorage(gl::RENDERBUFFER, gl::RGBA8, width, height);
+            check_error!();
+            gl::GenFramebuffers(1, &mut ctx.framebuffer);
+            check_error!();
+            gl::BindFramebuffer(gl::FRAMEBUFFER, ctx.framebuffer);
+            check_error!();
+            gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0,
+                                        gl::RENDERBUFFER, ctx.renderbuffer);
+            check_error!();
+            gl::Viewport(0, 0, width, height);
+            check_error!();
+        }
+        Ok(ctx)
+    }
+
+    pub fn width(&self) -> GLsizei
+    {
+        self.width
+    }
+
+    pub fn height(&self) -> GLsizei
+    {
+        self.height
+    }
+
+    /// Reads the framebuffer back, with the top row first.
+    pub fn read_pixels(&self) -> Result<image::RgbaImage, Error>
+    {
+        let mut pixels = vec![0u8; (self.width * self.height * 4) as usize];
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.framebuffer);
+            check_error!();
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            check_error!();
+            gl::ReadPixels(0, 0, self.width, self.height, gl::RGBA, gl::UNSIGNED_BYTE,
+                           pixels.as_mut_ptr() as *mut _);
+            check_error!();
+        }
+        let im = image::RgbaImage::from_raw(self.width as u32, self.height as u32, pixels)
+            .expect("pixel buffer matches the framebuffer size");
+        Ok(image::imageops::flip_vertical(&im))
+    }
+}
+
+impl Drop for HeadlessContext {
+    fn drop(&mut self)
+    {
+        // Deleting 0 is ignored, and `_egl` is torn down after this.
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.framebuffer);
+            gl::DeleteRenderbuffers(1, &self.renderbuffer);
+        }
+    }
+}
+
+/// Runs `f` with a fresh `DEFAULT_SIZE` square headless context current.
+/// Panics if the context cannot be created, which is what a test wants.
+pub fn with_headless_context<T, F>(f: F) -> T
+    where F: FnOnce(&HeadlessContext) -> T
+{
+    with_headless_context_version(3, 3, f)
+}
+
+/// `with_headless_context` for a `major.minor` core context.
+pub fn with_headless_context_version<T, F>(major: egl::Int, minor: egl::Int, f: F) -> T
+    where F: FnOnce(&HeadlessContext) -> T
+{
+    let ctx = HeadlessContext::with_version(DEFAULT_SIZE, DEFAULT_SIZE, major, minor)
+        .unwrap_or_else(|e| panic!("creating a headless GL {}.{} context: {}", major, minor, e));
+    f(&ctx)
+}
@@ -1,14 +1,34 @@
 extern crate gl;
 extern crate image;
+#[cfg(feature = "headless")]
+extern crate khronos_egl;
 
+#[cfg(any(test, feature = "mock"))]
+#[macro_use]
+pub mod mock;
 #[macro_use]
 pub mod util;
+pub mod error;
 pub mod shader;
 pub mod texture;
 pub mod uniform;
+pub mod buffer;
 pub mod vbo;
 pub mod vao;
+pub mod vertex_array;
 pub mod ebo;
+#[macro_use]
+pub mod std140;
+pub mod ubo;
+#[macro_use]
+pub mod std430;
+pub mod ssbo;
+pub mod context;
+pub mod mesh;
+#[cfg(feature = "headless")]
+pub mod headless;
+
+pub use error::Error;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,213 @@
+use gl::types::*;
+use buffer::{BufferUsage, Pod};
+use ebo::{EBORef, IndexType};
+use error::Error;
+use shader::Program;
+use uniform::Uniform;
+use util::*;
+use vao::VAORef;
+use vbo::VBORef;
+use vertex_array::VertexArray;
+
+/// An indexed mesh: a `VertexArray` reading one vertex and one index
+/// buffer, and the primitive mode to draw them with.
+#[derive(Debug,Clone)]
+pub struct Mesh<V: VertexAttribFields + Pod, I: IndexType = u32> {
+    array:    VertexArray,
+    vertices: VBORef<V>,
+    indices:  EBORef<I>,
+    mode:     GLenum,
+}
+
+impl<V: VertexAttribFields + Pod, I: IndexType> Mesh<V, I> {
+    /// Uploads `vertices` and `indices` and records the attribute layout of
+    /// `V`, using the attribute locations `program` assigns. The mesh can
+    /// be drawn with any program that uses the same locations.
+    pub fn new(program: &Program, mode: GLenum, vertices: &[V], indices: &[I],
+               usage: BufferUsage) -> Result<Self, Error>
+    {
+        let vbo = VBORef::new_with_data(vertices, usage)?;
+        let ebo = EBORef::new_with_data(indices, usage)?;
+        let array = VertexArray::builder_for(program)?
+            .vertices(&vbo)?
+            .indices(&ebo)?
+            .build()?;
+        Ok(Mesh { array, vertices: vbo, indices: ebo, mode })
+    }
+
+    pub fn array(&self) -> &VertexArray
+    {
+        &self.array
+    }
+
+    pub fn vao(&self) -> &VAORef
+    {
+        self.array.vao()
+    }
+
+    pub fn vertices(&self) -> &VBORef<V>
+    {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &EBORef<I>
+    {
+        &self.indices
+    }
+
+    pub fn mode(&self) -> GLenum
+    {
+        self.mode
+    }
+
+    pub fn vertex_count(&self) -> usize
+    {
+        self.vertices.len()
+    }
+
+    pub fn index_count(&self) -> usize
+    {
+        self.indices.len()
+    }
+
+    /// Reads the attributes of `J` from `vbo`, one `J` per `divisor`
+    /// instances. The mesh keeps `vbo` alive, so it can be refilled with
+    /// `set_data` between draws.
+    pub fn add_instances<J>(&mut self, program: &Program, vbo: &VBORef<J>, divisor: GLuint)
+        -> Result<(), Error>
+        where J: VertexAttribFields + Pod
+    {
+        self.array.add_instances(program, vbo, divisor)
+    }
+
+    /// Draws the whole mesh with `program`, setting `uniforms` first. The
+    /// mesh's vertex array is left bound.
+    pub fn draw(&self, program: &Program, uniforms: &[Uniform]) -> Result<(), Error>
+    {
+        program.use_program()?;
+        setup_uniforms(uniforms, program)?;
+        self.array.draw_elements(self.mode, self.indices.len() as GLsizei)
+    }
+
+    /// Draws `instances` copies of the mesh, reading per-instance
+    /// attributes from the buffers added with `add_instances`.
+    pub fn draw_instanced(&self, program: &Program, uniforms: &[Uniform],
+                          instances: GLsizei) -> Result<(), Error>
+    {
+        program.use_program()?;
+        setup_uniforms(uniforms, program)?;
+        self.array.draw_elements_instanced(self.mode, self.indices.len() as GLsizei,
+                                           instances)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gl;
+    use mock::{Arg, Mock};
+    use shader::ShaderSrc;
+
+    #[derive(Copy,Clone)]
+    #[repr(C)]
+    struct Vertex {
+        pos: [GLfloat; 2],
+    }
+
+    unsafe impl Pod for Vertex {}
+
+    implement_vertex_attrib!(Vertex, pos);
+
+    fn names(mock: &Mock) -> Vec<&'static str>
+    {
+        mock.calls().iter().map(|c| c.name).collect()
+    }
+
+    #[test]
+    fn new_records_layout_and_counts()
+    {
+        let mock = Mock::install();
+        let program = Program::new(ShaderSrc { vertex: "", fragment: "" }).unwrap();
+        let vertices = [Vertex { pos: [0.0, 0.0] }, Vertex { pos: [1.0, 0.0] },
+                        Vertex { pos: [0.0, 1.0] }, Vertex { pos: [1.0, 1.0] }];
+        mock.clear_calls();
+        let mesh = Mesh::new(&program, gl::TRIANGLES, &vertices, &[0u16, 1, 2, 2, 1, 3],
+                             BufferUsage::StaticDraw).unwrap();
+        assert_eq!((mesh.vertex_count(), mesh.index_count()), (4, 6));
+        let calls = names(&mock);
+        let bound = calls.iter().position(|&n| n == "glBindVertexArray").unwrap();
+        assert_eq!(&calls[bound..], &[
+            "glBindVertexArray",
+            "glGetAttribLocation",
+            "glBindBuffer",
+            "glVertexAttribPointer",
+            "glEnableVertexAttribArray",
+            "glVertexAttribDivisor",
+            "glBindBuffer",
+            "glBindVertexArray",
+        ]);
+        assert_eq!(mesh.array().buffers(), vec![mesh.vertices().id()]);
+        assert_eq!(mesh.array().index_buffer(), Some(mesh.indices().id()));
+    }
+
+    #[test]
+    fn draw_binds_and_draws_every_index()
+    {
+        let mock = Mock::install();
+        mock.add_active_uniform("scale", gl::FLOAT, 1);
+        let program = Program::new(ShaderSrc { vertex: "", fragment: "" }).unwrap();
+        let mesh: Mesh<Vertex> = Mesh::new(&program, gl::LINES, &[Vertex { pos: [0.0; 2] }],
+                                           &[0, 0], BufferUsage::StaticDraw).unwrap();
+        mock.clear_calls();
+        mesh.draw(&program, &uniforms!{ scale: [2.0f32], }).unwrap();
+        assert_eq!(names(&mock), vec![
+            "glUseProgram",
+            "glUniform1fv",
+            "glBindVertexArray",
+            "glDrawElements",
+        ]);
+        assert_eq!(mock.calls().last().unwrap(),
+                   &gl_call!("glDrawElements", gl::LINES, 2, gl::UNSIGNED_INT, Arg::Ptr(0)));
+    }
+
+    #[derive(Copy,Clone)]
+    #[repr(C)]
+    struct Instance {
+        offset: [GLfloat; 2],
+    }
+
+    unsafe impl Pod for Instance {}
+
+    implement_vertex_attrib!(Instance, offset);
+
+    #[test]
+    fn instance_buffers_stay_alive_with_the_mesh()
+    {
+        let mock = Mock::install();
+        mock.set_attribute_location("pos", 0);
+        mock.set_attribute_location("offset", 1);
+        let program = Program::new(ShaderSrc { vertex: "", fragment: "" }).unwrap();
+        let mut mesh: Mesh<Vertex> = Mesh::new(&program, gl::POINTS, &[Vertex { pos: [0.0; 2] }],
+                                               &[0], BufferUsage::StaticDraw).unwrap();
+        let offsets = VBORef::new_with_data(&[Instance { offset: [1.0, 2.0] }; 3],
+                                            BufferUsage::DynamicDraw).unwrap();
+        let id = offsets.id();
+        mock.clear_calls();
+        mesh.add_instances(&program, &offsets, 1).unwrap();
+        drop(offsets);
+        let calls = mock.calls();
+        let bound = calls.iter().position(|c| c.name == "glBindVertexArray").unwrap();
+        assert_eq!(&calls[bound..], &[
+            gl_call!("glBindVertexArray", mesh.vao().id()),
+            gl_call!("glBindBuffer", gl::ARRAY_BUFFER, id),
+            gl_call!("glVertexAttribPointer", 1u32, 2, gl::FLOAT, gl::FALSE, 8, Arg::Ptr(0)),
+            gl_call!("glEnableVertexAttribArray", 1u32),
+            gl_call!("glVertexAttribDivisor", 1u32, 1u32),
+            gl_call!("glBindVertexArray", 0u32),
+        ]);
+        assert_eq!(mesh.array().buffers(), vec![mesh.vertices().id(), id]);
+        assert_eq!(mesh.array().layout()[1].divisor, 1);
+        mesh.draw_instanced(&program, &[], 3).unwrap();
+        assert!(!mock.objects().iter().any(|&(f, _)| f == "glDeleteBuffers"));
+    }
+}
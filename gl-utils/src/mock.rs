@@ -0,0 +1,925 @@
+//! A recording stand-in for a GL driver, for tests that run without a
+//! context. `Mock::install` points the `gl` function pointers at stubs
+//! that log every call with its arguments and answer queries from a small
+//! scriptable model: object names, buffer contents, shader compile and
+//! program link results, active attributes, uniforms, uniform and shader
+//! storage blocks and their locations, and injected errors.
+//!
+//! Enable the `mock` feature to use it from other crates. Every GL entry
+//! point the code under test calls must have a stub below; calling one
+//! that is missing aborts the test binary.
+
+use gl;
+use gl::types::*;
+
+use std::collections::BTreeMap;
+use std::ffi::CStr;
+use std::os::raw::c_void;
+use std::ptr;
+use std::slice;
+use std::sync::{Mutex, MutexGuard};
+
+#[derive(Debug,Clone,PartialEq)]
+pub enum Arg {
+    Int(i64),
+    Float(f64),
+    Ptr(usize),
+    Str(String),
+    /// The bytes behind a data pointer, e.g. the contents of `glBufferData`.
+    Data(Vec<u8>),
+    /// The values behind a `glUniform*fv` pointer.
+    Floats(Vec<GLfloat>),
+    /// The values behind a `glUniform*iv` or `glUniform*uiv` pointer.
+    Ints(Vec<i64>),
+    /// The values behind a `glUniform*dv` pointer.
+    Doubles(Vec<GLdouble>),
+}
+
+macro_rules! arg_from {
+    ($variant:ident, $repr:ty, $($ty:ty),+) => ($(
+        impl From<$ty> for Arg {
+            fn from(v: $ty) -> Arg
+            {
+                Arg::$variant(v as $repr)
+            }
+        }
+    )+)
+}
+
+arg_from!(Int, i64, i8, u8, i16, u16, i32, u32, i64, isize, usize);
+arg_from!(Float, f64, f32, f64);
+
+impl<'a> From<&'a str> for Arg {
+    fn from(v: &'a str) -> Arg
+    {
+        Arg::Str(v.to_string())
+    }
+}
+
+impl From<Vec<u8>> for Arg {
+    fn from(v: Vec<u8>) -> Arg
+    {
+        Arg::Data(v)
+    }
+}
+
+impl<T> From<*const T> for Arg {
+    fn from(v: *const T) -> Arg
+    {
+        Arg::Ptr(v as usize)
+    }
+}
+
+impl<T> From<*mut T> for Arg {
+    fn from(v: *mut T) -> Arg
+    {
+        Arg::Ptr(v as usize)
+    }
+}
+
+/// One recorded GL call. `glGen*` and `glDelete*` record the count
+/// followed by the object names, and `glGetIntegerv` only the name it is
+/// asked for. `glGetError`, which follows nearly every call, is not
+/// recorded.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Call {
+    pub name: &'static str,
+    pub args: Vec<Arg>,
+}
+
+/// Builds a `Call` for comparing against `Mock::calls`, converting each
+/// argument with `Arg::from`.
+#[macro_export]
+macro_rules! gl_call {
+    ($name:expr $(, $arg:expr)*) => (
+        $crate::mock::Call {
+            name: $name,
+            args: vec![$($crate::mock::Arg::from($arg)),*],
+        }
+    )
+}
+
+struct State {
+    calls:       Vec<Call>,
+    objects:     Vec<(&'static str, GLuint)>,
+    next_id:     GLuint,
+    error:       GLenum,
+    fail_next:   Vec<(String, GLenum)>,
+    // shader name -> stage
+    shaders:     BTreeMap<GLuint, GLenum>,
+    // stage -> info log of the failure to report
+    compile_failures: BTreeMap<GLenum, String>,
+    link_failure: Option<String>,
+    // shader or program name -> info log
+    logs:        BTreeMap<GLuint, String>,
+    attribute_locations: BTreeMap<String, GLint>,
+    uniform_locations:   BTreeMap<String, GLint>,
+    // name, type and array size of what every program reports as active
+    active_attributes:   Vec<(String, GLenum, GLint)>,
+    active_uniforms:     Vec<(String, GLenum, GLint)>,
+    // name and data size of each uniform block, by index
+    uniform_blocks:      Vec<(String, GLint)>,
+    storage_blocks:      Vec<(String, GLint)>,
+    // target -> bound buffer name
+    buffer_bindings: BTreeMap<GLenum, GLuint>,
+    // buffer name -> contents
+    buffers:     BTreeMap<GLuint, Vec<u8>>,
+    // the program glUseProgram made current
+    current_program: GLuint,
+}
+
+impl State {
+    const fn new() -> State
+    {
+        State {
+            calls:       Vec::new(),
+            objects:     Vec::new(),
+            next_id:     1,
+            error:       gl::NO_ERROR,
+            fail_next:   Vec::new(),
+            shaders:     BTreeMap::new(),
+            compile_failures: BTreeMap::new(),
+            link_failure: None,
+            logs:        BTreeMap::new(),
+            attribute_locations: BTreeMap::new(),
+            uniform_locations:   BTreeMap::new(),
+            active_attributes:   Vec::new(),
+            active_uniforms:     Vec::new(),
+            uniform_blocks:      Vec::new(),
+            storage_blocks:      Vec::new(),
+            buffer_bindings: BTreeMap::new(),
+            buffers:     BTreeMap::new(),
+            current_program: 0,
+        }
+    }
+}
+
+static STATE: Mutex<State> = Mutex::new(State::new());
+
+// The `gl` function pointers are process wide, so only one test may use
+// the mock at a time.
+static INSTALLED: Mutex<()> = Mutex::new(());
+
+fn state() -> MutexGuard<'static, State>
+{
+    STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub struct Mock {
+    _installed: MutexGuard<'static, ()>,
+}
+
+impl Mock {
+    /// Loads the stubs into the `gl` function pointers with a fresh model.
+    /// Blocks while another `Mock` is alive.
+    pub fn install() -> Mock
+    {
+        let installed = INSTALLED.lock().unwrap_or_else(|e| e.into_inner());
+        *state() = State::new();
+        gl::load_with(load);
+        Mock { _installed: installed }
+    }
+
+    /// Every call recorded so far, in order.
+    pub fn calls(&self) -> Vec<Call>
+    {
+        state().calls.clone()
+    }
+
+    pub fn clear_calls(&self)
+    {
+        state().calls.clear();
+    }
+
+    /// Every object created or deleted so far, in order, as the name of
+    /// the GL function that did it and the object name it was given.
+    pub fn objects(&self) -> Vec<(&'static str, GLuint)>
+    {
+        state().objects.clone()
+    }
+
+    /// Makes every later compile of a `stage` shader fail with `log`.
+    pub fn fail_compile(&self, stage: GLenum, log: &str)
+    {
+        state().compile_failures.insert(stage, log.to_string());
+    }
+
+    /// Makes every later link fail with `log`.
+    pub fn fail_link(&self, log: &str)
+    {
+        state().link_failure = Some(log.to_string());
+    }
+
+    /// Locations not set here are handed out in order of first lookup.
+    /// Use -1 to simulate an attribute the program does not have.
+    pub fn set_attribute_location(&self, name: &str, location: GLint)
+    {
+        state().attribute_locations.insert(name.to_string(), location);
+    }
+
+    pub fn set_uniform_location(&self, name: &str, location: GLint)
+    {
+        state().uniform_locations.insert(name.to_string(), location);
+    }
+
+    /// Makes every program report an active attribute `name` of `gl_type`,
+    /// e.g. `gl::FLOAT_VEC3`, with `size` array elements.
+    pub fn add_active_attribute(&self, name: &str, gl_type: GLenum, size: GLint)
+    {
+        state().active_attributes.push((name.to_string(), gl_type, size));
+    }
+
+    /// Like `add_active_attribute`. Name arrays as GL does, e.g.
+    /// `"lights[0]"`.
+    pub fn add_active_uniform(&self, name: &str, gl_type: GLenum, size: GLint)
+    {
+        state().active_uniforms.push((name.to_string(), gl_type, size));
+    }
+
+    /// Makes every program report a uniform block `name` of `size` bytes,
+    /// at the next index.
+    pub fn add_uniform_block(&self, name: &str, size: GLint)
+    {
+        state().uniform_blocks.push((name.to_string(), size));
+    }
+
+    /// Like `add_uniform_block`, for shader storage blocks.
+    pub fn add_shader_storage_block(&self, name: &str, size: GLint)
+    {
+        state().storage_blocks.push((name.to_string(), size));
+    }
+
+    /// Makes the next call to `function`, e.g. `"glBindBuffer"`, raise
+    /// `error`.
+    pub fn fail_next(&self, function: &str, error: GLenum)
+    {
+        state().fail_next.push((function.to_string(), error));
+    }
+
+    /// Raises `error` as if the previous call had failed.
+    pub fn set_error(&self, error: GLenum)
+    {
+        raise(&mut state(), error);
+    }
+}
+
+fn record(s: &mut State, name: &'static str, args: Vec<Arg>)
+{
+    s.calls.push(Call { name, args });
+    if let Some(i) = s.fail_next.iter().position(|f| f.0 == name) {
+        let (_, error) = s.fail_next.remove(i);
+        raise(s, error);
+    }
+}
+
+// Like GL, keeps the first error until glGetError reports it.
+fn raise(s: &mut State, error: GLenum)
+{
+    if s.error == gl::NO_ERROR {
+        s.error = error;
+    }
+}
+
+fn location(locations: &mut BTreeMap<String, GLint>, name: String) -> GLint
+{
+    let next = locations.values().cloned().max().unwrap_or(-1) + 1;
+    *locations.entry(name).or_insert(next)
+}
+
+unsafe fn c_str(s: *const GLchar) -> String
+{
+    CStr::from_ptr(s).to_string_lossy().into_owned()
+}
+
+unsafe fn write_log(log: &str, buf_size: GLsizei, length: *mut GLsizei, out: *mut GLchar)
+{
+    let n = log.len().min((buf_size.max(1) - 1) as usize);
+    if buf_size > 0 {
+        ptr::copy_nonoverlapping(log.as_ptr() as *const GLchar, out, n);
+        *out.add(n) = 0;
+    }
+    if !length.is_null() {
+        *length = n as GLsizei;
+    }
+}
+
+fn load(name: &str) -> *const c_void
+{
+    match name {
+        "glGetError"            => get_error as *const c_void,
+        "glGenBuffers"          => gen_buffers as *const c_void,
+        "glDeleteBuffers"       => delete_buffers as *const c_void,
+        "glGenVertexArrays"     => gen_vertex_arrays as *const c_void,
+        "glDeleteVertexArrays"  => delete_vertex_arrays as *const c_void,
+        "glGenTextures"         => gen_textures as *const c_void,
+        "glDeleteTextures"      => delete_textures as *const c_void,
+        "glCreateShader"        => create_shader as *const c_void,
+        "glDeleteShader"        => delete_shader as *const c_void,
+        "glShaderSource"        => shader_source as *const c_void,
+        "glCompileShader"       => compile_shader as *const c_void,
+        "glGetShaderiv"         => get_shader_iv as *const c_void,
+        "glGetShaderInfoLog"    => get_shader_info_log as *const c_void,
+        "glCreateProgram"       => create_program as *const c_void,
+        "glDeleteProgram"       => delete_program as *const c_void,
+        "glLinkProgram"         => link_program as *const c_void,
+        "glGetProgramiv"        => get_program_iv as *const c_void,
+        "glGetProgramInfoLog"   => get_program_info_log as *const c_void,
+        "glGetAttribLocation"   => get_attrib_location as *const c_void,
+        "glGetUniformLocation"  => get_uniform_location as *const c_void,
+        "glGetActiveAttrib"     => get_active_attrib as *const c_void,
+        "glGetActiveUniform"    => get_active_uniform as *const c_void,
+        "glGetUniformBlockIndex" => get_uniform_block_index as *const c_void,
+        "glGetProgramResourceIndex" => get_program_resource_index as *const c_void,
+        "glGetProgramResourceiv" => get_program_resource_iv as *const c_void,
+        "glGetActiveUniformBlockiv" => get_active_uniform_block_iv as *const c_void,
+        "glBindAttribLocation"  => bind_attrib_location as *const c_void,
+        "glBindBuffer"          => bind_buffer as *const c_void,
+        "glBufferData"          => buffer_data as *const c_void,
+        "glBufferSubData"       => buffer_sub_data as *const c_void,
+        "glGetBufferSubData"    => get_buffer_sub_data as *const c_void,
+        "glUseProgram"          => use_program as *const c_void,
+        "glGetIntegerv"         => get_integer_v as *const c_void,
+        _                       => load_recording(name),
+    }
+}
+
+extern "system" fn get_error() -> GLenum
+{
+    let mut s = state();
+    let error = s.error;
+    s.error = gl::NO_ERROR;
+    error
+}
+
+unsafe fn gen(func: &'static str, n: GLsizei, ids: *mut GLuint)
+{
+    let mut s = state();
+    let mut args = vec![Arg::from(n)];
+    for id in slice::from_raw_parts_mut(ids, n as usize) {
+        *id = s.next_id;
+        s.next_id += 1;
+        s.objects.push((func, *id));
+        args.push(Arg::from(*id));
+    }
+    record(&mut s, func, args);
+}
+
+unsafe fn delete_n(func: &'static str, n: GLsizei, ids: *const GLuint)
+{
+    let mut s = state();
+    let mut args = vec![Arg::from(n)];
+    for id in slice::from_raw_parts(ids, n as usize) {
+        s.objects.push((func, *id));
+        args.push(Arg::from(*id));
+    }
+    record(&mut s, func, args);
+}
+
+extern "system" fn gen_buffers(n: GLsizei, ids: *mut GLuint)
+{
+    unsafe { gen("glGenBuffers", n, ids) }
+}
+
+extern "system" fn delete_buffers(n: GLsizei, ids: *const GLuint)
+{
+    unsafe { delete_n("glDeleteBuffers", n, ids) }
+}
+
+extern "system" fn gen_vertex_arrays(n: GLsizei, ids: *mut GLuint)
+{
+    unsafe { gen("glGenVertexArrays", n, ids) }
+}
+
+extern "system" fn delete_vertex_arrays(n: GLsizei, ids: *const GLuint)
+{
+    unsafe { delete_n("glDeleteVertexArrays", n, ids) }
+}
+
+extern "system" fn gen_textures(n: GLsizei, ids: *mut GLuint)
+{
+    unsafe { gen("glGenTextures", n, ids) }
+}
+
+extern "system" fn delete_textures(n: GLsizei, ids: *const GLuint)
+{
+    unsafe { delete_n("glDeleteTextures", n, ids) }
+}
+
+extern "system" fn create_shader(stage: GLenum) -> GLuint
+{
+    let mut s = state();
+    let id = s.next_id;
+    s.next_id += 1;
+    s.objects.push(("glCreateShader", id));
+    s.shaders.insert(id, stage);
+    record(&mut s, "glCreateShader", vec![Arg::from(stage)]);
+    id
+}
+
+extern "system" fn delete_shader(id: GLuint)
+{
+    let mut s = state();
+    s.objects.push(("glDeleteShader", id));
+    record(&mut s, "glDeleteShader", vec![Arg::from(id)]);
+}
+
+extern "system" fn shader_source(shader: GLuint, count: GLsizei,
+                                 strings: *const *const GLchar, lengths: *const GLint)
+{
+    let mut source = String::new();
+    unsafe {
+        for i in 0..count.max(0) as usize {
+            let s = *strings.add(i);
+            if lengths.is_null() || *lengths.add(i) < 0 {
+                source.push_str(&c_str(s));
+            } else {
+                let bytes = slice::from_raw_parts(s as *const u8, *lengths.add(i) as usize);
+                source.push_str(&String::from_utf8_lossy(bytes));
+            }
+        }
+    }
+    record(&mut state(), "glShaderSource",
+           vec![Arg::from(shader), Arg::from(count), Arg::Str(source)]);
+}
+
+extern "system" fn compile_shader(shader: GLuint)
+{
+    let mut s = state();
+    let failure = s.shaders.get(&shader)
+        .and_then(|stage| s.compile_failures.get(stage))
+        .cloned();
+    match failure {
+        Some(log) => { s.logs.insert(shader, log); }
+        None      => { s.logs.remove(&shader); }
+    }
+    record(&mut s, "glCompileShader", vec![Arg::from(shader)]);
+}
+
+// Answers the object queries shared by glGetShaderiv and glGetProgramiv.
+fn object_iv(s: &State, id: GLuint, pname: GLenum) -> GLint
+{
+    let failed = s.logs.contains_key(&id);
+    let max_len = |active: &[(String, GLenum, GLint)]| {
+        active.iter().map(|a| a.0.len() as GLint + 1).max().unwrap_or(0)
+    };
+    match pname {
+        gl::COMPILE_STATUS |
+        gl::LINK_STATUS      => (if failed { gl::FALSE } else { gl::TRUE }) as GLint,
+        gl::INFO_LOG_LENGTH  => s.logs.get(&id).map_or(0, |l| l.len() + 1) as GLint,
+        gl::ACTIVE_ATTRIBUTES => s.active_attributes.len() as GLint,
+        gl::ACTIVE_ATTRIBUTE_MAX_LENGTH => max_len(&s.active_attributes),
+        gl::ACTIVE_UNIFORMS  => s.active_uniforms.len() as GLint,
+        gl::ACTIVE_UNIFORM_MAX_LENGTH => max_len(&s.active_uniforms),
+        _                    => 0,
+    }
+}
+
+extern "system" fn get_shader_iv(shader: GLuint, pname: GLenum, params: *mut GLint)
+{
+    let mut s = state();
+    unsafe { *params = object_iv(&s, shader, pname) };
+    record(&mut s, "glGetShaderiv", vec![Arg::from(shader), Arg::from(pname)]);
+}
+
+extern "system" fn get_shader_info_log(shader: GLuint, buf_size: GLsizei,
+                                       length: *mut GLsizei, log: *mut GLchar)
+{
+    let mut s = state();
+    let text = s.logs.get(&shader).cloned().unwrap_or_default();
+    unsafe { write_log(&text, buf_size, length, log) };
+    record(&mut s, "glGetShaderInfoLog", vec![Arg::from(shader), Arg::from(buf_size)]);
+}
+
+extern "system" fn create_program() -> GLuint
+{
+    let mut s = state();
+    let id = s.next_id;
+    s.next_id += 1;
+    s.objects.push(("glCreateProgram", id));
+    record(&mut s, "glCreateProgram", vec![]);
+    id
+}
+
+extern "system" fn delete_program(id: GLuint)
+{
+    let mut s = state();
+    s.objects.push(("glDeleteProgram", id));
+    record(&mut s, "glDeleteProgram", vec![Arg::from(id)]);
+}
+
+extern "system" fn link_program(program: GLuint)
+{
+    let mut s = state();
+    match s.link_failure.clone() {
+        Some(log) => { s.logs.insert(program, log); }
+        None      => { s.logs.remove(&program); }
+    }
+    record(&mut s, "glLinkProgram", vec![Arg::from(program)]);
+}
+
+extern "system" fn get_program_iv(program: GLuint, pname: GLenum, params: *mut GLint)
+{
+    let mut s = state();
+    unsafe { *params = object_iv(&s, program, pname) };
+    record(&mut s, "glGetProgramiv", vec![Arg::from(program), Arg::from(pname)]);
+}
+
+extern "system" fn get_program_info_log(program: GLuint, buf_size: GLsizei,
+                                        length: *mut GLsizei, log: *mut GLchar)
+{
+    let mut s = state();
+    let text = s.logs.get(&program).cloned().unwrap_or_default();
+    unsafe { write_log(&text, buf_size, length, log) };
+    record(&mut s, "glGetProgramInfoLog", vec![Arg::from(program), Arg::from(buf_size)]);
+}
+
+extern "system" fn get_attrib_location(program: GLuint, name: *const GLchar) -> GLint
+{
+    let mut s = state();
+    let name = unsafe { c_str(name) };
+    let loc  = location(&mut s.attribute_locations, name.clone());
+    record(&mut s, "glGetAttribLocation", vec![Arg::from(program), Arg::Str(name)]);
+    loc
+}
+
+// Locations are shared by all programs, so a binding applies to every
+// later lookup of the name.
+extern "system" fn bind_attrib_location(program: GLuint, index: GLuint, name: *const GLchar)
+{
+    let mut s = state();
+    let name = unsafe { c_str(name) };
+    s.attribute_locations.insert(name.clone(), index as GLint);
+    record(&mut s, "glBindAttribLocation",
+           vec![Arg::from(program), Arg::from(index), Arg::Str(name)]);
+}
+
+extern "system" fn get_uniform_location(program: GLuint, name: *const GLchar) -> GLint
+{
+    let mut s = state();
+    let name = unsafe { c_str(name) };
+    let loc  = location(&mut s.uniform_locations, name.clone());
+    record(&mut s, "glGetUniformLocation", vec![Arg::from(program), Arg::Str(name)]);
+    loc
+}
+
+// Answers glGetActiveAttrib and glGetActiveUniform with `active`, raising
+// INVALID_VALUE for an index past the end.
+unsafe fn write_active(s: &mut State, active: Option<(String, GLenum, GLint)>,
+                       buf_size: GLsizei, length: *mut GLsizei, size: *mut GLint,
+                       gl_type: *mut GLenum, name: *mut GLchar)
+{
+    match active {
+        Some((n, t, n_elements)) => {
+            write_log(&n, buf_size, length, name);
+            *size    = n_elements;
+            *gl_type = t;
+        }
+        None => raise(s, gl::INVALID_VALUE),
+    }
+}
+
+extern "system" fn get_active_attrib(program: GLuint, index: GLuint, buf_size: GLsizei,
+                                     length: *mut GLsizei, size: *mut GLint,
+                                     gl_type: *mut GLenum, name: *mut GLchar)
+{
+    let mut s = state();
+    let active = s.active_attributes.get(index as usize).cloned();
+    unsafe { write_active(&mut s, active, buf_size, length, size, gl_type, name) };
+    record(&mut s, "glGetActiveAttrib",
+           vec![Arg::from(program), Arg::from(index), Arg::from(buf_size)]);
+}
+
+extern "system" fn get_active_uniform(program: GLuint, index: GLuint, buf_size: GLsizei,
+                                      length: *mut GLsizei, size: *mut GLint,
+                                      gl_type: *mut GLenum, name: *mut GLchar)
+{
+    let mut s = state();
+    let active = s.active_uniforms.get(index as usize).cloned();
+    unsafe { write_active(&mut s, active, buf_size, length, size, gl_type, name) };
+    record(&mut s, "glGetActiveUniform",
+           vec![Arg::from(program), Arg::from(index), Arg::from(buf_size)]);
+}
+
+extern "system" fn get_uniform_block_index(program: GLuint, name: *const GLchar) -> GLuint
+{
+    let mut s = state();
+    let name  = unsafe { c_str(name) };
+    let index = s.uniform_blocks.iter()
+        .position(|b| b.0 == name)
+        .map_or(gl::INVALID_INDEX, |i| i as GLuint);
+    record(&mut s, "glGetUniformBlockIndex", vec![Arg::from(program), Arg::Str(name)]);
+    index
+}
+
+extern "system" fn get_active_uniform_block_iv(program: GLuint, index: GLuint, pname: GLenum,
+                                               params: *mut GLint)
+{
+    let mut s = state();
+    match (s.uniform_blocks.get(index as usize).map(|b| b.1), pname) {
+        (Some(size), gl::UNIFORM_BLOCK_DATA_SIZE) => unsafe { *params = size },
+        (Some(_), _) => raise(&mut s, gl::INVALID_ENUM),
+        (None, _)    => raise(&mut s, gl::INVALID_VALUE),
+    }
+    record(&mut s, "glGetActiveUniformBlockiv",
+           vec![Arg::from(program), Arg::from(index), Arg::from(pname)]);
+}
+
+// Only shader storage blocks are program resources here.
+extern "system" fn get_program_resource_index(program: GLuint, interface: GLenum,
+                                              name: *const GLchar) -> GLuint
+{
+    let mut s = state();
+    let name  = unsafe { c_str(name) };
+    let index = match interface {
+        gl::SHADER_STORAGE_BLOCK => s.storage_blocks.iter()
+            .position(|b| b.0 == name)
+            .map_or(gl::INVALID_INDEX, |i| i as GLuint),
+        _ => gl::INVALID_INDEX,
+    };
+    record(&mut s, "glGetProgramResourceIndex",
+           vec![Arg::from(program), Arg::from(interface), Arg::Str(name)]);
+    index
+}
+
+extern "system" fn get_program_resource_iv(program: GLuint, interface: GLenum, index: GLuint,
+                                           prop_count: GLsizei, props: *const GLenum,
+                                           buf_size: GLsizei, length: *mut GLsizei,
+                                           params: *mut GLint)
+{
+    let mut s = state();
+    let props = unsafe { ::std::slice::from_raw_parts(props, prop_count as usize) };
+    let size  = s.storage_blocks.get(index as usize).map(|b| b.1);
+    match (interface, size, props) {
+        (gl::SHADER_STORAGE_BLOCK, Some(size), &[gl::BUFFER_DATA_SIZE]) if buf_size >= 1 =>
+            unsafe {
+                *params = size;
+                if !length.is_null() {
+                    *length = 1;
+                }
+            },
+        (gl::SHADER_STORAGE_BLOCK, None, _) => raise(&mut s, gl::INVALID_VALUE),
+        _ => raise(&mut s, gl::INVALID_ENUM),
+    }
+    record(&mut s, "glGetProgramResourceiv",
+           vec![Arg::from(program), Arg::from(interface), Arg::from(index)]);
+}
+
+extern "system" fn bind_buffer(target: GLenum, buffer: GLuint)
+{
+    let mut s = state();
+    s.buffer_bindings.insert(target, buffer);
+    record(&mut s, "glBindBuffer", vec![Arg::from(target), Arg::from(buffer)]);
+}
+
+// The contents of the buffer bound to `target`, and the range
+// `offset..offset + size` of them if it is in bounds.
+fn bound_range(s: &mut State, target: GLenum, offset: GLintptr, size: GLsizeiptr)
+    -> Option<(&mut Vec<u8>, ::std::ops::Range<usize>)>
+{
+    let id = s.buffer_bindings.get(&target).cloned().unwrap_or(0);
+    let contents = s.buffers.get_mut(&id)?;
+    if offset < 0 || size < 0 || (offset + size) as usize > contents.len() {
+        return None
+    }
+    Some((contents, offset as usize..(offset + size) as usize))
+}
+
+extern "system" fn buffer_data(target: GLenum, size: GLsizeiptr,
+                               data: *const c_void, usage: GLenum)
+{
+    let mut s = state();
+    let (contents, arg) = if data.is_null() {
+        (vec![0; size as usize], Arg::Ptr(0))
+    } else {
+        let bytes = unsafe { slice::from_raw_parts(data as *const u8, size as usize) }.to_vec();
+        (bytes.clone(), Arg::Data(bytes))
+    };
+    let id = s.buffer_bindings.get(&target).cloned().unwrap_or(0);
+    s.buffers.insert(id, contents);
+    record(&mut s, "glBufferData",
+           vec![Arg::from(target), Arg::from(size), arg, Arg::from(usage)]);
+}
+
+extern "system" fn buffer_sub_data(target: GLenum, offset: GLintptr,
+                                   size: GLsizeiptr, data: *const c_void)
+{
+    let mut s = state();
+    let bytes = unsafe { slice::from_raw_parts(data as *const u8, size as usize) }.to_vec();
+    match bound_range(&mut s, target, offset, size) {
+        Some((contents, range)) => contents[range].copy_from_slice(&bytes),
+        None                    => raise(&mut s, gl::INVALID_VALUE),
+    }
+    record(&mut s, "glBufferSubData",
+           vec![Arg::from(target), Arg::from(offset), Arg::from(size), Arg::Data(bytes)]);
+}
+
+extern "system" fn get_buffer_sub_data(target: GLenum, offset: GLintptr,
+                                       size: GLsizeiptr, data: *mut c_void)
+{
+    let mut s = state();
+    match bound_range(&mut s, target, offset, size) {
+        Some((contents, range)) => unsafe {
+            ptr::copy_nonoverlapping(contents[range].as_ptr(), data as *mut u8, size as usize)
+        },
+        None => raise(&mut s, gl::INVALID_VALUE),
+    }
+    record(&mut s, "glGetBufferSubData",
+           vec![Arg::from(target), Arg::from(offset), Arg::from(size)]);
+}
+
+extern "system" fn use_program(program: GLuint)
+{
+    let mut s = state();
+    s.current_program = program;
+    record(&mut s, "glUseProgram", vec![Arg::from(program)]);
+}
+
+// Only the current program is modelled.
+extern "system" fn get_integer_v(pname: GLenum, data: *mut GLint)
+{
+    let mut s = state();
+    record(&mut s, "glGetIntegerv", vec![Arg::from(pname)]);
+    match pname {
+        gl::CURRENT_PROGRAM => unsafe { *data = s.current_program as GLint },
+        _                   => raise(&mut s, gl::INVALID_ENUM),
+    }
+}
+
+// Stubs that only record their arguments.
+macro_rules! recording_stubs {
+    ($( $gl_name:literal => fn $f:ident ( $($arg:ident : $ty:ty),* ); )+) => (
+        $(
+            extern "system" fn $f($($arg: $ty),*)
+            {
+                record(&mut state(), $gl_name, vec![$(Arg::from($arg)),*]);
+            }
+        )+
+
+        fn load_recording(name: &str) -> *const c_void
+        {
+            match name {
+                $( $gl_name => $f as *const c_void, )+
+                _ => load_uniform(name),
+            }
+        }
+    )
+}
+
+recording_stubs! {
+    "glActiveTexture"       => fn active_texture(texture: GLenum);
+    "glAttachShader"        => fn attach_shader(program: GLuint, shader: GLuint);
+    "glBindBufferBase"      => fn bind_buffer_base(target: GLenum, index: GLuint,
+                                                   buffer: GLuint);
+    "glBindVertexBuffer"    => fn bind_vertex_buffer(bindingindex: GLuint, buffer: GLuint,
+                                                     offset: GLintptr, stride: GLsizei);
+    "glBindTexture"         => fn bind_texture(target: GLenum, texture: GLuint);
+    "glBindVertexArray"     => fn bind_vertex_array(array: GLuint);
+    "glBlendFunc"           => fn blend_func(sfactor: GLenum, dfactor: GLenum);
+    "glClear"               => fn clear(mask: GLbitfield);
+    "glClearColor"          => fn clear_color(red: GLfloat, green: GLfloat,
+                                              blue: GLfloat, alpha: GLfloat);
+    "glDepthFunc"           => fn depth_func(func: GLenum);
+    "glDisable"             => fn disable(cap: GLenum);
+    "glDrawArrays"          => fn draw_arrays(mode: GLenum, first: GLint, count: GLsizei);
+    "glDrawArraysInstanced" => fn draw_arrays_instanced(mode: GLenum, first: GLint,
+                                                        count: GLsizei, instancecount: GLsizei);
+    "glDrawElements"        => fn draw_elements(mode: GLenum, count: GLsizei,
+                                                type_: GLenum, indices: *const c_void);
+    "glDrawElementsBaseVertex" => fn draw_elements_base_vertex(mode: GLenum, count: GLsizei,
+                                                               type_: GLenum,
+                                                               indices: *const c_void,
+                                                               basevertex: GLint);
+    "glDrawElementsInstanced" => fn draw_elements_instanced(mode: GLenum, count: GLsizei,
+                                                            type_: GLenum,
+                                                            indices: *const c_void,
+                                                            instancecount: GLsizei);
+    "glDrawRangeElements"   => fn draw_range_elements(mode: GLenum, start: GLuint, end: GLuint,
+                                                      count: GLsizei, type_: GLenum,
+                                                      indices: *const c_void);
+    "glEnable"              => fn enable(cap: GLenum);
+    "glEnableVertexAttribArray" => fn enable_vertex_attrib_array(index: GLuint);
+    "glGenerateMipmap"      => fn generate_mipmap(target: GLenum);
+    "glShaderStorageBlockBinding" => fn shader_storage_block_binding(program: GLuint,
+                                                                     index: GLuint,
+                                                                     binding: GLuint);
+    "glTexImage2D"          => fn tex_image_2d(target: GLenum, level: GLint,
+                                               internalformat: GLint, width: GLsizei,
+                                               height: GLsizei, border: GLint,
+                                               format: GLenum, type_: GLenum,
+                                               pixels: *const c_void);
+    "glTexParameteri"       => fn tex_parameter_i(target: GLenum, pname: GLenum, param: GLint);
+    "glUniform1i"           => fn uniform_1i(location: GLint, v0: GLint);
+    "glUniformBlockBinding" => fn uniform_block_binding(program: GLuint, index: GLuint,
+                                                        binding: GLuint);
+    "glVertexAttribBinding" => fn vertex_attrib_binding(attribindex: GLuint,
+                                                        bindingindex: GLuint);
+    "glVertexAttribDivisor" => fn vertex_attrib_divisor(index: GLuint, divisor: GLuint);
+    "glVertexAttribFormat"  => fn vertex_attrib_format(attribindex: GLuint, size: GLint,
+                                                       type_: GLenum, normalized: GLboolean,
+                                                       relativeoffset: GLuint);
+    "glVertexAttribIFormat" => fn vertex_attrib_i_format(attribindex: GLuint, size: GLint,
+                                                         type_: GLenum, relativeoffset: GLuint);
+    "glVertexAttribIPointer" => fn vertex_attrib_i_pointer(index: GLuint, size: GLint,
+                                                           type_: GLenum, stride: GLsizei,
+                                                           pointer: *const c_void);
+    "glVertexAttribLFormat" => fn vertex_attrib_l_format(attribindex: GLuint, size: GLint,
+                                                         type_: GLenum, relativeoffset: GLuint);
+    "glVertexAttribLPointer" => fn vertex_attrib_l_pointer(index: GLuint, size: GLint,
+                                                           type_: GLenum, stride: GLsizei,
+                                                           pointer: *const c_void);
+    "glVertexAttribPointer" => fn vertex_attrib_pointer(index: GLuint, size: GLint,
+                                                        type_: GLenum,
+                                                        normalized: GLboolean,
+                                                        stride: GLsizei,
+                                                        pointer: *const c_void);
+    "glVertexBindingDivisor" => fn vertex_binding_divisor(bindingindex: GLuint,
+                                                          divisor: GLuint);
+}
+
+// glUniform* and glUniformMatrix* stubs, which record the values read
+// from the pointer as the given `Arg` variant. The number is the count of
+// values per element.
+macro_rules! uniform_stubs {
+    ($( $gl_name:literal => fn $f:ident($ty:ty), $n:expr, $variant:ident; )+) => (
+        $(
+            extern "system" fn $f(location: GLint, count: GLsizei, value: *const $ty)
+            {
+                let values = unsafe { slice::from_raw_parts(value, count.max(0) as usize * $n) };
+                record(&mut state(), $gl_name,
+                       vec![Arg::from(location), Arg::from(count),
+                            Arg::$variant(values.iter().map(|&v| v as _).collect())]);
+            }
+        )+
+
+        fn load_uniform(name: &str) -> *const c_void
+        {
+            match name {
+                $( $gl_name => $f as *const c_void, )+
+                _ => load_uniform_matrix(name),
+            }
+        }
+    )
+}
+
+macro_rules! uniform_matrix_stubs {
+    ($( $gl_name:literal => fn $f:ident($ty:ty), $n:expr, $variant:ident; )+) => (
+        $(
+            extern "system" fn $f(location: GLint, count: GLsizei,
+                                  transpose: GLboolean, value: *const $ty)
+            {
+                let values = unsafe { slice::from_raw_parts(value, count.max(0) as usize * $n) };
+                record(&mut state(), $gl_name,
+                       vec![Arg::from(location), Arg::from(count), Arg::from(transpose),
+                            Arg::$variant(values.iter().map(|&v| v as _).collect())]);
+            }
+        )+
+
+        fn load_uniform_matrix(name: &str) -> *const c_void
+        {
+            match name {
+                $( $gl_name => $f as *const c_void, )+
+                _ => ptr::null(),
+            }
+        }
+    )
+}
+
+uniform_stubs! {
+    "glUniform1fv"  => fn uniform_1fv(GLfloat), 1, Floats;
+    "glUniform2fv"  => fn uniform_2fv(GLfloat), 2, Floats;
+    "glUniform3fv"  => fn uniform_3fv(GLfloat), 3, Floats;
+    "glUniform4fv"  => fn uniform_4fv(GLfloat), 4, Floats;
+    "glUniform1iv"  => fn uniform_1iv(GLint), 1, Ints;
+    "glUniform2iv"  => fn uniform_2iv(GLint), 2, Ints;
+    "glUniform3iv"  => fn uniform_3iv(GLint), 3, Ints;
+    "glUniform4iv"  => fn uniform_4iv(GLint), 4, Ints;
+    "glUniform1uiv" => fn uniform_1uiv(GLuint), 1, Ints;
+    "glUniform2uiv" => fn uniform_2uiv(GLuint), 2, Ints;
+    "glUniform3uiv" => fn uniform_3uiv(GLuint), 3, Ints;
+    "glUniform4uiv" => fn uniform_4uiv(GLuint), 4, Ints;
+    "glUniform1dv"  => fn uniform_1dv(GLdouble), 1, Doubles;
+    "glUniform2dv"  => fn uniform_2dv(GLdouble), 2, Doubles;
+    "glUniform3dv"  => fn uniform_3dv(GLdouble), 3, Doubles;
+    "glUniform4dv"  => fn uniform_4dv(GLdouble), 4, Doubles;
+}
+
+uniform_matrix_stubs! {
+    "glUniformMatrix2fv"   => fn uniform_matrix_2fv(GLfloat), 4, Floats;
+    "glUniformMatrix3fv"   => fn uniform_matrix_3fv(GLfloat), 9, Floats;
+    "glUniformMatrix4fv"   => fn uniform_matrix_4fv(GLfloat), 16, Floats;
+    "glUniformMatrix2x3fv" => fn uniform_matrix_2x3fv(GLfloat), 6, Floats;
+    "glUniformMatrix2x4fv" => fn uniform_matrix_2x4fv(GLfloat), 8, Floats;
+    "glUniformMatrix3x2fv" => fn uniform_matrix_3x2fv(GLfloat), 6, Floats;
+    "glUniformMatrix3x4fv" => fn uniform_matrix_3x4fv(GLfloat), 12, Floats;
+    "glUniformMatrix4x2fv" => fn uniform_matrix_4x2fv(GLfloat), 8, Floats;
+    "glUniformMatrix4x3fv" => fn uniform_matrix_4x3fv(GLfloat), 12, Floats;
+    "glUniformMatrix2dv"   => fn uniform_matrix_2dv(GLdouble), 4, Doubles;
+    "glUniformMatrix3dv"   => fn uniform_matrix_3dv(GLdouble), 9, Doubles;
+    "glUniformMatrix4dv"   => fn uniform_matrix_4dv(GLdouble), 16, Doubles;
+    "glUniformMatrix2x3dv" => fn uniform_matrix_2x3dv(GLdouble), 6, Doubles;
+    "glUniformMatrix2x4dv" => fn uniform_matrix_2x4dv(GLdouble), 8, Doubles;
+    "glUniformMatrix3x2dv" => fn uniform_matrix_3x2dv(GLdouble), 6, Doubles;
+    "glUniformMatrix3x4dv" => fn uniform_matrix_3x4dv(GLdouble), 12, Doubles;
+    "glUniformMatrix4x2dv" => fn uniform_matrix_4x2dv(GLdouble), 8, Doubles;
+    "glUniformMatrix4x3dv" => fn uniform_matrix_4x3dv(GLdouble), 12, Doubles;
+}
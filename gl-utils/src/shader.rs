@@ -1,6 +1,7 @@
 use gl;
 use gl::types::*;
-use util::*;
+use context::{Slot, changing};
+use error::{Error, clear_pending_errors, log_pending_errors, report_drop_error};
 use std::rc::Rc;
 
 struct ShaderHandle {
@@ -8,7 +9,7 @@ struct ShaderHandle {
 }
 
 impl ShaderHandle {
-    fn new(target: GLenum) -> Result<Self, GLenum>
+    fn new(target: GLenum) -> Result<Self, Error>
     {
         unsafe {
             let mut h = ShaderHandle { id: 0 };
@@ -17,19 +18,28 @@ impl ShaderHandle {
             Ok(h)
         }
     }
+
+    fn delete(&mut self) -> Result<(), Error>
+    {
+        if self.id == 0 {
+            return Ok(())
+        }
+        unsafe {
+            clear_pending_errors();
+            gl::DeleteShader(self.id);
+            self.id = 0;
+            check_error!();
+            Ok(())
+        }
+    }
 }
 
 impl Drop for ShaderHandle {
     fn drop(&mut self)
     {
-        unsafe {
-            gl::DeleteShader(self.id);
-            // We should use check_error here, but the type of drop
-            // won't allow it. We have to panic instead.
-            let err = gl::GetError();
-            if err != gl::NO_ERROR {
-                panic!("DeleteShader returned: {}", gl_error_str(err));
-            }
+        log_pending_errors();
+        if let Err(e) = self.delete() {
+            report_drop_error(e);
         }
     }
 }
@@ -41,16 +51,104 @@ pub struct Program {
 
 #[derive(Debug)]
 struct ProgramHandle {
-    id: GLuint,
+    id:         GLuint,
+    attributes: Vec<ActiveVariable>,
+    uniforms:   Vec<ActiveVariable>,
+}
+
+/// An active attribute or uniform of a linked program.
+#[derive(Debug,Clone,PartialEq)]
+pub struct ActiveVariable {
+    /// The name, without the `[0]` GL appends to arrays.
+    pub name:     String,
+    /// The GLSL type, e.g. `gl::FLOAT_VEC3` or `gl::SAMPLER_2D`.
+    pub gl_type:  GLenum,
+    /// The number of array elements, 1 if it is not an array.
+    pub size:     GLint,
+    /// -1 for built-ins and uniforms in blocks.
+    pub location: GLint,
+    /// For uniforms with a location, the location of each array element,
+    /// looked up at link time. Empty otherwise.
+    pub elements: Vec<GLint>,
+}
+
+type GetActive = unsafe fn(GLuint, GLuint, GLsizei, *mut GLsizei, *mut GLint, *mut GLenum,
+                           *mut GLchar);
+type GetLocation = unsafe fn(GLuint, *const GLchar) -> GLint;
+
+// Lists the `count_pname` active variables of linked program `id`.
+unsafe fn active_variables(id: GLuint, count_pname: GLenum, max_length_pname: GLenum,
+                           get_active: GetActive, get_location: GetLocation)
+    -> Result<Vec<ActiveVariable>, Error>
+{
+    use std::ffi::CString;
+    let (mut count, mut max_length) = (0, 0);
+    gl::GetProgramiv(id, count_pname, &mut count);
+    check_error!();
+    gl::GetProgramiv(id, max_length_pname, &mut max_length);
+    check_error!();
+    let mut out = Vec::with_capacity(count.max(0) as usize);
+    for index in 0..count.max(0) as GLuint {
+        let mut name: Vec<u8> = vec![0; max_length.max(1) as usize];
+        let (mut length, mut size, mut gl_type) = (0, 0, 0);
+        get_active(id, index, max_length.max(1), &mut length, &mut size, &mut gl_type,
+                   name.as_mut_ptr() as *mut GLchar);
+        check_error!();
+        name.truncate(length.max(0) as usize);
+        let location = get_location(id, CString::new(name.clone())?.as_ptr());
+        check_error!();
+        if name.ends_with(b"[0]") {
+            let len = name.len() - 3;
+            name.truncate(len);
+        }
+        out.push(ActiveVariable {
+            name: String::from_utf8_lossy(&name).into_owned(),
+            gl_type,
+            size,
+            location,
+            elements: Vec::new(),
+        });
+    }
+    Ok(out)
+}
+
+// Fills in the `elements` of each of `uniforms` of linked program `id` that
+// has a location. GL makes no promise that they are consecutive.
+unsafe fn find_elements(id: GLuint, uniforms: &mut [ActiveVariable]) -> Result<(), Error>
+{
+    use std::ffi::CString;
+    for u in uniforms.iter_mut().filter(|u| u.location >= 0) {
+        u.elements.push(u.location);
+        for i in 1..u.size {
+            let name = CString::new(format!("{}[{}]", u.name, i))?;
+            u.elements.push(gl::GetUniformLocation(id, name.as_ptr()));
+            check_error!();
+        }
+    }
+    Ok(())
 }
 
 impl ProgramHandle {
-    fn new() -> Result<Rc<Self>, GLenum>
+    fn new() -> Result<Self, Error>
     {
         unsafe {
             let p = gl::CreateProgram();
             check_error!();
-            Ok(Rc::new(ProgramHandle { id: p }))
+            Ok(ProgramHandle { id: p, attributes: Vec::new(), uniforms: Vec::new() })
+        }
+    }
+
+    fn delete(&mut self) -> Result<(), Error>
+    {
+        if self.id == 0 {
+            return Ok(())
+        }
+        unsafe {
+            clear_pending_errors();
+            gl::DeleteProgram(self.id);
+            self.id = 0;
+            check_error!();
+            Ok(())
         }
     }
 }
@@ -58,14 +156,9 @@ impl ProgramHandle {
 impl Drop for ProgramHandle {
     fn drop(&mut self)
     {
-        unsafe {
-            gl::DeleteProgram(self.id);
-            // We should use check_error here, but the type of drop
-            // won't allow it. We have to panic instead.
-            let err = gl::GetError();
-            if err != gl::NO_ERROR {
-                panic!("DeleteProgram returned: {}", gl_error_str(err));
-            }
+        log_pending_errors();
+        if let Err(e) = self.delete() {
+            report_drop_error(e);
         }
     }
 }
@@ -75,99 +168,135 @@ pub struct ShaderSrc<'a> {
     pub fragment: &'a str,
 }
 
-fn check_shader_log(id: GLuint, pname: GLenum) -> Result<(), String>
+fn check_shader_log(id: GLuint, stage: GLenum) -> Result<(), Error>
 {
     let mut success = 0;
     unsafe {
-        gl::GetShaderiv(id, pname, &mut success);
-        check_error_as_string!();
+        gl::GetShaderiv(id, gl::COMPILE_STATUS, &mut success);
+        check_error!();
         if success == gl::FALSE as i32 {
             let mut log_size = 0;
             gl::GetShaderiv(id, gl::INFO_LOG_LENGTH, &mut log_size);
-            check_error_as_string!();
-            let mut log: Vec<u8> = Vec::with_capacity(log_size as usize);
+            check_error!();
+            let mut log: Vec<u8> = vec![0; log_size.max(0) as usize];
             gl::GetShaderInfoLog(id, log_size, &mut log_size,
                                  log.as_mut_ptr() as *mut GLchar);
-            check_error_as_string!();
-            log.set_len(log_size as usize);
-            return Err(String::from_utf8_unchecked(log))
+            check_error!();
+            log.truncate(log_size.max(0) as usize);
+            return Err(Error::ShaderCompile {
+                stage,
+                log: String::from_utf8_lossy(&log).into_owned(),
+            })
         }
     }
     Ok(())
 }
 
-fn check_program_log(id: GLuint, pname: GLenum) -> Result<(), String>
+fn check_program_log(id: GLuint) -> Result<(), Error>
 {
     let mut success = 0;
     unsafe {
-        gl::GetProgramiv(id, pname, &mut success);
-        check_error_as_string!();
+        gl::GetProgramiv(id, gl::LINK_STATUS, &mut success);
+        check_error!();
         if success == gl::FALSE as i32 {
             let mut log_size = 0;
             gl::GetProgramiv(id, gl::INFO_LOG_LENGTH, &mut log_size);
-            check_error_as_string!();
-            let mut log: Vec<u8> = Vec::with_capacity(log_size as usize);
+            check_error!();
+            let mut log: Vec<u8> = vec![0; log_size.max(0) as usize];
             gl::GetProgramInfoLog(id, log_size, &mut log_size,
                                   log.as_mut_ptr() as *mut GLchar);
-            check_error_as_string!();
-            log.set_len(log_size as usize);
-            return Err(String::from_utf8_unchecked(log))
+            check_error!();
+            log.truncate(log_size.max(0) as usize);
+            return Err(Error::Link {
+                log: String::from_utf8_lossy(&log).into_owned(),
+            })
         }
     }
     Ok(())
 }
 
 impl Program {
-    pub fn new(shaders: ShaderSrc) -> Result<Program, String>
+    pub fn new(shaders: ShaderSrc) -> Result<Program, Error>
+    {
+        Program::new_with_attrib_locations(shaders, &[])
+    }
+
+    /// Like `new`, binding each named vertex attribute to its location
+    /// before linking, e.g. with the result of `util::layout_locations`.
+    pub fn new_with_attrib_locations(shaders: ShaderSrc, locations: &[(&str, GLuint)])
+        -> Result<Program, Error>
     {
         unsafe {
             use std::ffi::CString;
             use std::ptr;
-            let vertex     = ShaderHandle::new(gl::VERTEX_SHADER)
-                .map_err(|e|format!("{}",e))?;
-            let vertex_str = CString::new(shaders.vertex)
-                .map_err(|e|format!("{}",e))?;
+            let vertex     = ShaderHandle::new(gl::VERTEX_SHADER)?;
+            let vertex_str = CString::new(shaders.vertex)?;
             gl::ShaderSource(vertex.id, 1, &vertex_str.as_ptr(), ptr::null());
-            check_error_as_string!();
+            check_error!();
             gl::CompileShader(vertex.id);
-            check_error_as_string!();
-            check_shader_log(vertex.id, gl::COMPILE_STATUS)?;
-            let fragment     = ShaderHandle::new(gl::FRAGMENT_SHADER)
-                .map_err(|e|format!("{}",e))?;
-            let fragment_str = CString::new(shaders.fragment)
-                .map_err(|e|format!("{}",e))?;
+            check_error!();
+            check_shader_log(vertex.id, gl::VERTEX_SHADER)?;
+            let fragment     = ShaderHandle::new(gl::FRAGMENT_SHADER)?;
+            let fragment_str = CString::new(shaders.fragment)?;
             gl::ShaderSource(fragment.id, 1, &fragment_str.as_ptr(), ptr::null());
-            check_error_as_string!();
+            check_error!();
             gl::CompileShader(fragment.id);
-            check_error_as_string!();
-            check_shader_log(fragment.id, gl::COMPILE_STATUS)?;
-            let h = ProgramHandle::new().map_err(|e| format!("{}",e))?;
-            check_error_as_string!();
+            check_error!();
+            check_shader_log(fragment.id, gl::FRAGMENT_SHADER)?;
+            let mut h = ProgramHandle::new()?;
             gl::AttachShader(h.id, vertex.id);
-            check_error_as_string!();
+            check_error!();
             gl::AttachShader(h.id, fragment.id);
-            check_error_as_string!();
+            check_error!();
+            for &(name, location) in locations {
+                let name_str = CString::new(name)?;
+                gl::BindAttribLocation(h.id, location, name_str.as_ptr());
+                check_error!();
+            }
             gl::LinkProgram(h.id);
-            check_error_as_string!();
-            check_program_log(h.id, gl::LINK_STATUS)?;
-            Ok(Program { handle: h })
+            check_error!();
+            check_program_log(h.id)?;
+            h.attributes = active_variables(h.id, gl::ACTIVE_ATTRIBUTES,
+                                            gl::ACTIVE_ATTRIBUTE_MAX_LENGTH,
+                                            gl::GetActiveAttrib, gl::GetAttribLocation)?;
+            h.uniforms   = active_variables(h.id, gl::ACTIVE_UNIFORMS,
+                                            gl::ACTIVE_UNIFORM_MAX_LENGTH,
+                                            gl::GetActiveUniform, gl::GetUniformLocation)?;
+            find_elements(h.id, &mut h.uniforms)?;
+            Ok(Program { handle: Rc::new(h) })
+        }
+    }
+
+    /// Deletes the program, returning any error instead of handing it to
+    /// the drop error policy. Clones share the program, so while others are
+    /// alive this only releases this one and the last clone deletes it.
+    pub fn delete(self) -> Result<(), Error>
+    {
+        match Rc::try_unwrap(self.handle) {
+            Ok(mut h) => h.delete(),
+            Err(_)    => Ok(()),
         }
     }
 
-    pub fn use_program(&self) -> Result<(), GLenum>
+    pub fn id(&self) -> GLuint
+    {
+        self.handle.id
+    }
+
+    pub fn use_program(&self) -> Result<(), Error>
     {
         unsafe {
+            changing(Slot::Program);
             gl::UseProgram(self.handle.id);
             check_error!();
             Ok(())
         }
     }
 
-    pub fn get_uniform_location<'a>(&self, name: &'a str) -> Result<GLint, GLenum>
+    pub fn get_uniform_location(&self, name: &str) -> Result<GLint, Error>
     {
         use std::ffi::CString;
-        let name_str = CString::new(name)
-            .map_err(|_| gl::INVALID_OPERATION)?;
+        let name_str = CString::new(name)?;
         unsafe {
             let loc = gl::GetUniformLocation(self.handle.id, name_str.as_ptr());
             check_error!();
@@ -175,11 +304,150 @@ impl Program {
         }
     }
 
-    pub fn get_attribute_location<'a>(&self, name: &'a str) -> Result<GLint, GLenum>
+    /// The active attributes, as reflected at link time.
+    pub fn active_attributes(&self) -> &[ActiveVariable]
+    {
+        &self.handle.attributes
+    }
+
+    /// The active uniforms, as reflected at link time. Each element of an
+    /// array of structs is listed separately, e.g. `lights[1].color`.
+    pub fn active_uniforms(&self) -> &[ActiveVariable]
+    {
+        &self.handle.uniforms
+    }
+
+    /// The active attribute called `name`, if there is one.
+    pub fn attribute(&self, name: &str) -> Option<&ActiveVariable>
+    {
+        self.handle.attributes.iter().find(|a| a.name == name)
+    }
+
+    /// The active uniform called `name`, or `name[0]`, if there is one.
+    pub fn uniform(&self, name: &str) -> Option<&ActiveVariable>
+    {
+        let name = name.strip_suffix("[0]").unwrap_or(name);
+        self.handle.uniforms.iter().find(|u| u.name == name)
+    }
+
+    /// The location of uniform `name`, the declaration it belongs to and
+    /// how many array elements are left from there on. `name` can be an
+    /// element of an array, e.g. `lights[2]`. Everything comes from link
+    /// time, so no GL calls are made. Members of uniform blocks have no
+    /// location and are an error; set them through a `UniformBuffer`.
+    pub fn find_uniform(&self, name: &str)
+        -> Result<Option<(GLint, &ActiveVariable, GLint)>, Error>
+    {
+        let element = match self.uniform(name) {
+            Some(u) => Some((u, 0)),
+            None    => name.strip_suffix(']')
+                .and_then(|n| n.rfind('[').map(|i| (&n[..i], &n[i + 1..])))
+                .and_then(|(base, i)| Some((self.uniform(base)?, i.parse::<GLint>().ok()?)))
+                .filter(|&(u, i)| 0 <= i && i < u.size),
+        };
+        let (u, i) = match element {
+            Some(e) => e,
+            None    => return Ok(None),
+        };
+        if u.location < 0 {
+            return Err(Error::Validation(format!(
+                "uniform `{}` of program {} has no location, as it is in a uniform block; \
+                 set it through a `UniformBuffer`", name, self.handle.id)))
+        }
+        Ok(Some((u.elements[i as usize], u, u.size - i)))
+    }
+
+    /// The index of uniform block `name`, or None if the program has no
+    /// such active block.
+    pub fn uniform_block_index(&self, name: &str) -> Result<Option<GLuint>, Error>
+    {
+        use std::ffi::CString;
+        let name_str = CString::new(name)?;
+        unsafe {
+            let index = gl::GetUniformBlockIndex(self.handle.id, name_str.as_ptr());
+            check_error!();
+            Ok(if index == gl::INVALID_INDEX { None } else { Some(index) })
+        }
+    }
+
+    /// The size in bytes GL gives uniform block `name`.
+    pub fn uniform_block_data_size(&self, name: &str) -> Result<Option<usize>, Error>
+    {
+        let index = match self.uniform_block_index(name)? {
+            Some(index) => index,
+            None        => return Ok(None),
+        };
+        let mut size = 0;
+        unsafe {
+            gl::GetActiveUniformBlockiv(self.handle.id, index, gl::UNIFORM_BLOCK_DATA_SIZE,
+                                        &mut size);
+            check_error!();
+        }
+        Ok(Some(size as usize))
+    }
+
+    /// Makes uniform block `name` read the buffer bound to uniform buffer
+    /// binding point `binding`, e.g. by `UniformBuffer::bind_base`.
+    pub fn bind_uniform_block(&self, name: &str, binding: GLuint) -> Result<(), Error>
+    {
+        let index = self.uniform_block_index(name)?.ok_or_else(|| Error::Validation(
+            format!("program {} has no uniform block `{}`", self.handle.id, name)))?;
+        unsafe {
+            gl::UniformBlockBinding(self.handle.id, index, binding);
+            check_error!();
+            Ok(())
+        }
+    }
+
+    /// The index of shader storage block `name`, or None if the program
+    /// has no such active block.
+    pub fn shader_storage_block_index(&self, name: &str) -> Result<Option<GLuint>, Error>
     {
         use std::ffi::CString;
-        let name_str = CString::new(name)
-            .map_err(|_| gl::INVALID_OPERATION)?;
+        let name_str = CString::new(name)?;
+        unsafe {
+            let index = gl::GetProgramResourceIndex(self.handle.id, gl::SHADER_STORAGE_BLOCK,
+                                                    name_str.as_ptr());
+            check_error!();
+            Ok(if index == gl::INVALID_INDEX { None } else { Some(index) })
+        }
+    }
+
+    /// The minimum size in bytes GL gives shader storage block `name`,
+    /// counting a runtime-sized array as one element.
+    pub fn shader_storage_block_data_size(&self, name: &str) -> Result<Option<usize>, Error>
+    {
+        let index = match self.shader_storage_block_index(name)? {
+            Some(index) => index,
+            None        => return Ok(None),
+        };
+        let mut size = 0;
+        unsafe {
+            gl::GetProgramResourceiv(self.handle.id, gl::SHADER_STORAGE_BLOCK, index,
+                                     1, &gl::BUFFER_DATA_SIZE, 1, ::std::ptr::null_mut(),
+                                     &mut size);
+            check_error!();
+        }
+        Ok(Some(size as usize))
+    }
+
+    /// Makes shader storage block `name` use the buffer bound to shader
+    /// storage buffer binding point `binding`.
+    pub fn bind_shader_storage_block(&self, name: &str, binding: GLuint) -> Result<(), Error>
+    {
+        let index = self.shader_storage_block_index(name)?.ok_or_else(|| Error::Validation(
+            format!("program {} has no shader storage block `{}`", self.handle.id, name)))?;
+        unsafe {
+            gl::ShaderStorageBlockBinding(self.handle.id, index, binding);
+            check_error!();
+            Ok(())
+        }
+    }
+
+    pub fn get_attribute_location(&self, name: &str) -> Result<GLint, Error>
+    {
+        use std::ffi::CString;
+        let name_str = CString::new(name)?;
         unsafe {
             let loc = gl::GetAttribLocation(self.handle.id, name_str.as_ptr());
             check_error!();
@@ -187,3 +455,121 @@ impl Program {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::Mock;
+
+    #[test]
+    fn shaders_and_program_are_deleted()
+    {
+        let mock = Mock::install();
+        let program = Program::new(ShaderSrc { vertex: "", fragment: "" }).unwrap();
+        let id = program.handle.id;
+        let created = mock.objects();
+        assert_eq!(created, vec![("glCreateShader", 1),
+                                 ("glCreateShader", 2),
+                                 ("glCreateProgram", id),
+                                 ("glDeleteShader", 2),
+                                 ("glDeleteShader", 1)]);
+        drop(program);
+        assert_eq!(mock.objects().last(), Some(&("glDeleteProgram", id)));
+    }
+
+    #[test]
+    fn compile_failure_reports_stage_and_log()
+    {
+        let mock = Mock::install();
+        mock.fail_compile(gl::FRAGMENT_SHADER, "0:1: syntax error");
+        let r = Program::new(ShaderSrc { vertex: "void main() {}", fragment: "oops" });
+        assert_eq!(r.err(), Some(Error::ShaderCompile {
+            stage: gl::FRAGMENT_SHADER,
+            log:   "0:1: syntax error".to_string(),
+        }));
+        assert!(mock.calls().contains(&gl_call!("glShaderSource", 2u32, 1, "oops")));
+        assert_eq!(mock.objects(), vec![("glCreateShader", 1),
+                                        ("glCreateShader", 2),
+                                        ("glDeleteShader", 2),
+                                        ("glDeleteShader", 1)]);
+    }
+
+    #[test]
+    fn link_failure_reports_log()
+    {
+        let mock = Mock::install();
+        mock.fail_link("undefined varying");
+        let r = Program::new(ShaderSrc { vertex: "", fragment: "" });
+        assert_eq!(r.err(), Some(Error::Link { log: "undefined varying".to_string() }));
+    }
+
+    #[test]
+    fn attrib_locations_are_bound_before_linking()
+    {
+        let mock = Mock::install();
+        let program = Program::new_with_attrib_locations(
+            ShaderSrc { vertex: "", fragment: "" }, &[("pos", 3)]).unwrap();
+        let names = mock.calls().iter().map(|c| c.name).collect::<Vec<_>>();
+        let bind = names.iter().position(|&n| n == "glBindAttribLocation").unwrap();
+        let link = names.iter().position(|&n| n == "glLinkProgram").unwrap();
+        assert!(bind < link);
+        assert_eq!(program.get_attribute_location("pos"), Ok(3));
+    }
+
+    #[test]
+    fn reflects_active_variables_at_link_time()
+    {
+        let mock = Mock::install();
+        mock.set_attribute_location("pos", 2);
+        mock.add_active_attribute("pos", gl::FLOAT_VEC3, 1);
+        mock.add_active_uniform("mvp", gl::FLOAT_MAT4, 1);
+        mock.add_active_uniform("lights[0]", gl::FLOAT_VEC4, 16);
+        let program = Program::new(ShaderSrc { vertex: "", fragment: "" }).unwrap();
+        assert_eq!(program.active_attributes(), &[ActiveVariable {
+            name: "pos".to_string(), gl_type: gl::FLOAT_VEC3, size: 1, location: 2,
+            elements: vec![],
+        }]);
+        assert_eq!(program.uniform("mvp").map(|u| u.gl_type), Some(gl::FLOAT_MAT4));
+        let lights = program.uniform("lights[0]").unwrap();
+        assert_eq!((lights.name.as_str(), lights.size), ("lights", 16));
+        assert_eq!(program.uniform("lights"), Some(lights));
+        assert_eq!(program.attribute("normal"), None);
+
+        mock.clear_calls();
+        program.uniform("mvp").unwrap();
+        assert_eq!(mock.calls(), vec![]);
+    }
+
+    #[test]
+    fn find_uniform_resolves_array_elements()
+    {
+        let mock = Mock::install();
+        mock.set_uniform_location("lights[0]", 4);
+        mock.set_uniform_location("lights[3]", 7);
+        mock.add_active_uniform("lights[0]", gl::FLOAT_VEC4, 16);
+        let program = Program::new(ShaderSrc { vertex: "", fragment: "" }).unwrap();
+        mock.clear_calls();
+        let (location, lights, left) = program.find_uniform("lights").unwrap().unwrap();
+        assert_eq!((location, lights.name.as_str(), left), (4, "lights", 16));
+        let (location, _, left) = program.find_uniform("lights[3]").unwrap().unwrap();
+        assert_eq!((location, left), (7, 13));
+        assert_eq!(program.find_uniform("lights[16]"), Ok(None));
+        assert_eq!(program.find_uniform("lights[-1]"), Ok(None));
+        assert_eq!(program.find_uniform("shadows[1]"), Ok(None));
+        assert_eq!(mock.calls(), vec![]);
+    }
+
+    #[test]
+    fn uniform_block_members_are_rejected()
+    {
+        let mock = Mock::install();
+        mock.set_uniform_location("Camera.view", -1);
+        mock.add_active_uniform("Camera.view", gl::FLOAT_MAT4, 1);
+        let program = Program::new(ShaderSrc { vertex: "", fragment: "" }).unwrap();
+        assert_eq!(program.uniform("Camera.view").map(|u| u.elements.len()), Some(0));
+        match program.find_uniform("Camera.view") {
+            Err(Error::Validation(msg)) => assert!(msg.contains("UniformBuffer"), "{}", msg),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+}
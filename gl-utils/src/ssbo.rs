@@ -0,0 +1,270 @@
+use gl;
+use gl::types::*;
+use buffer::{Buffer, BufferTarget, BufferUsage};
+use context::{Slot, changing};
+use error::Error;
+use shader::Program;
+use std140::round_up;
+use std430::{Std430, std430_stride};
+use std::fmt;
+use std::marker::PhantomData;
+
+/// A `Buffer` holding a shader storage block in std430 layout: the fields
+/// of a `T` as its first members, then a runtime-sized array of `E` as its
+/// last, unless `E` is `()`. The array starts where the last field of `T`
+/// ends, as it does in GLSL, not after `T`'s padding. Use `T = ()` for a
+/// block that is only an array.
+///
+/// Bind it like a `UniformBuffer`, with `bind_base` and
+/// `Program::bind_shader_storage_block`. As there, it is only written
+/// through its own methods, which keep the layout.
+pub struct ShaderStorageBuffer<T: Std430, E: Std430 = ()> {
+    buffer:  Buffer<u8>,
+    phantom: PhantomData<(T, E)>,
+}
+
+impl<T: Std430> ShaderStorageBuffer<T> {
+    pub fn new(value: &T, usage: BufferUsage) -> Result<Self, Error>
+    {
+        ShaderStorageBuffer::with_elements(value, &[], usage)
+    }
+}
+
+impl<T: Std430, E: Std430> ShaderStorageBuffer<T, E> {
+    const HAS_ARRAY: bool = E::STD430_SIZE > 0;
+    const ARRAY_OFFSET: usize = round_up(T::STD430_END, E::STD430_ALIGN);
+
+    /// Makes a buffer holding `value` followed by `elements`.
+    pub fn with_elements(value: &T, elements: &[E], usage: BufferUsage) -> Result<Self, Error>
+    {
+        let buffer = Buffer::new_with_data(BufferTarget::ShaderStorage,
+                                           &Self::to_bytes(value, elements), usage)?;
+        Ok(ShaderStorageBuffer { buffer, phantom: PhantomData })
+    }
+
+    // The bytes of the fixed members. With an array after them, the
+    // padding of `T` is left out, as the array may start inside it.
+    fn value_bytes(value: &T) -> Vec<u8>
+    {
+        let mut out = vec![0; if Self::HAS_ARRAY { T::STD430_END } else { T::STD430_SIZE }];
+        value.write_std430(&mut out);
+        out
+    }
+
+    fn element_bytes(elements: &[E]) -> Vec<u8>
+    {
+        let stride = std430_stride::<E>();
+        let mut out = vec![0; elements.len() * stride];
+        for (i, e) in elements.iter().enumerate() {
+            e.write_std430(&mut out[i * stride..]);
+        }
+        out
+    }
+
+    fn to_bytes(value: &T, elements: &[E]) -> Vec<u8>
+    {
+        let mut out = Self::value_bytes(value);
+        if Self::HAS_ARRAY {
+            out.resize(Self::ARRAY_OFFSET, 0);
+            out.extend(Self::element_bytes(elements));
+        }
+        out
+    }
+
+    /// Deletes the buffer, as `Buffer::delete` does: not until the last
+    /// clone is released.
+    pub fn delete(self) -> Result<(), Error>
+    {
+        self.buffer.delete()
+    }
+
+    pub fn id(&self) -> GLuint
+    {
+        self.buffer.id()
+    }
+
+    /// The size in bytes.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize
+    {
+        self.buffer.len()
+    }
+
+    /// The number of elements in the runtime-sized array.
+    pub fn element_count(&self) -> usize
+    {
+        if Self::HAS_ARRAY {
+            (self.buffer.len() - Self::ARRAY_OFFSET) / std430_stride::<E>()
+        } else {
+            0
+        }
+    }
+
+    /// Replaces the fixed members with `value`.
+    pub fn update(&self, value: &T) -> Result<(), Error>
+    {
+        self.buffer.update(0, &Self::value_bytes(value))
+    }
+
+    /// Overwrites the elements from element `start` on with `elements`,
+    /// which must fit within `element_count()`.
+    pub fn update_elements(&self, start: usize, elements: &[E]) -> Result<(), Error>
+    {
+        if start.checked_add(elements.len()).is_none_or(|end| end > self.element_count()) {
+            return Err(Error::Validation(format!(
+                "updating {} elements from element {} of a shader storage buffer of {}",
+                elements.len(), start, self.element_count())))
+        }
+        self.buffer.update(Self::ARRAY_OFFSET + start * std430_stride::<E>(),
+                           &Self::element_bytes(elements))
+    }
+
+    /// Replaces the whole block, reallocating it for the new number of
+    /// elements.
+    pub fn replace(&self, value: &T, elements: &[E]) -> Result<(), Error>
+    {
+        self.buffer.set_data(&Self::to_bytes(value, elements), self.buffer.usage())
+    }
+
+    /// Reads the fixed members back from GL.
+    pub fn read(&self) -> Result<T, Error>
+    {
+        Ok(T::read_std430(&self.buffer.read_back()?))
+    }
+
+    /// Reads the runtime-sized array back from GL.
+    pub fn read_elements(&self) -> Result<Vec<E>, Error>
+    {
+        let bytes = self.buffer.read_back()?;
+        Ok((0..self.element_count())
+           .map(|i| E::read_std430(&bytes[Self::ARRAY_OFFSET + i * std430_stride::<E>()..]))
+           .collect())
+    }
+
+    /// Binds the buffer to shader storage buffer binding point `binding`.
+    pub fn bind_base(&self, binding: GLuint) -> Result<(), Error>
+    {
+        unsafe {
+            // This binds the generic binding point too.
+            changing(Slot::Buffer(BufferTarget::ShaderStorage));
+            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, binding, self.buffer.id());
+            check_error!();
+            Ok(())
+        }
+    }
+
+    /// Checks that the shader storage block `name` of `program` has the
+    /// std430 size of `T` and `E`. GL sizes a runtime-sized array as one
+    /// element, and may pad the block to a vec4, as Mesa does.
+    pub fn check_block(&self, program: &Program, name: &str) -> Result<(), Error>
+    {
+        let expected = if Self::HAS_ARRAY {
+            Self::ARRAY_OFFSET + std430_stride::<E>()
+        } else {
+            T::STD430_SIZE
+        };
+        match program.shader_storage_block_data_size(name)? {
+            Some(size) if size >= expected && size <= round_up(expected, 16) => Ok(()),
+            Some(size) => Err(Error::Validation(format!(
+                "shader storage block `{}` of program {} is {} bytes, but its std430 value is {}",
+                name, program.id(), size, expected))),
+            None => Err(Error::Validation(format!(
+                "program {} has no shader storage block `{}`", program.id(), name))),
+        }
+    }
+}
+
+impl<T: Std430, E: Std430> Clone for ShaderStorageBuffer<T, E> {
+    fn clone(&self) -> Self
+    {
+        ShaderStorageBuffer { buffer: self.buffer.clone(), phantom: PhantomData }
+    }
+}
+
+impl<T: Std430, E: Std430> fmt::Debug for ShaderStorageBuffer<T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        f.debug_tuple("ShaderStorageBuffer").field(&self.buffer).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::Mock;
+    use shader::ShaderSrc;
+
+    struct Header {
+        origin: [GLfloat; 3],
+    }
+
+    implement_std430!(Header, origin);
+
+    #[derive(Debug,PartialEq)]
+    struct Particle {
+        velocity: [GLfloat; 2],
+        age:      GLfloat,
+    }
+
+    implement_std430!(Particle, velocity, age);
+
+    #[test]
+    fn array_starts_inside_the_padding()
+    {
+        let _mock = Mock::install();
+        let particles = [Particle { velocity: [1.0, 2.0], age: 3.0 },
+                         Particle { velocity: [4.0, 5.0], age: 6.0 }];
+        let ssbo = ShaderStorageBuffer::with_elements(&Header { origin: [0.0; 3] }, &particles,
+                                                      BufferUsage::DynamicDraw).unwrap();
+        // The origin ends at 12, and the particles are 8 aligned, 16 apart.
+        assert_eq!(ssbo.len(), 16 + 2 * 16);
+        assert_eq!(ssbo.element_count(), 2);
+        ssbo.update(&Header { origin: [1.0; 3] }).unwrap();
+        ssbo.update_elements(1, &particles[..1]).unwrap();
+        match ssbo.update_elements(2, &particles[..1]) {
+            Err(Error::Validation(_)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        match ssbo.update_elements(usize::MAX, &particles[..1]) {
+            Err(Error::Validation(_)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        ssbo.replace(&Header { origin: [0.0; 3] }, &particles[..1]).unwrap();
+        assert_eq!(ssbo.element_count(), 1);
+
+        let counters = ShaderStorageBuffer::new(&[7u32; 3], BufferUsage::DynamicCopy).unwrap();
+        assert_eq!((counters.len(), counters.element_count()), (12, 0));
+    }
+
+    #[test]
+    fn binds_blocks_by_name()
+    {
+        let mock = Mock::install();
+        mock.add_shader_storage_block("Particles", 16);
+        mock.add_shader_storage_block("Counters", 16);
+        mock.add_shader_storage_block("Cells", 48);
+        let program = Program::new(ShaderSrc { vertex: "", fragment: "" }).unwrap();
+        let ssbo = ShaderStorageBuffer::<(), Particle>::with_elements(
+            &(), &[], BufferUsage::DynamicDraw).unwrap();
+        ssbo.check_block(&program, "Particles").unwrap();
+        let counters = ShaderStorageBuffer::new(&0u32, BufferUsage::DynamicCopy).unwrap();
+        counters.check_block(&program, "Counters").unwrap();
+        match ssbo.check_block(&program, "Cells") {
+            Err(Error::Validation(msg)) => assert!(msg.contains("48 bytes"), "{}", msg),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        mock.clear_calls();
+        ssbo.bind_base(1).unwrap();
+        program.bind_shader_storage_block("Particles", 1).unwrap();
+        assert_eq!(mock.calls(), vec![
+            gl_call!("glBindBufferBase", gl::SHADER_STORAGE_BUFFER, 1u32, ssbo.id()),
+            gl_call!("glGetProgramResourceIndex", program.id(), gl::SHADER_STORAGE_BLOCK,
+                     "Particles"),
+            gl_call!("glShaderStorageBlockBinding", program.id(), 0u32, 1u32),
+        ]);
+        match program.bind_shader_storage_block("Grid", 0) {
+            Err(Error::Validation(msg)) => assert!(msg.contains("`Grid`"), "{}", msg),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,311 @@
+//! The std140 layout of uniform blocks. Scalars and vectors are written as
+//! in GLSL; `[S; 1]` to `[S; 4]` of a scalar `S` are vectors. Every other
+//! array is a GLSL array, so `[[f32; 4]; 4]` is a `mat4`, which std140
+//! lays out exactly like a `vec4[4]`, and a `float[8]` is `[[f32; 1]; 8]`.
+//! Structs get their layout from `implement_std140!` or
+//! `#[derive(Std140)]`.
+
+use gl::types::*;
+
+/// A value with a std140 layout.
+pub trait Std140 {
+    /// The base alignment in bytes.
+    const STD140_ALIGN: usize;
+    /// The size in bytes, including trailing padding for arrays and
+    /// structs.
+    const STD140_SIZE: usize;
+    /// Where the last field of a struct ends, before its trailing padding.
+    /// The size for other types.
+    const STD140_END: usize = Self::STD140_SIZE;
+    /// The byte offset of each field of a struct, empty for other types.
+    const STD140_OFFSETS: &'static [usize] = &[];
+
+    /// Writes the value into `out`, which is `STD140_SIZE` bytes long,
+    /// leaving padding untouched.
+    fn write_std140(&self, out: &mut [u8]);
+}
+
+/// The types that can be the elements of a std140 array: everything but
+/// the scalars, which would be taken for vectors. Use `[S; 1]` for them.
+pub trait Std140ArrayElement: Std140 {}
+
+/// Copies a value into a new buffer of its std140 size, with zeroed
+/// padding.
+pub fn to_std140_bytes<T: Std140 + ?Sized>(value: &T) -> Vec<u8>
+{
+    let mut out = vec![0; T::STD140_SIZE];
+    value.write_std140(&mut out);
+    out
+}
+
+#[doc(hidden)]
+pub const fn round_up(n: usize, align: usize) -> usize
+{
+    n.div_ceil(align) * align
+}
+
+macro_rules! std140_scalar {
+    ($($ty:ty),+) => ($(
+        impl Std140 for $ty {
+            const STD140_ALIGN: usize = ::std::mem::size_of::<$ty>();
+            const STD140_SIZE:  usize = ::std::mem::size_of::<$ty>();
+
+            fn write_std140(&self, out: &mut [u8])
+            {
+                out[..Self::STD140_SIZE].copy_from_slice(&self.to_ne_bytes());
+            }
+        }
+
+        std140_vector!($ty, 1, 1);
+        std140_vector!($ty, 2, 2);
+        std140_vector!($ty, 3, 4);
+        std140_vector!($ty, 4, 4);
+    )+)
+}
+
+// A vector of `$n` `$ty`s, aligned to `$align` of them.
+macro_rules! std140_vector {
+    ($ty:ty, $n:expr, $align:expr) => (
+        impl Std140 for [$ty; $n] {
+            const STD140_ALIGN: usize = $align * <$ty as Std140>::STD140_ALIGN;
+            const STD140_SIZE:  usize = $n * <$ty as Std140>::STD140_SIZE;
+
+            fn write_std140(&self, out: &mut [u8])
+            {
+                let size = <$ty as Std140>::STD140_SIZE;
+                for (v, out) in self.iter().zip(out.chunks_mut(size)) {
+                    v.write_std140(out);
+                }
+            }
+        }
+
+        impl Std140ArrayElement for [$ty; $n] {}
+    )
+}
+
+std140_scalar!(GLfloat, GLdouble, GLint, GLuint);
+
+// GLSL bools take four bytes in a block.
+impl Std140 for bool {
+    const STD140_ALIGN: usize = 4;
+    const STD140_SIZE:  usize = 4;
+
+    fn write_std140(&self, out: &mut [u8])
+    {
+        (*self as GLuint).write_std140(out);
+    }
+}
+
+std140_vector!(bool, 1, 1);
+std140_vector!(bool, 2, 2);
+std140_vector!(bool, 3, 4);
+std140_vector!(bool, 4, 4);
+
+// Array elements, and so matrix columns, are aligned to at least a vec4.
+const fn std140_array_align(element_align: usize) -> usize
+{
+    if element_align > 16 { element_align } else { 16 }
+}
+
+impl<T: Std140ArrayElement, const N: usize> Std140 for [T; N] {
+    const STD140_ALIGN: usize = std140_array_align(T::STD140_ALIGN);
+    const STD140_SIZE:  usize = N * round_up(T::STD140_SIZE, Self::STD140_ALIGN);
+
+    fn write_std140(&self, out: &mut [u8])
+    {
+        let stride = round_up(T::STD140_SIZE, Self::STD140_ALIGN);
+        for (v, out) in self.iter().zip(out.chunks_mut(stride)) {
+            v.write_std140(&mut out[..T::STD140_SIZE]);
+        }
+    }
+}
+
+impl<T: Std140ArrayElement, const N: usize> Std140ArrayElement for [T; N] {}
+
+/// The offset of each field of a struct, given the alignment and size of
+/// each, in order. Shared with std430, which differs only in alignments.
+#[doc(hidden)]
+pub const fn field_offsets<const N: usize>(fields: [(usize, usize); N]) -> [usize; N]
+{
+    let mut offsets = [0; N];
+    let mut end = 0;
+    let mut i = 0;
+    while i < N {
+        offsets[i] = round_up(end, fields[i].0);
+        end = offsets[i] + fields[i].1;
+        i += 1;
+    }
+    offsets
+}
+
+/// The alignment of a struct with the given fields, at least `min_align`:
+/// 16 for std140, 1 for std430.
+#[doc(hidden)]
+pub const fn struct_align(fields: &[(usize, usize)], min_align: usize) -> usize
+{
+    let mut align = min_align;
+    let mut i = 0;
+    while i < fields.len() {
+        if fields[i].0 > align {
+            align = fields[i].0;
+        }
+        i += 1;
+    }
+    align
+}
+
+/// The end of the last field of a struct with the given fields and field
+/// offsets.
+#[doc(hidden)]
+pub const fn struct_end(fields: &[(usize, usize)], offsets: &[usize]) -> usize
+{
+    match fields.len() {
+        0 => 0,
+        n => offsets[n - 1] + fields[n - 1].1,
+    }
+}
+
+// `implement_std140!` names a field's type by passing a projection to this.
+#[doc(hidden)]
+pub const fn field_std140<S, F: Std140>(_: fn(&S) -> &F) -> (usize, usize)
+{
+    (F::STD140_ALIGN, F::STD140_SIZE)
+}
+
+/// Implements `Std140` for a struct, laying its fields out in order. The
+/// full form can also assert at compile time that a field lands at the
+/// offset the GLSL block expects:
+///
+/// ```
+/// # #[macro_use] extern crate gl_utils;
+/// # type GLfloat = f32;
+/// struct Camera {
+///     view:       [[GLfloat; 4]; 4],
+///     projection: [[GLfloat; 4]; 4],
+///     position:   [GLfloat; 3],
+/// }
+///
+/// struct Light {
+///     color:    [GLfloat; 3],
+///     position: [GLfloat; 3],
+/// }
+///
+/// implement_std140!(Camera, view, projection, position);
+/// implement_std140!(Light { color: auto; position: 16; });
+/// # fn main() {}
+/// ```
+///
+/// This is what `#[derive(Std140)]` expands to.
+#[macro_export]
+macro_rules! implement_std140 {
+    ($struct_name:ident, $($field_name:ident),+ $(,)*) => (
+        $crate::implement_std140!($struct_name { $($field_name: auto;)+ });
+    );
+
+    ($struct_name:ident { $($field_name:ident : $offset:tt;)+ }) => (
+        impl $crate::std140::Std140 for $struct_name {
+            const STD140_ALIGN: usize = $crate::std140::struct_align(&[$(
+                $crate::std140::field_std140(|s: &$struct_name| &s.$field_name)
+            ),+], 16);
+            const STD140_SIZE: usize =
+                $crate::std140::round_up(Self::STD140_END, Self::STD140_ALIGN);
+            const STD140_END: usize = $crate::std140::struct_end(
+                &[$( $crate::std140::field_std140(|s: &$struct_name| &s.$field_name) ),+],
+                Self::STD140_OFFSETS);
+            const STD140_OFFSETS: &'static [usize] = &$crate::std140::field_offsets([$(
+                $crate::std140::field_std140(|s: &$struct_name| &s.$field_name)
+            ),+]);
+
+            fn write_std140(&self, out: &mut [u8])
+            {
+                let mut offsets = Self::STD140_OFFSETS.iter();
+                $(
+                    let offset = *offsets.next().unwrap();
+                    let (_, size) =
+                        $crate::std140::field_std140(|s: &$struct_name| &s.$field_name);
+                    $crate::std140::Std140::write_std140(&self.$field_name,
+                                                         &mut out[offset..offset + size]);
+                )+
+            }
+        }
+
+        impl $crate::std140::Std140ArrayElement for $struct_name {}
+
+        const _: () = {
+            let mut _i = 0;
+            $(
+                $crate::std140_offset_check!($struct_name, $field_name, _i, $offset);
+                _i += 1;
+            )+
+        };
+    );
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! std140_offset_check {
+    ($struct_name:ident, $field_name:ident, $i:expr, auto) => ();
+    ($struct_name:ident, $field_name:ident, $i:expr, $offset:expr) => (
+        assert!(<$struct_name as $crate::std140::Std140>::STD140_OFFSETS[$i] == $offset,
+                concat!("std140 offset of `", stringify!($struct_name), ".",
+                        stringify!($field_name), "` is not ", stringify!($offset)));
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Light {
+        color:     [GLfloat; 3],
+        intensity: GLfloat,
+        position:  [GLfloat; 3],
+    }
+
+    implement_std140!(Light { color: 0; intensity: 12; position: 16; });
+
+    struct Scene {
+        enabled: bool,
+        model:   [[GLfloat; 3]; 3],
+        lights:  [Light; 2],
+        weights: [[GLfloat; 1]; 3],
+        time:    GLdouble,
+    }
+
+    implement_std140!(Scene, enabled, model, lights, weights, time);
+
+    #[test]
+    fn vectors_and_matrices()
+    {
+        assert_eq!(<[GLfloat; 3]>::STD140_ALIGN, 16);
+        assert_eq!(<[GLfloat; 3]>::STD140_SIZE, 12);
+        assert_eq!(<[GLdouble; 2]>::STD140_ALIGN, 16);
+        assert_eq!(<[[GLfloat; 2]; 2]>::STD140_SIZE, 32);
+        assert_eq!(<[[GLfloat; 3]; 3]>::STD140_SIZE, 48);
+        assert_eq!(<[[GLdouble; 3]; 2]>::STD140_SIZE, 64);
+        assert_eq!(<[[GLfloat; 1]; 4]>::STD140_SIZE, 64);
+    }
+
+    #[test]
+    fn structs_pad_to_a_vec4()
+    {
+        assert_eq!(Light::STD140_OFFSETS, &[0, 12, 16]);
+        assert_eq!((Light::STD140_ALIGN, Light::STD140_END, Light::STD140_SIZE), (16, 28, 32));
+        assert_eq!(Scene::STD140_OFFSETS, &[0, 16, 64, 128, 176]);
+        assert_eq!(Scene::STD140_SIZE, 192);
+    }
+
+    #[test]
+    fn writes_values_at_their_offsets()
+    {
+        let light = Light { color: [1.0, 0.5, 0.25], intensity: 2.0, position: [3.0; 3] };
+        let bytes = to_std140_bytes(&[[7u32; 1]; 2]);
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(&bytes[16..20], &7u32.to_ne_bytes());
+        let bytes = to_std140_bytes(&light);
+        let float = |o: usize| GLfloat::from_ne_bytes([bytes[o], bytes[o + 1],
+                                                        bytes[o + 2], bytes[o + 3]]);
+        assert_eq!((float(4), float(12), float(24)), (0.5, 2.0, 3.0));
+        assert_eq!(&bytes[28..], &[0; 4]);
+    }
+}
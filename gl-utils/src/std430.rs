@@ -0,0 +1,297 @@
+//! The std430 layout of shader storage blocks. It is std140 without the
+//! rounding of array strides and struct alignments up to a vec4, so a
+//! `float[8]`, `[[f32; 1]; 8]`, is tightly packed and a `vec2[4]` takes 32
+//! bytes. Rust types map to GLSL types as in `std140`. Values can also be
+//! read back, for data a shader has written.
+
+use gl::types::*;
+use std140::round_up;
+
+/// A value with a std430 layout.
+pub trait Std430 {
+    /// The base alignment in bytes.
+    const STD430_ALIGN: usize;
+    /// The size in bytes, including trailing padding for arrays and
+    /// structs.
+    const STD430_SIZE: usize;
+    /// Where the last field of a struct ends, which is where a block
+    /// member after the struct's fields may start. The size for other
+    /// types.
+    const STD430_END: usize = Self::STD430_SIZE;
+    /// The byte offset of each field of a struct, empty for other types.
+    const STD430_OFFSETS: &'static [usize] = &[];
+
+    /// Writes the value into `out`, which is at least `STD430_END` bytes
+    /// long, leaving padding untouched.
+    fn write_std430(&self, out: &mut [u8]);
+
+    /// Reads a value written by GL from the start of `bytes`.
+    fn read_std430(bytes: &[u8]) -> Self;
+}
+
+/// The types that can be the elements of a std430 array, as for
+/// `Std140ArrayElement`.
+pub trait Std430ArrayElement: Std430 {}
+
+/// Copies a value into a new buffer of its std430 size, with zeroed
+/// padding.
+pub fn to_std430_bytes<T: Std430>(value: &T) -> Vec<u8>
+{
+    let mut out = vec![0; T::STD430_SIZE];
+    value.write_std430(&mut out);
+    out
+}
+
+macro_rules! std430_scalar {
+    ($($ty:ty),+) => ($(
+        impl Std430 for $ty {
+            const STD430_ALIGN: usize = ::std::mem::size_of::<$ty>();
+            const STD430_SIZE:  usize = ::std::mem::size_of::<$ty>();
+
+            fn write_std430(&self, out: &mut [u8])
+            {
+                out[..Self::STD430_SIZE].copy_from_slice(&self.to_ne_bytes());
+            }
+
+            fn read_std430(bytes: &[u8]) -> Self
+            {
+                let mut ne_bytes = [0; ::std::mem::size_of::<$ty>()];
+                ne_bytes.copy_from_slice(&bytes[..Self::STD430_SIZE]);
+                <$ty>::from_ne_bytes(ne_bytes)
+            }
+        }
+
+        std430_vector!($ty, 1, 1);
+        std430_vector!($ty, 2, 2);
+        std430_vector!($ty, 3, 4);
+        std430_vector!($ty, 4, 4);
+    )+)
+}
+
+// A vector of `$n` `$ty`s, aligned to `$align` of them.
+macro_rules! std430_vector {
+    ($ty:ty, $n:expr, $align:expr) => (
+        impl Std430 for [$ty; $n] {
+            const STD430_ALIGN: usize = $align * <$ty as Std430>::STD430_ALIGN;
+            const STD430_SIZE:  usize = $n * <$ty as Std430>::STD430_SIZE;
+
+            fn write_std430(&self, out: &mut [u8])
+            {
+                let size = <$ty as Std430>::STD430_SIZE;
+                for (v, out) in self.iter().zip(out.chunks_mut(size)) {
+                    v.write_std430(out);
+                }
+            }
+
+            fn read_std430(bytes: &[u8]) -> Self
+            {
+                let size = <$ty as Std430>::STD430_SIZE;
+                ::std::array::from_fn(|i| <$ty as Std430>::read_std430(&bytes[i * size..]))
+            }
+        }
+
+        impl Std430ArrayElement for [$ty; $n] {}
+    )
+}
+
+std430_scalar!(GLfloat, GLdouble, GLint, GLuint);
+
+// GLSL bools take four bytes in a block.
+impl Std430 for bool {
+    const STD430_ALIGN: usize = 4;
+    const STD430_SIZE:  usize = 4;
+
+    fn write_std430(&self, out: &mut [u8])
+    {
+        (*self as GLuint).write_std430(out);
+    }
+
+    fn read_std430(bytes: &[u8]) -> Self
+    {
+        GLuint::read_std430(bytes) != 0
+    }
+}
+
+std430_vector!(bool, 1, 1);
+std430_vector!(bool, 2, 2);
+std430_vector!(bool, 3, 4);
+std430_vector!(bool, 4, 4);
+
+// Nothing: the fixed members of a block that is only a runtime-sized
+// array, or the array of a block without one.
+impl Std430 for () {
+    const STD430_ALIGN: usize = 1;
+    const STD430_SIZE:  usize = 0;
+
+    fn write_std430(&self, _: &mut [u8])
+    {
+    }
+
+    fn read_std430(_: &[u8]) -> Self
+    {
+    }
+}
+
+/// The distance between the elements of a std430 array of `T`.
+pub const fn std430_stride<T: Std430>() -> usize
+{
+    round_up(T::STD430_SIZE, T::STD430_ALIGN)
+}
+
+impl<T: Std430ArrayElement, const N: usize> Std430 for [T; N] {
+    const STD430_ALIGN: usize = T::STD430_ALIGN;
+    const STD430_SIZE:  usize = N * std430_stride::<T>();
+
+    fn write_std430(&self, out: &mut [u8])
+    {
+        for (i, v) in self.iter().enumerate() {
+            v.write_std430(&mut out[i * std430_stride::<T>()..]);
+        }
+    }
+
+    fn read_std430(bytes: &[u8]) -> Self
+    {
+        ::std::array::from_fn(|i| T::read_std430(&bytes[i * std430_stride::<T>()..]))
+    }
+}
+
+impl<T: Std430ArrayElement, const N: usize> Std430ArrayElement for [T; N] {}
+
+// `implement_std430!` names a field's type by passing a projection to this.
+#[doc(hidden)]
+pub const fn field_std430<S, F: Std430>(_: fn(&S) -> &F) -> (usize, usize)
+{
+    (F::STD430_ALIGN, F::STD430_SIZE)
+}
+
+/// Implements `Std430` for a struct, as `implement_std140!` does `Std140`.
+///
+/// ```
+/// # #[macro_use] extern crate gl_utils;
+/// # type GLfloat = f32;
+/// # type GLuint = u32;
+/// struct Particle {
+///     position: [GLfloat; 3],
+///     velocity: [GLfloat; 3],
+/// }
+///
+/// struct Cell {
+///     bounds: [[GLfloat; 2]; 3],
+///     count:  GLuint,
+/// }
+///
+/// implement_std430!(Particle, position, velocity);
+/// implement_std430!(Cell { bounds: auto; count: 24; });
+/// # fn main() {}
+/// ```
+///
+/// This is what `#[derive(Std430)]` expands to.
+#[macro_export]
+macro_rules! implement_std430 {
+    ($struct_name:ident, $($field_name:ident),+ $(,)*) => (
+        $crate::implement_std430!($struct_name { $($field_name: auto;)+ });
+    );
+
+    ($struct_name:ident { $($field_name:ident : $offset:tt;)+ }) => (
+        impl $crate::std430::Std430 for $struct_name {
+            const STD430_ALIGN: usize = $crate::std140::struct_align(&[$(
+                $crate::std430::field_std430(|s: &$struct_name| &s.$field_name)
+            ),+], 1);
+            const STD430_SIZE: usize =
+                $crate::std140::round_up(Self::STD430_END, Self::STD430_ALIGN);
+            const STD430_END: usize = $crate::std140::struct_end(
+                &[$( $crate::std430::field_std430(|s: &$struct_name| &s.$field_name) ),+],
+                Self::STD430_OFFSETS);
+            const STD430_OFFSETS: &'static [usize] = &$crate::std140::field_offsets([$(
+                $crate::std430::field_std430(|s: &$struct_name| &s.$field_name)
+            ),+]);
+
+            fn write_std430(&self, out: &mut [u8])
+            {
+                let mut offsets = Self::STD430_OFFSETS.iter();
+                $(
+                    let offset = *offsets.next().unwrap();
+                    let (_, size) =
+                        $crate::std430::field_std430(|s: &$struct_name| &s.$field_name);
+                    $crate::std430::Std430::write_std430(&self.$field_name,
+                                                         &mut out[offset..offset + size]);
+                )+
+            }
+
+            fn read_std430(bytes: &[u8]) -> Self
+            {
+                let mut offsets = Self::STD430_OFFSETS.iter();
+                $struct_name { $(
+                    $field_name: $crate::std430::Std430::read_std430(
+                        &bytes[*offsets.next().unwrap()..]),
+                )+ }
+            }
+        }
+
+        impl $crate::std430::Std430ArrayElement for $struct_name {}
+
+        const _: () = {
+            let mut _i = 0;
+            $(
+                $crate::std430_offset_check!($struct_name, $field_name, _i, $offset);
+                _i += 1;
+            )+
+        };
+    );
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! std430_offset_check {
+    ($struct_name:ident, $field_name:ident, $i:expr, auto) => ();
+    ($struct_name:ident, $field_name:ident, $i:expr, $offset:expr) => (
+        assert!(<$struct_name as $crate::std430::Std430>::STD430_OFFSETS[$i] == $offset,
+                concat!("std430 offset of `", stringify!($struct_name), ".",
+                        stringify!($field_name), "` is not ", stringify!($offset)));
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug,PartialEq)]
+    struct Particle {
+        position: [GLfloat; 3],
+        age:      GLfloat,
+        velocity: [GLfloat; 2],
+    }
+
+    implement_std430!(Particle { position: 0; age: 12; velocity: 16; });
+
+    struct Cell {
+        alive:   bool,
+        weights: [[GLfloat; 1]; 3],
+        bounds:  [[GLfloat; 2]; 2],
+    }
+
+    implement_std430!(Cell, alive, weights, bounds);
+
+    #[test]
+    fn arrays_and_structs_are_not_padded_to_a_vec4()
+    {
+        assert_eq!(<[[GLfloat; 1]; 8]>::STD430_SIZE, 32);
+        assert_eq!(<[[GLfloat; 2]; 4]>::STD430_SIZE, 32);
+        assert_eq!(<[[GLfloat; 3]; 3]>::STD430_SIZE, 48);
+        assert_eq!((Particle::STD430_ALIGN, Particle::STD430_SIZE), (16, 32));
+        assert_eq!((Particle::STD430_END, <[Particle; 2]>::STD430_SIZE), (24, 64));
+        assert_eq!(Cell::STD430_OFFSETS, &[0, 4, 16]);
+        assert_eq!((Cell::STD430_ALIGN, Cell::STD430_END, Cell::STD430_SIZE), (8, 32, 32));
+    }
+
+    #[test]
+    fn values_read_back_as_written()
+    {
+        let particles = [Particle { position: [1.0, 2.0, 3.0], age: 4.0, velocity: [5.0; 2] },
+                         Particle { position: [6.0; 3], age: 7.0, velocity: [8.0, 9.0] }];
+        let bytes = to_std430_bytes(&particles);
+        assert_eq!(&bytes[44..48], &7.0f32.to_ne_bytes());
+        assert_eq!(<[Particle; 2]>::read_std430(&bytes), particles);
+        assert_eq!(<[bool; 2]>::read_std430(&to_std430_bytes(&[false, true])), [false, true]);
+    }
+}
@@ -1,6 +1,7 @@
 use gl;
 use gl::types::*;
-use util::*;
+use context::{Slot, changing};
+use error::{Error, clear_pending_errors, log_pending_errors, report_drop_error};
 use std::rc::Rc;
 
 use std::marker::PhantomData;
@@ -12,7 +13,7 @@ pub struct TextureHandle<'a> {
 }
 
 impl<'a> TextureHandle<'a> {
-    fn new() -> Result<Self, GLenum>
+    fn new() -> Result<Self, Error>
     {
         unsafe {
             let mut th = TextureHandle { id: 0, phantom: PhantomData };
@@ -21,23 +22,43 @@ impl<'a> TextureHandle<'a> {
             Ok(th)
         }
     }
+
+    fn delete(&mut self) -> Result<(), Error>
+    {
+        if self.id == 0 {
+            return Ok(())
+        }
+        unsafe {
+            clear_pending_errors();
+            gl::DeleteTextures(1, &self.id);
+            self.id = 0;
+            check_error!();
+            Ok(())
+        }
+    }
 }
 
 impl<'a> Drop for TextureHandle<'a> {
     fn drop(&mut self)
     {
-        unsafe {
-            gl::DeleteTextures(1, &mut self.id);
-            // We should use check_error here, but the type of drop
-            // won't allow it. We have to panic instead.
-            let err = gl::GetError();
-            if err != gl::NO_ERROR {
-                panic!("DeleteTextures returned: {}", gl_error_str(err));
-            }
+        log_pending_errors();
+        if let Err(e) = self.delete() {
+            report_drop_error(e);
         }
     }
 }
 
+/// Selects texture unit `unit`, i.e. `gl::TEXTURE0 + unit`.
+pub(crate) fn active_texture(unit: GLuint) -> Result<(), Error>
+{
+    unsafe {
+        changing(Slot::ActiveTexture);
+        gl::ActiveTexture(gl::TEXTURE0 + unit);
+        check_error!();
+        Ok(())
+    }
+}
+
 #[derive(Debug,Clone)]
 pub struct TextureRef<'a> {
     handle: Rc<TextureHandle<'a>>,
@@ -45,43 +66,68 @@ pub struct TextureRef<'a> {
 }
 
 impl<'a> TextureRef<'a> {
-    pub fn new(target: GLenum) -> Result<Self, GLenum>
+    pub fn new(target: GLenum) -> Result<Self, Error>
     {
         let h = TextureHandle::new()?;
-        Ok(TextureRef { handle: Rc::new(h), target: target })
+        Ok(TextureRef { handle: Rc::new(h), target })
+    }
+
+    /// Deletes the texture, returning any error instead of handing it to
+    /// the drop error policy. Clones share the texture, so while others are
+    /// alive this only releases this one and the last clone deletes it.
+    pub fn delete(self) -> Result<(), Error>
+    {
+        match Rc::try_unwrap(self.handle) {
+            Ok(mut h) => h.delete(),
+            Err(_)    => Ok(()),
+        }
+    }
+
+    pub fn id(&self) -> GLuint
+    {
+        self.handle.id
+    }
+
+    pub fn target(&self) -> GLenum
+    {
+        self.target
     }
 
-    pub fn bind(&self) -> Result<(), GLenum>
+    pub fn bind(&self) -> Result<(), Error>
     {
         unsafe {
+            changing(Slot::Textures);
             gl::BindTexture(self.target, self.handle.id);
             check_error!();
             Ok(())
         }
     }
 
-    pub fn unbind(&self) -> Result<(), GLenum>
+    pub fn unbind(&self) -> Result<(), Error>
     {
         unsafe {
+            changing(Slot::Textures);
             gl::BindTexture(self.target, 0);
             check_error!();
             Ok(())
         }
     }
 
-    pub fn unbind_target(target: GLenum) -> Result<(), GLenum>
+    pub fn unbind_target(target: GLenum) -> Result<(), Error>
     {
         unsafe {
+            changing(Slot::Textures);
             gl::BindTexture(target, 0);
             check_error!();
             Ok(())
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn image_2d<T>(&self, level: GLint, internalformat: GLint,
                        width: GLsizei, height: GLsizei, border: GLint,
                        format: GLenum, type_: GLenum, pixels: *const T)
-                       -> Result<(),GLenum>
+                       -> Result<(),Error>
     {
         unsafe {
             gl::TexImage2D(self.target, level, internalformat, width, height,
@@ -91,13 +137,13 @@ impl<'a> TextureRef<'a> {
         }
     }
 
-    pub fn parameter(&self, param: TexParameter) -> Result<(), GLenum>
+    pub fn parameter(&self, param: TexParameter) -> Result<(), Error>
     {
         let (pname, pvalue) = param.to_gl();
         self.tex_parameter_i(pname, pvalue)
     }
 
-    pub fn tex_parameter_i(&self, pname: GLenum, param: GLint) -> Result<(),GLenum>
+    pub fn tex_parameter_i(&self, pname: GLenum, param: GLint) -> Result<(),Error>
     {
         unsafe {
             gl::TexParameteri(self.target, pname, param);
@@ -106,7 +152,7 @@ impl<'a> TextureRef<'a> {
         }
     }
 
-    pub fn generate_mipmap(&self) -> Result<(),GLenum>
+    pub fn generate_mipmap(&self) -> Result<(),Error>
     {
         unsafe {
             gl::GenerateMipmap(self.target);
@@ -149,35 +195,50 @@ pub enum TexWrap {
 }
 
 impl TexParameter {
-    fn to_gl(&self) -> (GLenum, GLint)
-    {
-        match *self {
-            TexParameter::MinFilter(ref f) => (gl::TEXTURE_MIN_FILTER, f.to_gl()),
-            TexParameter::MagFilter(ref f) => (gl::TEXTURE_MAG_FILTER, f.to_gl()),
-            TexParameter::WrapS    (ref w) => (gl::TEXTURE_WRAP_S, w.to_gl()),
-            TexParameter::WrapT    (ref w) => (gl::TEXTURE_WRAP_T, w.to_gl()),
-            TexParameter::WrapR    (ref w) => (gl::TEXTURE_WRAP_R, w.to_gl()),
+    fn to_gl(self) -> (GLenum, GLint)
+    {
+        match self {
+            TexParameter::MinFilter(f) => (gl::TEXTURE_MIN_FILTER, f.to_gl()),
+            TexParameter::MagFilter(f) => (gl::TEXTURE_MAG_FILTER, f.to_gl()),
+            TexParameter::WrapS    (w) => (gl::TEXTURE_WRAP_S, w.to_gl()),
+            TexParameter::WrapT    (w) => (gl::TEXTURE_WRAP_T, w.to_gl()),
+            TexParameter::WrapR    (w) => (gl::TEXTURE_WRAP_R, w.to_gl()),
         }
     }
 }
 
 impl TexMinFilter {
-    fn to_gl(&self) -> GLint
+    fn to_gl(self) -> GLint
     {
-        *self as GLint
+        self as GLint
     }
 }
 
 impl TexMagFilter {
-    fn to_gl(&self) -> GLint
+    fn to_gl(self) -> GLint
     {
-        *self as GLint
+        self as GLint
     }
 }
 
 impl TexWrap {
-    fn to_gl(&self) -> GLint
+    fn to_gl(self) -> GLint
+    {
+        self as GLint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::Mock;
+
+    #[test]
+    fn drop_deletes_texture()
     {
-        *self as GLint
+        let mock = Mock::install();
+        let id = TextureRef::new(gl::TEXTURE_2D).unwrap().handle.id;
+        assert_eq!(mock.objects(),
+                   vec![("glGenTextures", id), ("glDeleteTextures", id)]);
     }
 }
@@ -0,0 +1,167 @@
+use gl;
+use gl::types::*;
+use buffer::{Buffer, BufferTarget, BufferUsage};
+use context::{Slot, changing};
+use error::Error;
+use shader::Program;
+use std140::{Std140, round_up, to_std140_bytes};
+use std::fmt;
+use std::marker::PhantomData;
+
+/// A `Buffer` holding one `T` in std140 layout, for a uniform block. Bind
+/// it to a binding point with `bind_base`, and point the blocks of any
+/// number of programs at that binding with `Program::bind_uniform_block`;
+/// they all see each `update`, the only way to write it, so its contents
+/// always follow the std140 layout.
+pub struct UniformBuffer<T: Std140> {
+    buffer:  Buffer<u8>,
+    phantom: PhantomData<T>,
+}
+
+impl<T: Std140> UniformBuffer<T> {
+    pub fn new(value: &T, usage: BufferUsage) -> Result<Self, Error>
+    {
+        let buffer = Buffer::new_with_data(BufferTarget::Uniform, &to_std140_bytes(value),
+                                           usage)?;
+        Ok(UniformBuffer { buffer, phantom: PhantomData })
+    }
+
+    /// Deletes the buffer, as `Buffer::delete` does: not until the last
+    /// clone is released.
+    pub fn delete(self) -> Result<(), Error>
+    {
+        self.buffer.delete()
+    }
+
+    pub fn id(&self) -> GLuint
+    {
+        self.buffer.id()
+    }
+
+    /// The size in bytes, the std140 size of `T`.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize
+    {
+        self.buffer.len()
+    }
+
+    /// Replaces the contents with `value`.
+    pub fn update(&self, value: &T) -> Result<(), Error>
+    {
+        self.buffer.update(0, &to_std140_bytes(value))
+    }
+
+    /// Binds the buffer to uniform buffer binding point `binding`.
+    pub fn bind_base(&self, binding: GLuint) -> Result<(), Error>
+    {
+        unsafe {
+            // This binds the generic binding point too.
+            changing(Slot::Buffer(BufferTarget::Uniform));
+            gl::BindBufferBase(gl::UNIFORM_BUFFER, binding, self.buffer.id());
+            check_error!();
+            Ok(())
+        }
+    }
+
+    /// Checks that the uniform block `name` of `program` is the std140
+    /// size of `T`, which catches most layouts that have drifted apart.
+    /// GL may or may not pad a block whose last member is, say, a vec3 up
+    /// to a vec4, so any size from the end of that member on is accepted.
+    pub fn check_block(&self, program: &Program, name: &str) -> Result<(), Error>
+    {
+        let expected = T::STD140_END;
+        match program.uniform_block_data_size(name)? {
+            Some(size) if size >= expected && size <= round_up(expected, 16) => Ok(()),
+            Some(size) => Err(Error::Validation(format!(
+                "uniform block `{}` of program {} is {} bytes, but its std140 value is {}",
+                name, program.id(), size, expected))),
+            None => Err(Error::Validation(format!(
+                "program {} has no uniform block `{}`", program.id(), name))),
+        }
+    }
+}
+
+impl<T: Std140> Clone for UniformBuffer<T> {
+    fn clone(&self) -> Self
+    {
+        UniformBuffer { buffer: self.buffer.clone(), phantom: PhantomData }
+    }
+}
+
+impl<T: Std140> fmt::Debug for UniformBuffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        f.debug_tuple("UniformBuffer").field(&self.buffer).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::{Arg, Mock};
+    use shader::ShaderSrc;
+
+    struct Camera {
+        view_projection: [[GLfloat; 4]; 4],
+        position:        [GLfloat; 3],
+    }
+
+    implement_std140!(Camera, view_projection, position);
+
+    #[test]
+    fn shares_one_binding_between_programs()
+    {
+        let mock = Mock::install();
+        mock.add_uniform_block("Camera", 80);
+        let a = Program::new(ShaderSrc { vertex: "", fragment: "" }).unwrap();
+        let b = Program::new(ShaderSrc { vertex: "", fragment: "" }).unwrap();
+        let camera = Camera { view_projection: [[0.0; 4]; 4], position: [1.0, 2.0, 3.0] };
+        let ubo = UniformBuffer::new(&camera, BufferUsage::DynamicDraw).unwrap();
+        assert_eq!(ubo.len(), 80);
+        ubo.check_block(&a, "Camera").unwrap();
+        mock.clear_calls();
+        ubo.bind_base(2).unwrap();
+        a.bind_uniform_block("Camera", 2).unwrap();
+        b.bind_uniform_block("Camera", 2).unwrap();
+        assert_eq!(mock.calls(), vec![
+            gl_call!("glBindBufferBase", gl::UNIFORM_BUFFER, 2u32, ubo.id()),
+            gl_call!("glGetUniformBlockIndex", a.id(), "Camera"),
+            gl_call!("glUniformBlockBinding", a.id(), 0u32, 2u32),
+            gl_call!("glGetUniformBlockIndex", b.id(), "Camera"),
+            gl_call!("glUniformBlockBinding", b.id(), 0u32, 2u32),
+        ]);
+        mock.clear_calls();
+        ubo.update(&Camera { position: [4.0; 3], ..camera }).unwrap();
+        let data = match mock.calls()[1].args[3] {
+            Arg::Data(ref data) => data.clone(),
+            ref arg => panic!("unexpected argument: {:?}", arg),
+        };
+        assert_eq!(&data[64..68], &4.0f32.to_ne_bytes());
+    }
+
+    #[test]
+    fn check_block_compares_sizes()
+    {
+        let mock = Mock::install();
+        mock.add_uniform_block("Camera", 96);
+        mock.add_uniform_block("Unpadded", 76);
+        mock.add_uniform_block("Short", 72);
+        let program = Program::new(ShaderSrc { vertex: "", fragment: "" }).unwrap();
+        let camera = Camera { view_projection: [[0.0; 4]; 4], position: [0.0; 3] };
+        let ubo = UniformBuffer::new(&camera, BufferUsage::StaticDraw).unwrap();
+        match ubo.check_block(&program, "Camera") {
+            Err(Error::Validation(msg)) => assert!(msg.contains("96 bytes"), "{}", msg),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        // The mat4 and vec3 end at 76 bytes, which GL need not pad to 80.
+        assert_eq!(ubo.check_block(&program, "Unpadded"), Ok(()));
+        match ubo.check_block(&program, "Short") {
+            Err(Error::Validation(msg)) => assert!(msg.contains("72 bytes"), "{}", msg),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        match program.bind_uniform_block("Lights", 0) {
+            Err(Error::Validation(msg)) => assert!(msg.contains("`Lights`"), "{}", msg),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+}
@@ -1,27 +1,482 @@
 use texture;
 
+use gl;
 use gl::types::*;
+use error::Error;
+use shader::Program;
+use util::*;
+use std::cell::Cell;
+use std::fmt;
+use std::marker::PhantomData;
 
 pub struct Uniform<'a> {
     pub name:  &'static str,
     pub value: UniformEnum<'a>,
 }
 
+/// A uniform value: one element, or an array of them. The element types
+/// follow GLSL, with matrices stored column by column, so `[[f32; 3]; 2]`
+/// is a `mat2x3`.
+#[derive(Clone,Copy)]
 pub enum UniformEnum<'a> {
-    Vec1(&'a[GLfloat;1]),
-    Vec2(&'a[GLfloat;2]),
-    Vec3(&'a[GLfloat;3]),
-    Vec4(&'a[GLfloat;4]),
-    Mat2(&'a[[GLfloat;2];2]),
-    Mat3(&'a[[GLfloat;3];3]),
-    Mat4(&'a[[GLfloat;4];4]),
+    Vec1(&'a [GLfloat]),
+    Vec2(&'a [[GLfloat;2]]),
+    Vec3(&'a [[GLfloat;3]]),
+    Vec4(&'a [[GLfloat;4]]),
+    IVec1(&'a [GLint]),
+    IVec2(&'a [[GLint;2]]),
+    IVec3(&'a [[GLint;3]]),
+    IVec4(&'a [[GLint;4]]),
+    UVec1(&'a [GLuint]),
+    UVec2(&'a [[GLuint;2]]),
+    UVec3(&'a [[GLuint;3]]),
+    UVec4(&'a [[GLuint;4]]),
+    BVec1(&'a [bool]),
+    BVec2(&'a [[bool;2]]),
+    BVec3(&'a [[bool;3]]),
+    BVec4(&'a [[bool;4]]),
+    DVec1(&'a [GLdouble]),
+    DVec2(&'a [[GLdouble;2]]),
+    DVec3(&'a [[GLdouble;3]]),
+    DVec4(&'a [[GLdouble;4]]),
+    Mat2(&'a [[[GLfloat;2];2]]),
+    Mat3(&'a [[[GLfloat;3];3]]),
+    Mat4(&'a [[[GLfloat;4];4]]),
+    Mat2x3(&'a [[[GLfloat;3];2]]),
+    Mat2x4(&'a [[[GLfloat;4];2]]),
+    Mat3x2(&'a [[[GLfloat;2];3]]),
+    Mat3x4(&'a [[[GLfloat;4];3]]),
+    Mat4x2(&'a [[[GLfloat;2];4]]),
+    Mat4x3(&'a [[[GLfloat;3];4]]),
+    DMat2(&'a [[[GLdouble;2];2]]),
+    DMat3(&'a [[[GLdouble;3];3]]),
+    DMat4(&'a [[[GLdouble;4];4]]),
+    DMat2x3(&'a [[[GLdouble;3];2]]),
+    DMat2x4(&'a [[[GLdouble;4];2]]),
+    DMat3x2(&'a [[[GLdouble;2];3]]),
+    DMat3x4(&'a [[[GLdouble;4];3]]),
+    DMat4x2(&'a [[[GLdouble;2];4]]),
+    DMat4x3(&'a [[[GLdouble;3];4]]),
     Tex(&'a texture::TextureRef<'a>),
 }
 
+impl<'a> UniformEnum<'a> {
+    /// The GLSL type of each element, e.g. `gl::FLOAT_VEC3`, or None for a
+    /// texture, whose sampler type is not fixed.
+    pub fn gl_type(&self) -> Option<GLenum>
+    {
+        use self::UniformEnum::*;
+        Some(match *self {
+            Vec1(..)    => gl::FLOAT,
+            Vec2(..)    => gl::FLOAT_VEC2,
+            Vec3(..)    => gl::FLOAT_VEC3,
+            Vec4(..)    => gl::FLOAT_VEC4,
+            IVec1(..)   => gl::INT,
+            IVec2(..)   => gl::INT_VEC2,
+            IVec3(..)   => gl::INT_VEC3,
+            IVec4(..)   => gl::INT_VEC4,
+            UVec1(..)   => gl::UNSIGNED_INT,
+            UVec2(..)   => gl::UNSIGNED_INT_VEC2,
+            UVec3(..)   => gl::UNSIGNED_INT_VEC3,
+            UVec4(..)   => gl::UNSIGNED_INT_VEC4,
+            BVec1(..)   => gl::BOOL,
+            BVec2(..)   => gl::BOOL_VEC2,
+            BVec3(..)   => gl::BOOL_VEC3,
+            BVec4(..)   => gl::BOOL_VEC4,
+            DVec1(..)   => gl::DOUBLE,
+            DVec2(..)   => gl::DOUBLE_VEC2,
+            DVec3(..)   => gl::DOUBLE_VEC3,
+            DVec4(..)   => gl::DOUBLE_VEC4,
+            Mat2(..)    => gl::FLOAT_MAT2,
+            Mat3(..)    => gl::FLOAT_MAT3,
+            Mat4(..)    => gl::FLOAT_MAT4,
+            Mat2x3(..)  => gl::FLOAT_MAT2x3,
+            Mat2x4(..)  => gl::FLOAT_MAT2x4,
+            Mat3x2(..)  => gl::FLOAT_MAT3x2,
+            Mat3x4(..)  => gl::FLOAT_MAT3x4,
+            Mat4x2(..)  => gl::FLOAT_MAT4x2,
+            Mat4x3(..)  => gl::FLOAT_MAT4x3,
+            DMat2(..)   => gl::DOUBLE_MAT2,
+            DMat3(..)   => gl::DOUBLE_MAT3,
+            DMat4(..)   => gl::DOUBLE_MAT4,
+            DMat2x3(..) => gl::DOUBLE_MAT2x3,
+            DMat2x4(..) => gl::DOUBLE_MAT2x4,
+            DMat3x2(..) => gl::DOUBLE_MAT3x2,
+            DMat3x4(..) => gl::DOUBLE_MAT3x4,
+            DMat4x2(..) => gl::DOUBLE_MAT4x2,
+            DMat4x3(..) => gl::DOUBLE_MAT4x3,
+            Tex(..)     => return None,
+        })
+    }
+
+    /// The number of array elements.
+    pub fn len(&self) -> usize
+    {
+        use self::UniformEnum::*;
+        match *self {
+            Vec1(v)     => v.len(),
+            Vec2(v)     => v.len(),
+            Vec3(v)     => v.len(),
+            Vec4(v)     => v.len(),
+            IVec1(v)    => v.len(),
+            IVec2(v)    => v.len(),
+            IVec3(v)    => v.len(),
+            IVec4(v)    => v.len(),
+            UVec1(v)    => v.len(),
+            UVec2(v)    => v.len(),
+            UVec3(v)    => v.len(),
+            UVec4(v)    => v.len(),
+            BVec1(v)    => v.len(),
+            BVec2(v)    => v.len(),
+            BVec3(v)    => v.len(),
+            BVec4(v)    => v.len(),
+            DVec1(v)    => v.len(),
+            DVec2(v)    => v.len(),
+            DVec3(v)    => v.len(),
+            DVec4(v)    => v.len(),
+            Mat2(v)     => v.len(),
+            Mat3(v)     => v.len(),
+            Mat4(v)     => v.len(),
+            Mat2x3(v)   => v.len(),
+            Mat2x4(v)   => v.len(),
+            Mat3x2(v)   => v.len(),
+            Mat3x4(v)   => v.len(),
+            Mat4x2(v)   => v.len(),
+            Mat4x3(v)   => v.len(),
+            DMat2(v)    => v.len(),
+            DMat3(v)    => v.len(),
+            DMat4(v)    => v.len(),
+            DMat2x3(v)  => v.len(),
+            DMat2x4(v)  => v.len(),
+            DMat3x2(v)  => v.len(),
+            DMat3x4(v)  => v.len(),
+            DMat4x2(v)  => v.len(),
+            DMat4x3(v)  => v.len(),
+            Tex(..)     => 1,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.len() == 0
+    }
+
+    /// Whether this value can set a uniform of GLSL type `gl_type`, as
+    /// reported by `Program::active_uniforms`. Textures match the samplers
+    /// of their target.
+    pub fn matches(&self, gl_type: GLenum) -> bool
+    {
+        match *self {
+            UniformEnum::Tex(t) => sampler_target(gl_type) == Some(t.target()),
+            ref v               => v.gl_type() == Some(gl_type),
+        }
+    }
+
+    /// The GLSL type this value sets, for error messages.
+    pub fn type_name(&self) -> &'static str
+    {
+        match *self {
+            UniformEnum::Tex(t) => SAMPLERS.iter()
+                .find(|s| s.1 == t.target())
+                .map_or("texture", |s| glsl_type_name(s.0)),
+            ref v               => glsl_type_name(v.gl_type().unwrap_or(gl::NONE)),
+        }
+    }
+}
+
+// Each sampler type and the texture target it samples, float samplers
+// first.
+const SAMPLERS: &[(GLenum, GLenum)] = &[
+    (gl::SAMPLER_1D,                     gl::TEXTURE_1D),
+    (gl::SAMPLER_2D,                     gl::TEXTURE_2D),
+    (gl::SAMPLER_3D,                     gl::TEXTURE_3D),
+    (gl::SAMPLER_CUBE,                   gl::TEXTURE_CUBE_MAP),
+    (gl::SAMPLER_1D_ARRAY,               gl::TEXTURE_1D_ARRAY),
+    (gl::SAMPLER_2D_ARRAY,               gl::TEXTURE_2D_ARRAY),
+    (gl::SAMPLER_CUBE_MAP_ARRAY,         gl::TEXTURE_CUBE_MAP_ARRAY),
+    (gl::SAMPLER_2D_RECT,                gl::TEXTURE_RECTANGLE),
+    (gl::SAMPLER_2D_MULTISAMPLE,         gl::TEXTURE_2D_MULTISAMPLE),
+    (gl::SAMPLER_2D_MULTISAMPLE_ARRAY,   gl::TEXTURE_2D_MULTISAMPLE_ARRAY),
+    (gl::SAMPLER_BUFFER,                 gl::TEXTURE_BUFFER),
+    (gl::SAMPLER_1D_SHADOW,              gl::TEXTURE_1D),
+    (gl::SAMPLER_2D_SHADOW,              gl::TEXTURE_2D),
+    (gl::SAMPLER_CUBE_SHADOW,            gl::TEXTURE_CUBE_MAP),
+    (gl::SAMPLER_1D_ARRAY_SHADOW,        gl::TEXTURE_1D_ARRAY),
+    (gl::SAMPLER_2D_ARRAY_SHADOW,        gl::TEXTURE_2D_ARRAY),
+    (gl::SAMPLER_CUBE_MAP_ARRAY_SHADOW,  gl::TEXTURE_CUBE_MAP_ARRAY),
+    (gl::SAMPLER_2D_RECT_SHADOW,         gl::TEXTURE_RECTANGLE),
+    (gl::INT_SAMPLER_1D,                 gl::TEXTURE_1D),
+    (gl::INT_SAMPLER_2D,                 gl::TEXTURE_2D),
+    (gl::INT_SAMPLER_3D,                 gl::TEXTURE_3D),
+    (gl::INT_SAMPLER_CUBE,               gl::TEXTURE_CUBE_MAP),
+    (gl::INT_SAMPLER_1D_ARRAY,           gl::TEXTURE_1D_ARRAY),
+    (gl::INT_SAMPLER_2D_ARRAY,           gl::TEXTURE_2D_ARRAY),
+    (gl::INT_SAMPLER_2D_RECT,            gl::TEXTURE_RECTANGLE),
+    (gl::INT_SAMPLER_BUFFER,             gl::TEXTURE_BUFFER),
+    (gl::UNSIGNED_INT_SAMPLER_1D,        gl::TEXTURE_1D),
+    (gl::UNSIGNED_INT_SAMPLER_2D,        gl::TEXTURE_2D),
+    (gl::UNSIGNED_INT_SAMPLER_3D,        gl::TEXTURE_3D),
+    (gl::UNSIGNED_INT_SAMPLER_CUBE,      gl::TEXTURE_CUBE_MAP),
+    (gl::UNSIGNED_INT_SAMPLER_1D_ARRAY,  gl::TEXTURE_1D_ARRAY),
+    (gl::UNSIGNED_INT_SAMPLER_2D_ARRAY,  gl::TEXTURE_2D_ARRAY),
+    (gl::UNSIGNED_INT_SAMPLER_2D_RECT,   gl::TEXTURE_RECTANGLE),
+    (gl::UNSIGNED_INT_SAMPLER_BUFFER,    gl::TEXTURE_BUFFER),
+];
+
+/// The texture target a sampler of GLSL type `gl_type` samples, or None if
+/// it is not a sampler.
+pub fn sampler_target(gl_type: GLenum) -> Option<GLenum>
+{
+    SAMPLERS.iter().find(|s| s.0 == gl_type).map(|s| s.1)
+}
+
+/// The GLSL spelling of uniform type `gl_type`, e.g. "vec3" for
+/// `gl::FLOAT_VEC3`.
+pub fn glsl_type_name(gl_type: GLenum) -> &'static str
+{
+    match gl_type {
+        gl::FLOAT                         => "float",
+        gl::FLOAT_VEC2                    => "vec2",
+        gl::FLOAT_VEC3                    => "vec3",
+        gl::FLOAT_VEC4                    => "vec4",
+        gl::DOUBLE                        => "double",
+        gl::DOUBLE_VEC2                   => "dvec2",
+        gl::DOUBLE_VEC3                   => "dvec3",
+        gl::DOUBLE_VEC4                   => "dvec4",
+        gl::INT                           => "int",
+        gl::INT_VEC2                      => "ivec2",
+        gl::INT_VEC3                      => "ivec3",
+        gl::INT_VEC4                      => "ivec4",
+        gl::UNSIGNED_INT                  => "uint",
+        gl::UNSIGNED_INT_VEC2             => "uvec2",
+        gl::UNSIGNED_INT_VEC3             => "uvec3",
+        gl::UNSIGNED_INT_VEC4             => "uvec4",
+        gl::BOOL                          => "bool",
+        gl::BOOL_VEC2                     => "bvec2",
+        gl::BOOL_VEC3                     => "bvec3",
+        gl::BOOL_VEC4                     => "bvec4",
+        gl::FLOAT_MAT2                    => "mat2",
+        gl::FLOAT_MAT3                    => "mat3",
+        gl::FLOAT_MAT4                    => "mat4",
+        gl::FLOAT_MAT2x3                  => "mat2x3",
+        gl::FLOAT_MAT2x4                  => "mat2x4",
+        gl::FLOAT_MAT3x2                  => "mat3x2",
+        gl::FLOAT_MAT3x4                  => "mat3x4",
+        gl::FLOAT_MAT4x2                  => "mat4x2",
+        gl::FLOAT_MAT4x3                  => "mat4x3",
+        gl::DOUBLE_MAT2                   => "dmat2",
+        gl::DOUBLE_MAT3                   => "dmat3",
+        gl::DOUBLE_MAT4                   => "dmat4",
+        gl::DOUBLE_MAT2x3                 => "dmat2x3",
+        gl::DOUBLE_MAT2x4                 => "dmat2x4",
+        gl::DOUBLE_MAT3x2                 => "dmat3x2",
+        gl::DOUBLE_MAT3x4                 => "dmat3x4",
+        gl::DOUBLE_MAT4x2                 => "dmat4x2",
+        gl::DOUBLE_MAT4x3                 => "dmat4x3",
+        gl::SAMPLER_1D                    => "sampler1D",
+        gl::SAMPLER_2D                    => "sampler2D",
+        gl::SAMPLER_3D                    => "sampler3D",
+        gl::SAMPLER_CUBE                  => "samplerCube",
+        gl::SAMPLER_1D_ARRAY              => "sampler1DArray",
+        gl::SAMPLER_2D_ARRAY              => "sampler2DArray",
+        gl::SAMPLER_CUBE_MAP_ARRAY        => "samplerCubeArray",
+        gl::SAMPLER_2D_RECT               => "sampler2DRect",
+        gl::SAMPLER_2D_MULTISAMPLE        => "sampler2DMS",
+        gl::SAMPLER_2D_MULTISAMPLE_ARRAY  => "sampler2DMSArray",
+        gl::SAMPLER_BUFFER                => "samplerBuffer",
+        gl::SAMPLER_1D_SHADOW             => "sampler1DShadow",
+        gl::SAMPLER_2D_SHADOW             => "sampler2DShadow",
+        gl::SAMPLER_CUBE_SHADOW           => "samplerCubeShadow",
+        gl::SAMPLER_1D_ARRAY_SHADOW       => "sampler1DArrayShadow",
+        gl::SAMPLER_2D_ARRAY_SHADOW       => "sampler2DArrayShadow",
+        gl::SAMPLER_CUBE_MAP_ARRAY_SHADOW => "samplerCubeArrayShadow",
+        gl::SAMPLER_2D_RECT_SHADOW        => "sampler2DRectShadow",
+        gl::INT_SAMPLER_1D                => "isampler1D",
+        gl::INT_SAMPLER_2D                => "isampler2D",
+        gl::INT_SAMPLER_3D                => "isampler3D",
+        gl::INT_SAMPLER_CUBE              => "isamplerCube",
+        gl::INT_SAMPLER_1D_ARRAY          => "isampler1DArray",
+        gl::INT_SAMPLER_2D_ARRAY          => "isampler2DArray",
+        gl::INT_SAMPLER_2D_RECT           => "isampler2DRect",
+        gl::INT_SAMPLER_BUFFER            => "isamplerBuffer",
+        gl::UNSIGNED_INT_SAMPLER_1D       => "usampler1D",
+        gl::UNSIGNED_INT_SAMPLER_2D       => "usampler2D",
+        gl::UNSIGNED_INT_SAMPLER_3D       => "usampler3D",
+        gl::UNSIGNED_INT_SAMPLER_CUBE     => "usamplerCube",
+        gl::UNSIGNED_INT_SAMPLER_1D_ARRAY => "usampler1DArray",
+        gl::UNSIGNED_INT_SAMPLER_2D_ARRAY => "usampler2DArray",
+        gl::UNSIGNED_INT_SAMPLER_2D_RECT  => "usampler2DRect",
+        gl::UNSIGNED_INT_SAMPLER_BUFFER   => "usamplerBuffer",
+        _                                 => "an unknown type",
+    }
+}
+
+/// A struct whose fields are uniform values, usually implemented with
+/// `#[derive(Uniforms)]` or `implement_uniforms!`. Upload it through
+/// `UniformLocations`.
+pub trait Uniforms {
+    /// The GLSL name of each field, in declaration order.
+    fn uniform_names() -> &'static [&'static str];
+
+    /// Calls `f` with the value of each field, in declaration order,
+    /// stopping at the first error.
+    fn visit_uniforms<'s>(&'s self, f: &mut dyn FnMut(UniformEnum<'s>) -> Result<(), Error>)
+        -> Result<(), Error>;
+}
+
+/// The locations of the fields of `U` in one program, resolved and type
+/// checked once so that uploads need no name lookups or allocation.
+pub struct UniformLocations<U: Uniforms> {
+    program:   Program,
+    // The location of each array element a field has room for, or None
+    // if the program does not have it.
+    locations: Vec<Option<Vec<GLint>>>,
+    phantom:   PhantomData<fn(&U)>,
+}
+
+impl<U: Uniforms> UniformLocations<U> {
+    /// Resolves each field of `uniforms` in `program` and checks its type.
+    /// Fields the program does not have follow `unknown_uniform_policy`
+    /// and are skipped by uploads.
+    pub fn new(program: &Program, uniforms: &U) -> Result<Self, Error>
+    {
+        let names = U::uniform_names();
+        let mut locations = Vec::with_capacity(names.len());
+        uniforms.visit_uniforms(&mut |value| {
+            let name = names[locations.len()];
+            match program.find_uniform(name)? {
+                Some((_, active, left)) => {
+                    check_uniform(program, name, &value, active, left)?;
+                    locations.push(Some(element_locations(active, left).to_vec()));
+                }
+                None => {
+                    unknown_uniform(program, name)?;
+                    locations.push(None);
+                }
+            }
+            Ok(())
+        })?;
+        Ok(UniformLocations { program: program.clone(), locations, phantom: PhantomData })
+    }
+
+    /// The program the locations belong to, which they keep alive.
+    pub fn program(&self) -> &Program
+    {
+        &self.program
+    }
+
+    /// The location of each field, or None where the program lacks it.
+    pub fn locations(&self) -> Vec<Option<GLint>>
+    {
+        self.locations.iter().map(|l| l.as_ref().map(|elements| elements[0])).collect()
+    }
+
+    /// Sets every field in the program, which must be in use, binding
+    /// textures to consecutive units from 0.
+    pub fn upload(&self, uniforms: &U) -> Result<(), Error>
+    {
+        self.upload_with(uniforms, |unit, t| {
+            texture::active_texture(unit)?;
+            t.bind()
+        })
+    }
+
+    /// Like `upload`, but leaves binding each texture to its unit to
+    /// `bind_texture`. Uploading while another program is in use is an
+    /// error.
+    pub fn upload_with<'s, F>(&self, uniforms: &'s U, mut bind_texture: F) -> Result<(), Error>
+        where F: FnMut(GLuint, &texture::TextureRef<'s>) -> Result<(), Error>
+    {
+        let mut current = 0;
+        unsafe {
+            gl::GetIntegerv(gl::CURRENT_PROGRAM, &mut current);
+            check_error!();
+        }
+        if current as GLuint != self.program.id() {
+            return Err(Error::Validation(format!(
+                "uploading the uniforms of program {} while program {} is in use",
+                self.program.id(), current)))
+        }
+        let names = U::uniform_names();
+        let mut unit = 0;
+        let mut i = 0;
+        uniforms.visit_uniforms(&mut |value| {
+            let field = i;
+            i += 1;
+            match self.locations[field] {
+                Some(ref elements) => {
+                    // Slices can grow after the check.
+                    check_uniform_count(self.program.id(), names[field], &value,
+                                        elements.len() as GLint)?;
+                    set_uniform(elements, &value, &mut unit, &mut bind_texture)
+                }
+                None => Ok(()),
+            }
+        })
+    }
+}
+
+impl<U: Uniforms> Clone for UniformLocations<U> {
+    fn clone(&self) -> Self
+    {
+        UniformLocations {
+            program:   self.program.clone(),
+            locations: self.locations.clone(),
+            phantom:   PhantomData,
+        }
+    }
+}
+
+impl<U: Uniforms> fmt::Debug for UniformLocations<U> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        f.debug_struct("UniformLocations")
+            .field("program", &self.program.id())
+            .field("names", &U::uniform_names())
+            .field("locations", &self.locations())
+            .finish()
+    }
+}
+
+/// What `setup_uniforms` does with a uniform the program does not have,
+/// e.g. because the compiler optimized it out.
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum UnknownUniformPolicy {
+    Ignore,
+    /// Report a warning through the drop error policy and carry on.
+    Warn,
+    /// Fail with `Error::Validation`.
+    Error,
+}
+
+// Per-thread like the drop error policy, since contexts are.
+thread_local! {
+    static UNKNOWN_UNIFORM_POLICY: Cell<UnknownUniformPolicy> =
+        const { Cell::new(UnknownUniformPolicy::Ignore) };
+}
+
+pub fn set_unknown_uniform_policy(policy: UnknownUniformPolicy)
+{
+    UNKNOWN_UNIFORM_POLICY.with(|p| p.set(policy));
+}
+
+pub fn unknown_uniform_policy() -> UnknownUniformPolicy
+{
+    UNKNOWN_UNIFORM_POLICY.with(|p| p.get())
+}
+
+// Implements `ToUniform` for a single `$ty` and for slices of them.
 macro_rules! implement_to_uniform {
     ($ty:ty, $field:ident) => {
         impl ToUniform for $ty {
-            fn to_uniform(&self) -> UniformEnum
+            fn to_uniform(&self) -> UniformEnum<'_>
+            {
+                UniformEnum::$field(::std::slice::from_ref(self))
+            }
+        }
+
+        impl ToUniform for [$ty] {
+            fn to_uniform(&self) -> UniformEnum<'_>
             {
                 UniformEnum::$field(self)
             }
@@ -29,21 +484,154 @@ macro_rules! implement_to_uniform {
     }
 }
 
+/// Converts a value, or a slice of values, into a `UniformEnum`. Arrays
+/// are taken as vectors or matrices, so pass a GLSL array as a slice,
+/// e.g. `&lights[..]`; a `Vec` works as is.
+///
+/// Doubles are left out so that unsuffixed float literals stay `f32`;
+/// pass them as a `UniformEnum`, e.g. `UniformEnum::DVec2(&[origin])`.
 pub trait ToUniform {
-    fn to_uniform(&self) -> UniformEnum;
+    fn to_uniform(&self) -> UniformEnum<'_>;
+}
+
+implement_to_uniform!(GLfloat, Vec1);
+implement_to_uniform!([GLfloat;2], Vec2);
+implement_to_uniform!([GLfloat;3], Vec3);
+implement_to_uniform!([GLfloat;4], Vec4);
+implement_to_uniform!(GLint, IVec1);
+implement_to_uniform!([GLint;2], IVec2);
+implement_to_uniform!([GLint;3], IVec3);
+implement_to_uniform!([GLint;4], IVec4);
+implement_to_uniform!(GLuint, UVec1);
+implement_to_uniform!([GLuint;2], UVec2);
+implement_to_uniform!([GLuint;3], UVec3);
+implement_to_uniform!([GLuint;4], UVec4);
+implement_to_uniform!(bool, BVec1);
+implement_to_uniform!([bool;2], BVec2);
+implement_to_uniform!([bool;3], BVec3);
+implement_to_uniform!([bool;4], BVec4);
+implement_to_uniform!([[GLfloat;2];2], Mat2);
+implement_to_uniform!([[GLfloat;3];3], Mat3);
+implement_to_uniform!([[GLfloat;4];4], Mat4);
+implement_to_uniform!([[GLfloat;3];2], Mat2x3);
+implement_to_uniform!([[GLfloat;4];2], Mat2x4);
+implement_to_uniform!([[GLfloat;2];3], Mat3x2);
+implement_to_uniform!([[GLfloat;4];3], Mat3x4);
+implement_to_uniform!([[GLfloat;2];4], Mat4x2);
+implement_to_uniform!([[GLfloat;3];4], Mat4x3);
+
+impl ToUniform for [GLfloat;1] {
+    fn to_uniform(&self) -> UniformEnum<'_>
+    {
+        UniformEnum::Vec1(&self[..])
+    }
+}
+
+impl<'a> ToUniform for UniformEnum<'a> {
+    fn to_uniform(&self) -> UniformEnum<'_>
+    {
+        *self
+    }
 }
 
-implement_to_uniform!([GLfloat;1],Vec1);
-implement_to_uniform!([GLfloat;2],Vec2);
-implement_to_uniform!([GLfloat;3],Vec3);
-implement_to_uniform!([GLfloat;4],Vec4);
-implement_to_uniform!([[GLfloat;2];2],Mat2);
-implement_to_uniform!([[GLfloat;3];3],Mat3);
-implement_to_uniform!([[GLfloat;4];4],Mat4);
-//implement_to_uniform!(texture::TextureRef,Tex);
 impl<'a> ToUniform for texture::TextureRef<'a> {
-    fn to_uniform(&self) -> UniformEnum
+    fn to_uniform(&self) -> UniformEnum<'_>
     {
         UniformEnum::Tex(self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::{Arg, Mock};
+    use shader::ShaderSrc;
+    use texture::TextureRef;
+
+    struct Scene<'a> {
+        mvp:    [[GLfloat; 4]; 4],
+        tint:   [GLfloat; 4],
+        albedo: TextureRef<'a>,
+    }
+
+    implement_uniforms!(Scene<'a> { mvp: "u_mvp"; tint: "tint"; albedo: "albedo"; });
+
+    struct Fog {
+        density: GLfloat,
+    }
+
+    implement_uniforms!(Fog, density);
+
+    fn program(mock: &Mock) -> Program
+    {
+        mock.add_active_uniform("u_mvp", gl::FLOAT_MAT4, 1);
+        mock.add_active_uniform("albedo", gl::SAMPLER_2D, 1);
+        Program::new(ShaderSrc { vertex: "", fragment: "" }).unwrap()
+    }
+
+    #[test]
+    fn uploads_without_lookups()
+    {
+        let mock = Mock::install();
+        let program = program(&mock);
+        let scene = Scene {
+            mvp:    [[0.0; 4]; 4],
+            tint:   [1.0; 4],
+            albedo: TextureRef::new(gl::TEXTURE_2D).unwrap(),
+        };
+        let locations = UniformLocations::new(&program, &scene).unwrap();
+        assert_eq!(Scene::uniform_names(), &["u_mvp", "tint", "albedo"]);
+        assert_eq!(locations.locations(), vec![Some(0), None, Some(1)]);
+        program.use_program().unwrap();
+        mock.clear_calls();
+        locations.upload(&scene).unwrap();
+        assert_eq!(mock.calls(), vec![
+            gl_call!("glGetIntegerv", gl::CURRENT_PROGRAM),
+            gl_call!("glUniformMatrix4fv", 0, 1, gl::FALSE, Arg::Floats(vec![0.0; 16])),
+            gl_call!("glActiveTexture", gl::TEXTURE0),
+            gl_call!("glBindTexture", gl::TEXTURE_2D, scene.albedo.id()),
+            gl_call!("glUniform1i", 1, 0),
+        ]);
+    }
+
+    #[test]
+    fn types_are_checked_once()
+    {
+        let mock = Mock::install();
+        mock.add_active_uniform("density", gl::FLOAT_VEC2, 1);
+        let program = Program::new(ShaderSrc { vertex: "", fragment: "" }).unwrap();
+        match UniformLocations::new(&program, &Fog { density: 0.5 }) {
+            Err(Error::Validation(msg)) =>
+                assert!(msg.contains("`density`") && msg.contains("vec2"), "{}", msg),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn upload_checks_the_program_is_in_use()
+    {
+        let mock = Mock::install();
+        let program = program(&mock);
+        let locations = UniformLocations::new(&program, &Fog { density: 0.5 }).unwrap();
+        match locations.upload(&Fog { density: 0.5 }) {
+            Err(Error::Validation(msg)) =>
+                assert!(msg.contains("while program 0 is in use"), "{}", msg),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn locations_keep_their_program_alive()
+    {
+        let mock = Mock::install();
+        let locations = {
+            let program = program(&mock);
+            UniformLocations::new(&program, &Fog { density: 0.5 }).unwrap()
+        };
+        assert!(!mock.objects().iter().any(|&(f, _)| f == "glDeleteProgram"));
+        locations.program().use_program().unwrap();
+        assert_eq!(locations.upload(&Fog { density: 0.5 }), Ok(()));
+        drop(locations);
+        assert!(mock.objects().iter().any(|&(f, _)| f == "glDeleteProgram"));
+    }
+}
@@ -1,5 +1,9 @@
 use gl;
 use gl::types::*;
+use error::{Error, report_warning};
+use buffer::{BufferUsage, Pod};
+use ebo::{EBORef, IndexType};
+use vbo::VBORef;
 use shader;
 use image;
 use texture;
@@ -25,29 +29,34 @@ macro_rules! check_error {
     () => (
         let err = gl::GetError();
         if err != gl::NO_ERROR {
-            return Err(err);
+            return Err($crate::error::Error::GL {
+                code: err,
+                file: file!(),
+                line: line!(),
+            });
         }
     )
 }
 
+/// Like `check_error!`, for functions returning `Result<_, String>`.
+#[deprecated(note = "the crate returns `error::Error` now; use `check_error!`")]
 #[macro_export]
-macro_rules! panic_error {
+macro_rules! check_error_as_string {
     () => (
         let err = gl::GetError();
         if err != gl::NO_ERROR {
-            use util::*;
-            panic!(gl_error_str(err));
+            return Err($crate::util::gl_error_str(err).to_string());
         }
     )
 }
 
 #[macro_export]
-macro_rules! check_error_as_string {
+macro_rules! panic_error {
     () => (
         let err = gl::GetError();
         if err != gl::NO_ERROR {
             use util::*;
-            return Err(format!("{}", gl_error_str(err)));
+            panic!(gl_error_str(err));
         }
     )
 }
@@ -55,75 +64,119 @@ macro_rules! check_error_as_string {
 #[macro_export]
 macro_rules! sub { ($_x:expr => $y:expr) => ($y) }
 
+/// Implements `VertexAttribFields` for a `#[repr(C)]` struct from a list
+/// of its fields. Every field's type must implement `HasVertexAttribSize`
+/// and `HasGLType`; the layout is worked out at compile time.
+///
+/// The second form, which `#[derive(VertexAttribFields)]` expands to, also
+/// gives each field its attribute name, whether it is normalized, its
+/// `VertexAttribKind` (or `auto` to go by its type) and its location (or
+/// `auto` to look the name up). A field can only be read as an `Integer`
+/// if it is one, and as a `Double` if it is one:
+///
+/// ```compile_fail
+/// # #[macro_use] extern crate gl_utils;
+/// # extern crate gl;
+/// #[repr(C)]
+/// struct Vertex { weight: f32 }
+///
+/// implement_vertex_attrib!(Vertex { weight: "a_weight", false, Integer, auto; });
+/// # fn main() { let _ = <Vertex as gl_utils::util::VertexAttribFields>::kinds(); }
+/// ```
 #[macro_export]
 macro_rules! implement_vertex_attrib {
     ($struct_name:path, $($field_name:ident),+) => (
+        $crate::implement_vertex_attrib!($struct_name {
+            $($field_name: stringify!($field_name), false, auto, auto;)+
+        });
+    );
+    ($struct_name:path {
+        $($field_name:ident : $name:expr, $normalized:expr, $kind:ident, $location:tt;)+
+    }) => (
         impl $crate::util::VertexAttribFields for $struct_name {
             fn names() -> &'static [&'static str]
             {
-                const FIELD_NAMES: &'static [&'static str] =
-                    &[$(stringify!($field_name)),+];
+                const FIELD_NAMES: &'static [&'static str] = &[$($name),+];
                 FIELD_NAMES
             }
-
-            fn sizes() -> Vec<::gl::types::GLint>
+            fn locations() -> &'static [Option<::gl::types::GLuint>]
+            {
+                const LOCATIONS: &'static [Option<::gl::types::GLuint>] = &[$(
+                    $crate::vertex_attrib_location!($location)
+                ),+];
+                LOCATIONS
+            }
+            fn sizes() -> &'static [::gl::types::GLint]
+            {
+                const SIZES: &'static [::gl::types::GLint] = &[$(
+                    $crate::util::field_vertex_attrib_size(
+                        |s: &$struct_name| &s.$field_name)
+                ),+];
+                SIZES
+            }
+            fn columns() -> &'static [::gl::types::GLint]
             {
-                vec![$(
-                    {
-                        fn get_size<T: $crate::util::HasVertexAttribSize>(_: &T) ->
-                            ::gl::types::GLint
-                        {
-                            <T as $crate::util::HasVertexAttribSize>::
-                            get_vertex_attrib_size()
-                        }
-
-                        let s: &$struct_name = unsafe { ::std::mem::uninitialized() };
-                        let s_f = &s.$field_name;
-                        ::std::mem::forget(s);
-                        get_size(s_f)
-                    }
-                ),+]
+                const COLUMNS: &'static [::gl::types::GLint] = &[$(
+                    $crate::util::field_vertex_attrib_columns(
+                        |s: &$struct_name| &s.$field_name)
+                ),+];
+                COLUMNS
             }
-            fn types() -> Vec<::gl::types::GLenum>
+            fn types() -> &'static [::gl::types::GLenum]
             {
-                vec![$(
-                    {
-                        fn get_gl_type<T: $crate::util::HasGLType>(_: &T) ->
-                            ::gl::types::GLenum
-                        {
-                            <T as $crate::util::HasGLType>::get_gl_type()
-                        }
-
-                        let s: &$struct_name = unsafe { ::std::mem::uninitialized() };
-                        let s_f = &s.$field_name;
-                        ::std::mem::forget(s);
-                        get_gl_type(s_f)
-                    }
-                ),+]
+                const TYPES: &'static [::gl::types::GLenum] = &[$(
+                    $crate::util::field_gl_type(|s: &$struct_name| &s.$field_name)
+                ),+];
+                TYPES
+            }
+            fn kinds() -> &'static [$crate::util::VertexAttribKind]
+            {
+                const KINDS: &'static [$crate::util::VertexAttribKind] = &[$(
+                    $crate::vertex_attrib_kind!($kind, $struct_name, $field_name)
+                ),+];
+                KINDS
             }
             fn normalizeds() -> &'static [::gl::types::GLboolean]
             {
-                const N: &'static[::gl::types::GLboolean] =
-                    &[$( sub!(stringify!($field_name) => ::gl::FALSE) ),+];
+                const N: &'static[::gl::types::GLboolean] = &[$(
+                    if $normalized { ::gl::TRUE } else { ::gl::FALSE }
+                ),+];
                 N
             }
             fn stride() -> ::gl::types::GLsizei
             {
                 ::std::mem::size_of::<$struct_name>() as ::gl::types::GLsizei
             }
-            fn pointers() -> Vec<* const ::gl::types::GLvoid>
+            fn pointers() -> &'static [* const ::gl::types::GLvoid]
             {
-                vec![$(
-                    {
-                        offset_of!($struct_name => $field_name).get_byte_offset()
-                            as * const _
-                    }
-                ),+]
+                const POINTERS: &'static [* const ::gl::types::GLvoid] = &[$(
+                    ::std::mem::offset_of!($struct_name, $field_name) as * const _
+                ),+];
+                POINTERS
             }
         }
     )
 }
 
+#[doc(hidden)]
+#[macro_export]
+macro_rules! vertex_attrib_kind {
+    (auto, $struct_name:path, $field_name:ident) => (
+        $crate::util::field_vertex_attrib_kind(|s: &$struct_name| &s.$field_name)
+    );
+    ($kind:ident, $struct_name:path, $field_name:ident) => (
+        $crate::util::field_vertex_attrib_kind_as(|s: &$struct_name| &s.$field_name,
+                                                  $crate::util::VertexAttribKind::$kind)
+    );
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! vertex_attrib_location {
+    (auto)            => (None);
+    ($location:expr)  => (Some($location));
+}
+
 #[macro_export]
 macro_rules! uniforms {
     { $( $field_name: ident : $value:expr, )+ } => {
@@ -140,41 +193,100 @@ macro_rules! uniforms {
     }
 }
 
-pub fn size_of_gl_type(gl_type: GLenum) -> GLsizei
+/// Implements `uniform::Uniforms` for a struct, naming each uniform after
+/// its field or, in the full form, as given:
+///
+/// ```
+/// # #[macro_use] extern crate gl_utils;
+/// # use gl_utils::texture::TextureRef;
+/// struct Scene {
+///     mvp:  [[f32; 4]; 4],
+///     tint: [f32; 4],
+/// }
+///
+/// implement_uniforms!(Scene, mvp, tint);
+///
+/// struct Material<'a> {
+///     albedo:    TextureRef<'a>,
+///     roughness: f32,
+/// }
+///
+/// implement_uniforms!(Material<'a> { albedo: "u_albedo"; roughness: "u_roughness"; });
+/// # fn main() {}
+/// ```
+///
+/// Only lifetime parameters are supported. This is what
+/// `#[derive(Uniforms)]` expands to.
+#[macro_export]
+macro_rules! implement_uniforms {
+    ($struct_name:ident, $($field_name:ident),+ $(,)*) => (
+        $crate::implement_uniforms!($struct_name { $($field_name: stringify!($field_name);)+ });
+    );
+
+    ($struct_name:ident { $($field_name:ident : $name:expr;)* }) => (
+        $crate::implement_uniforms!($struct_name<> { $($field_name: $name;)* });
+    );
+
+    ($struct_name:ident < $($lt:lifetime),* > { $($field_name:ident : $name:expr;)* }) => (
+        impl<$($lt),*> $crate::uniform::Uniforms for $struct_name<$($lt),*> {
+            fn uniform_names() -> &'static [&'static str]
+            {
+                &[$($name),*]
+            }
+
+            #[allow(unused_variables)]
+            fn visit_uniforms<'s>(&'s self,
+                                  f: &mut dyn FnMut($crate::uniform::UniformEnum<'s>)
+                                                    -> Result<(), $crate::Error>)
+                -> Result<(), $crate::Error>
+            {
+                use $crate::uniform::ToUniform;
+                $( f(self.$field_name.to_uniform())?; )*
+                Ok(())
+            }
+        }
+    );
+}
+
+/// The size in bytes of a component of `gl_type`, or `None` for packed
+/// types such as `INT_2_10_10_10_REV`, whose components are not whole
+/// bytes, and for anything that is not a component type.
+pub fn size_of_gl_type(gl_type: GLenum) -> Option<GLsizei>
 {
     use std::mem::size_of;
-    (match gl_type {
+    let size = match gl_type {
         gl::BYTE           => size_of::<GLbyte>(),
         gl::UNSIGNED_BYTE  => size_of::<GLubyte>(),
         gl::SHORT          => size_of::<GLshort>(),
         gl::UNSIGNED_SHORT => size_of::<GLushort>(),
         gl::INT            => size_of::<GLint>(),
         gl::UNSIGNED_INT   => size_of::<GLuint>(),
+        gl::FIXED          => size_of::<GLfixed>(),
+        gl::HALF_FLOAT     => size_of::<GLhalf>(),
         gl::FLOAT          => size_of::<GLfloat>(),
         gl::DOUBLE         => size_of::<GLdouble>(),
-        _ => unimplemented!()
-    }) as GLsizei
+        _                  => return None,
+    };
+    Some(size as GLsizei)
 }
 
 pub trait HasVertexAttribSize {
-    fn get_vertex_attrib_size() -> GLint;
-}
-
-impl HasVertexAttribSize for GLfloat {
-    fn get_vertex_attrib_size() -> GLint
-    {
-        1
-    }
+    // components per location
+    const VERTEX_ATTRIB_SIZE: GLint;
+    // consecutive locations taken, one per matrix column
+    const VERTEX_ATTRIB_COLUMNS: GLint = 1;
 }
 
+/// An array of scalars is a vector, and an array of vectors is a matrix
+/// stored column by column.
 #[macro_export]
 macro_rules! has_vertex_attrib_size {
     ($size:expr) => (
-        impl<T> HasVertexAttribSize for [T;$size] {
-            fn get_vertex_attrib_size() -> GLint
-            {
-                $size
-            }
+        impl<T: HasVertexAttribSize> HasVertexAttribSize for [T;$size] {
+            const VERTEX_ATTRIB_SIZE: GLint =
+                if T::VERTEX_ATTRIB_SIZE == 1 { $size } else { T::VERTEX_ATTRIB_SIZE };
+            const VERTEX_ATTRIB_COLUMNS: GLint =
+                if T::VERTEX_ATTRIB_SIZE == 1 { 1 } else { $size };
         }
     )
 }
@@ -185,25 +297,47 @@ has_vertex_attrib_size!(3);
 has_vertex_attrib_size!(4);
 
 pub trait HasGLType {
-    fn get_gl_type() -> GLenum;
+    const GL_TYPE: GLenum;
 }
 
-impl HasGLType for GLfloat {
-    fn get_gl_type() -> GLenum
-    {
-        gl::FLOAT
-    }
+/// A half precision float, stored as its bits. `GLhalf` is only an alias
+/// for `u16`, which is an integer attribute.
+#[derive(Debug,Copy,Clone,PartialEq,Eq,Default)]
+#[repr(transparent)]
+pub struct Half(pub GLhalf);
+
+unsafe impl Pod for Half {}
+
+macro_rules! scalar_vertex_attrib {
+    ($($ty:ty => $gl_type:expr),+) => ($(
+        impl HasVertexAttribSize for $ty {
+            const VERTEX_ATTRIB_SIZE: GLint = 1;
+        }
+
+        impl HasGLType for $ty {
+            const GL_TYPE: GLenum = $gl_type;
+        }
+    )+)
+}
+
+scalar_vertex_attrib! {
+    GLbyte   => gl::BYTE,
+    GLubyte  => gl::UNSIGNED_BYTE,
+    GLshort  => gl::SHORT,
+    GLushort => gl::UNSIGNED_SHORT,
+    GLint    => gl::INT,
+    GLuint   => gl::UNSIGNED_INT,
+    Half     => gl::HALF_FLOAT,
+    GLfloat  => gl::FLOAT,
+    GLdouble => gl::DOUBLE
 }
 
-/// Note: This also defines [[T;$size];$size]
+/// Arrays of arrays, i.e. matrices, have the type of their scalars.
 #[macro_export]
 macro_rules! has_gl_type {
     ($size:expr) => (
         impl<T: HasGLType> HasGLType for [T;$size] {
-            fn get_gl_type() -> GLenum
-            {
-                <T as HasGLType>::get_gl_type()
-            }
+            const GL_TYPE: GLenum = T::GL_TYPE;
         }
     )
 }
@@ -212,61 +346,368 @@ has_gl_type!(2);
 has_gl_type!(3);
 has_gl_type!(4);
 
+/// How the shader sees an attribute, which decides the
+/// `glVertexAttrib*Pointer` function that sets it up.
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum VertexAttribKind {
+    /// `float`/`vec`, converted from any type by `glVertexAttribPointer`.
+    Float,
+    /// `int`/`uint`/`ivec`/`uvec`, via `glVertexAttribIPointer`.
+    Integer,
+    /// `double`/`dvec`, via `glVertexAttribLPointer`.
+    Double,
+}
+
+impl VertexAttribKind {
+    /// The kind a field of `gl_type` gets unless told otherwise: integers
+    /// stay integers.
+    pub const fn of_gl_type(gl_type: GLenum) -> VertexAttribKind
+    {
+        match gl_type {
+            gl::HALF_FLOAT | gl::FLOAT | gl::FIXED => VertexAttribKind::Float,
+            gl::DOUBLE                             => VertexAttribKind::Double,
+            _                                      => VertexAttribKind::Integer,
+        }
+    }
+}
+
+// `implement_vertex_attrib!` names a field's type by passing a projection
+// to these, which works in a constant without a value of the struct.
+#[doc(hidden)]
+pub const fn field_vertex_attrib_size<S, F: HasVertexAttribSize>(_: fn(&S) -> &F) -> GLint
+{
+    F::VERTEX_ATTRIB_SIZE
+}
+
+#[doc(hidden)]
+pub const fn field_vertex_attrib_columns<S, F: HasVertexAttribSize>(_: fn(&S) -> &F) -> GLint
+{
+    F::VERTEX_ATTRIB_COLUMNS
+}
+
+#[doc(hidden)]
+pub const fn field_gl_type<S, F: HasGLType>(_: fn(&S) -> &F) -> GLenum
+{
+    F::GL_TYPE
+}
+
+#[doc(hidden)]
+pub const fn field_vertex_attrib_kind<S, F: HasGLType>(_: fn(&S) -> &F) -> VertexAttribKind
+{
+    VertexAttribKind::of_gl_type(F::GL_TYPE)
+}
+
+// Any type converts to floats, but only integers can be read as integers
+// and only doubles as doubles.
+#[doc(hidden)]
+pub const fn field_vertex_attrib_kind_as<S, F: HasGLType>(_: fn(&S) -> &F,
+                                                           kind: VertexAttribKind)
+    -> VertexAttribKind
+{
+    match (kind, VertexAttribKind::of_gl_type(F::GL_TYPE)) {
+        (VertexAttribKind::Float, _)                             => kind,
+        (VertexAttribKind::Integer, VertexAttribKind::Integer)   => kind,
+        (VertexAttribKind::Double, VertexAttribKind::Double)     => kind,
+        (VertexAttribKind::Integer, _) => panic!("an integer attribute needs an integer field"),
+        (VertexAttribKind::Double, _)  => panic!("a double attribute needs a double field"),
+    }
+}
+
 pub trait VertexAttribFields {
     // the name to look up in the shader program
     fn names() -> &'static[&'static str];
+    // locations fixed by the struct rather than looked up by name; may be
+    // shorter than names()
+    fn locations() -> &'static [Option<GLuint>]
+    {
+        &[]
+    }
     // index parameter to glVertexAttribPointer
-    fn indexes(program: &shader::Program) -> Result<Vec<GLint>,GLenum>
+    fn indexes(program: &shader::Program) -> Result<Vec<GLint>,Error>
     {
+        let locations = Self::locations();
         Self::names()
             .iter()
-            .map(|n|
-                 program.get_attribute_location(n))
+            .enumerate()
+            .map(|(i, n)| match locations.get(i) {
+                Some(&Some(l)) => Ok(l as GLint),
+                _              => program.get_attribute_location(n),
+            })
             .collect::<_>()
     }
     // size parameter to glVertexAttribPointer
-    fn sizes() -> Vec<GLint>;
-    // type parameter to glVertexAttribPointer
-    fn types() -> Vec<GLenum>;
+    fn sizes() -> &'static [GLint];
+    // locations each attribute takes, one per matrix column; may be
+    // shorter than names(), the rest taking one
+    fn columns() -> &'static [GLint]
+    {
+        &[]
+    }
     // type parameter to glVertexAttribPointer
+    fn types() -> &'static [GLenum];
+    // which glVertexAttrib*Pointer to call; may be shorter than names(),
+    // the rest getting VertexAttribKind::of_gl_type of their type
+    fn kinds() -> &'static [VertexAttribKind]
+    {
+        &[]
+    }
+    // normalized parameter to glVertexAttribPointer
     fn normalizeds() -> &'static [GLboolean];
     // stride parameter to glVertexAttribPointer
     fn stride() -> GLsizei;
     // pointer parameter to glVertexAttribPointer
-    fn pointers() -> Vec<* const GLvoid>;
+    fn pointers() -> &'static [* const GLvoid];
 }
 
+/// Points each attribute at its field of `VAF` in the bound vertex buffer,
+/// at the locations `shader` assigns. Attributes the program does not use
+/// are skipped.
 pub fn setup_vertex_attrib<VAF: VertexAttribFields>
-    (shader: &shader::Program) -> Result<(),GLenum>
+    (shader: &shader::Program) -> Result<(),Error>
+{
+    setup_attribs::<VAF>(&program_locations::<VAF>(shader, false)?, 0)
+}
+
+/// Like `setup_vertex_attrib`, but an attribute the program does not use
+/// is an error.
+pub fn setup_vertex_attrib_strict<VAF: VertexAttribFields>
+    (shader: &shader::Program) -> Result<(),Error>
+{
+    setup_attribs::<VAF>(&program_locations::<VAF>(shader, true)?, 0)
+}
+
+/// Like `setup_vertex_attrib`, for a buffer of per-instance data: each
+/// element is used for `divisor` instances in a row.
+pub fn setup_instance_attrib<VAF: VertexAttribFields>
+    (shader: &shader::Program, divisor: GLuint) -> Result<(),Error>
+{
+    setup_attribs::<VAF>(&program_locations::<VAF>(shader, false)?, divisor)
+}
+
+/// Sets up the attributes at the locations of `layout_locations`, without
+/// asking any program. The resulting vertex array works with every
+/// program that uses those locations, whether by `layout(location = N)`
+/// or by linking with `Program::new_with_attrib_locations`.
+pub fn setup_vertex_layout<VAF: VertexAttribFields>() -> Result<(),Error>
 {
-    let locs        = VAF::indexes(shader)?
+    setup_instance_layout::<VAF>(0)
+}
+
+pub fn setup_instance_layout<VAF: VertexAttribFields>(divisor: GLuint) -> Result<(),Error>
+{
+    let locations = layout_locations::<VAF>()?
+        .into_iter()
+        .map(|(_, l)| Some(l))
+        .collect::<Vec<_>>();
+    setup_attribs::<VAF>(&locations, divisor)
+}
+
+/// The name and location of every attribute when they are not looked up:
+/// the attribute's fixed location if it has one, otherwise the one after
+/// the previous attribute's last column, starting from 0. Attributes whose
+/// locations overlap are an error.
+pub fn layout_locations<VAF: VertexAttribFields>()
+    -> Result<Vec<(&'static str, GLuint)>, Error>
+{
+    let fixed   = VAF::locations();
+    let columns = VAF::columns();
+    let mut next = 0;
+    // The locations taken so far, as (name, first, end).
+    let mut taken: Vec<(&str, GLuint, GLuint)> = Vec::new();
+    let mut out = Vec::new();
+    for (i, name) in VAF::names().iter().enumerate() {
+        let location = fixed.get(i).cloned().and_then(|l| l).unwrap_or(next);
+        next = location + columns.get(i).cloned().unwrap_or(1) as GLuint;
+        if let Some(&(other, _, _)) = taken.iter()
+            .find(|&&(_, first, end)| location < end && first < next)
+        {
+            return Err(Error::Validation(format!(
+                "attributes `{}` and `{}` of the vertex layout overlap at location {}",
+                other, name, location)))
+        }
+        taken.push((name, location, next));
+        out.push((*name, location));
+    }
+    Ok(out)
+}
+
+// The location of each attribute in `shader`, or None if it is not active.
+pub(crate) fn program_locations<VAF: VertexAttribFields>
+    (shader: &shader::Program, strict: bool) -> Result<Vec<Option<GLuint>>,Error>
+{
+    VAF::indexes(shader)?
         .iter()
-        .filter_map(|l| match *l {
-            -1 => None,
-            l  => Some(l as GLuint),
+        .zip(VAF::names())
+        .map(|(l, name)| match *l {
+            -1 if strict => Err(Error::Validation(format!(
+                "program {} has no active attribute `{}`", shader.id(), name))),
+            -1 => Ok(None),
+            l  => Ok(Some(l as GLuint)),
         })
-        .collect::<Vec<GLuint>>();
+        .collect()
+}
+
+/// One location's worth of an attribute: a whole vector attribute, or one
+/// column of a matrix.
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub struct AttribColumn {
+    /// The attribute's name, empty for an unnamed stream.
+    pub name:       &'static str,
+    pub location:   GLuint,
+    pub size:       GLint,
+    pub gl_type:    GLenum,
+    pub kind:       VertexAttribKind,
+    pub normalized: GLboolean,
+    /// Byte offset within a vertex.
+    pub offset:     usize,
+}
+
+// The distance between the columns of a matrix attribute. Only matrices
+// need the size of their type, so other attributes can be of any type.
+fn column_len(name: &str, size: GLint, gl_type: GLenum, columns: GLint)
+    -> Result<usize, Error>
+{
+    if columns <= 1 {
+        return Ok(0)
+    }
+    match size_of_gl_type(gl_type) {
+        Some(type_size) => Ok((size * type_size) as usize),
+        None            => Err(Error::Validation(format!(
+            "matrix attribute `{}` has type {:#x}, which has no component size",
+            name, gl_type))),
+    }
+}
+
+/// Splits the attributes of `VAF` into columns, given the location of each
+/// attribute. Attributes without a location are left out.
+pub fn attrib_columns<VAF: VertexAttribFields>(locations: &[Option<GLuint>])
+    -> Result<Vec<AttribColumn>, Error>
+{
+    let names       = VAF::names();
     let sizes       = VAF::sizes();
+    let columns     = VAF::columns();
     let types       = VAF::types();
+    let kinds       = VAF::kinds();
     let normalizeds = VAF::normalizeds();
-    let stride      = VAF::stride();
     let pointers    = VAF::pointers();
 
-    for (i,index) in locs.iter().enumerate() {
-        let size       = sizes[i];
-        let typ        = types[i];
-        let normalized = normalizeds[i];
-        let pointer    = pointers[i];
+    let mut out = Vec::new();
+    for (i, location) in locations.iter().enumerate() {
+        // Skipping keeps `i` in step with the other attribute data.
+        let location = match *location {
+            Some(l) => l,
+            None    => continue,
+        };
+        let column_count = columns.get(i).cloned().unwrap_or(1);
+        let column_len = column_len(names[i], sizes[i], types[i], column_count)?;
+        for column in 0..column_count {
+            out.push(AttribColumn {
+                name:       names[i],
+                location:   location + column as GLuint,
+                size:       sizes[i],
+                gl_type:    types[i],
+                kind:       kinds.get(i).cloned()
+                                 .unwrap_or_else(|| VertexAttribKind::of_gl_type(types[i])),
+                normalized: normalizeds[i],
+                offset:     pointers[i] as usize + column as usize * column_len,
+            });
+        }
+    }
+    Ok(out)
+}
+
+/// The single column, or one per matrix column, of a tightly packed
+/// stream of `T` whose attribute is at `location`.
+pub fn stream_columns<T>(name: &'static str, location: GLuint)
+    -> Result<Vec<AttribColumn>, Error>
+    where T: HasVertexAttribSize + HasGLType
+{
+    let column_len = column_len(name, T::VERTEX_ATTRIB_SIZE, T::GL_TYPE,
+                                T::VERTEX_ATTRIB_COLUMNS)?;
+    Ok((0..T::VERTEX_ATTRIB_COLUMNS)
+        .map(|column| AttribColumn {
+            name,
+            location:   location + column as GLuint,
+            size:       T::VERTEX_ATTRIB_SIZE,
+            gl_type:    T::GL_TYPE,
+            kind:       VertexAttribKind::of_gl_type(T::GL_TYPE),
+            normalized: gl::FALSE,
+            offset:     column as usize * column_len,
+        })
+        .collect())
+}
+
+fn setup_attribs<VAF: VertexAttribFields>
+    (locations: &[Option<GLuint>], divisor: GLuint) -> Result<(),Error>
+{
+    setup_attrib_columns(&attrib_columns::<VAF>(locations)?, VAF::stride(), divisor)
+}
+
+/// Points and enables each of `columns` in the buffer bound to
+/// `ARRAY_BUFFER`, `stride` bytes per vertex, or per `divisor` instances
+/// if that is not 0.
+pub fn setup_attrib_columns(columns: &[AttribColumn], stride: GLsizei, divisor: GLuint)
+    -> Result<(),Error>
+{
+    for c in columns {
+        let pointer = c.offset as *const GLvoid;
         unsafe {
-            gl::VertexAttribPointer(*index, size, typ, normalized, stride, pointer);
+            match c.kind {
+                VertexAttribKind::Float   =>
+                    gl::VertexAttribPointer(c.location, c.size, c.gl_type, c.normalized,
+                                            stride, pointer),
+                VertexAttribKind::Integer =>
+                    gl::VertexAttribIPointer(c.location, c.size, c.gl_type, stride, pointer),
+                VertexAttribKind::Double  =>
+                    gl::VertexAttribLPointer(c.location, c.size, c.gl_type, stride, pointer),
+            }
+            check_error!();
+            gl::EnableVertexAttribArray(c.location);
             check_error!();
-            gl::EnableVertexAttribArray(*index);
+            // Also when 0, as the location may have been per instance in
+            // an earlier setup of the bound vertex array.
+            gl::VertexAttribDivisor(c.location, divisor);
             check_error!();
         }
     }
     Ok(())
 }
 
+/// Points the attribute at `location` at `vbo`, a tightly packed stream
+/// holding just that attribute, e.g. a `VBORef<[GLfloat; 3]>` of normals.
+/// Leaves `vbo` bound to `ARRAY_BUFFER`.
+///
+/// Vertex arrays can be built from several such streams, and from
+/// `VertexAttribFields` streams, by setting each one up in turn.
+pub fn setup_attrib_stream<T>(vbo: &VBORef<T>, location: GLuint) -> Result<(),Error>
+    where T: Pod + HasVertexAttribSize + HasGLType
+{
+    vbo.bind()?;
+    setup_attrib_columns(&stream_columns::<T>("", location)?,
+                         ::std::mem::size_of::<T>() as GLsizei, 0)
+}
+
+/// `setup_attrib_stream` at the location of attribute `name` in `shader`.
+/// Does nothing if the program does not use `name`.
+pub fn setup_named_attrib_stream<T>(shader: &shader::Program, name: &str, vbo: &VBORef<T>)
+    -> Result<(),Error>
+    where T: Pod + HasVertexAttribSize + HasGLType
+{
+    match shader.get_attribute_location(name)? {
+        -1       => Ok(()),
+        location => setup_attrib_stream(vbo, location as GLuint),
+    }
+}
+
+/// `setup_vertex_attrib` for a stream of `VAF` in `vbo`, which it leaves
+/// bound to `ARRAY_BUFFER`.
+pub fn setup_vertex_stream<VAF>(shader: &shader::Program, vbo: &VBORef<VAF>) -> Result<(),Error>
+    where VAF: VertexAttribFields + Pod
+{
+    vbo.bind()?;
+    setup_vertex_attrib::<VAF>(shader)
+}
+
 pub trait HasUniformCount {
     fn get_uniform_count(&self) -> GLint;
 }
@@ -283,6 +724,13 @@ macro_rules! has_uniform_count {
     )
 }
 
+impl<T> HasUniformCount for [T] {
+    fn get_uniform_count(&self) -> GLint
+    {
+        self.len() as GLint
+    }
+}
+
 impl<T> HasUniformCount for Vec<T> {
     fn get_uniform_count(&self) -> GLint
     {
@@ -308,87 +756,238 @@ impl<'a> HasUniformCount for texture::TextureRef<'a> {
 impl<'a> HasUniformCount for uniform::UniformEnum<'a> {
     fn get_uniform_count(&self) -> GLint
     {
+        self.len() as GLint
+    }
+}
+
+/// Sets each of `uniforms` in `shader`, which must be in use, binding
+/// textures to consecutive units from 0. Locations and types come from the
+/// program's reflection, and a value of the wrong type is an error.
+/// Uniforms the program does not have are handled according to
+/// `uniform::unknown_uniform_policy`.
+pub fn setup_uniforms(uniforms: &[uniform::Uniform], shader: &shader::Program) ->
+    Result<(), Error>
+{
+    setup_uniforms_with(uniforms, shader, |unit, t| {
+        texture::active_texture(unit)?;
+        t.bind()
+    })
+}
+
+// GL takes bools as ints. They are converted on the stack a batch of
+// elements at a time, each batch going to the location of its first
+// element in `elements`, so uploads do not allocate.
+unsafe fn set_bool_uniform<'b, I>(elements: &[GLint], components: usize, bools: I,
+                                  set: unsafe fn(GLint, GLsizei, *const GLint))
+    where I: IntoIterator<Item = &'b bool>
+{
+    // Whole elements of every vector size fit.
+    let mut ints = [0 as GLint; 48];
+    let mut first = 0;
+    let mut len = 0;
+    for &b in bools {
+        ints[len] = b as GLint;
+        len += 1;
+        if len == ints.len() / components * components {
+            set(elements[first], (len / components) as GLsizei, ints.as_ptr());
+            first += len / components;
+            len = 0;
+        }
+    }
+    if len > 0 {
+        set(elements[first], (len / components) as GLsizei, ints.as_ptr());
+    }
+}
+
+// The locations of the `left` array elements of `active` a value goes to.
+pub(crate) fn element_locations(active: &shader::ActiveVariable, left: GLint) -> &[GLint]
+{
+    &active.elements[(active.size - left) as usize..]
+}
+
+pub(crate) fn unknown_uniform(shader: &shader::Program, name: &str) -> Result<(), Error>
+{
+    use uniform::UnknownUniformPolicy;
+    let err = || Error::Validation(format!(
+        "program {} has no active uniform `{}`", shader.id(), name));
+    match uniform::unknown_uniform_policy() {
+        UnknownUniformPolicy::Ignore => Ok(()),
+        UnknownUniformPolicy::Warn   => {
+            report_warning(err());
+            Ok(())
+        }
+        UnknownUniformPolicy::Error  => Err(err()),
+    }
+}
+
+// Checks `value` against the declaration `active` of uniform `name`, which
+// has `left` array elements from where `value` goes.
+pub(crate) fn check_uniform(shader: &shader::Program, name: &str, value: &uniform::UniformEnum,
+                            active: &shader::ActiveVariable, left: GLint) -> Result<(), Error>
+{
+    if !value.matches(active.gl_type) {
+        return Err(Error::Validation(format!(
+            "uniform `{}` of program {} is declared {} but was given {}",
+            name, shader.id(), uniform::glsl_type_name(active.gl_type), value.type_name())))
+    }
+    check_uniform_count(shader.id(), name, value, left)
+}
+
+pub(crate) fn check_uniform_count(program: GLuint, name: &str, value: &uniform::UniformEnum,
+                                  left: GLint) -> Result<(), Error>
+{
+    let count = value.get_uniform_count();
+    if count > left {
+        return Err(Error::Validation(format!(
+            "uniform `{}` of program {} has room for {} elements but was given {}",
+            name, program, left, count)))
+    }
+    Ok(())
+}
+
+// Sets the uniform whose array elements are at `elements` to `value`,
+// which has been checked. A texture is bound to `unit`, which then moves on
+// to the next unit.
+pub(crate) fn set_uniform<'a, F>(elements: &[GLint], value: &uniform::UniformEnum<'a>,
+                                 unit: &mut GLuint, bind_texture: &mut F) -> Result<(), Error>
+    where F: FnMut(GLuint, &texture::TextureRef<'a>) -> Result<(), Error>
+{
+    let index = elements[0];
+    let count = value.get_uniform_count();
+    unsafe {
         use uniform::UniformEnum::*;
-        match self {
-            &Vec1(..) => 1,
-            &Vec2(..) => 1,
-            &Vec3(..) => 1,
-            &Vec4(..) => 1,
-            &Mat2(..) => 1,
-            &Mat3(..) => 1,
-            &Mat4(..) => 1,
-            &Tex(..)  => 1,
+        match *value {
+            Vec1(v)  => gl::Uniform1fv(index, count, v.as_ptr() as * const _),
+            Vec2(v)  => gl::Uniform2fv(index, count, v.as_ptr() as * const _),
+            Vec3(v)  => gl::Uniform3fv(index, count, v.as_ptr() as * const _),
+            Vec4(v)  => gl::Uniform4fv(index, count, v.as_ptr() as * const _),
+            IVec1(v) => gl::Uniform1iv(index, count, v.as_ptr() as * const _),
+            IVec2(v) => gl::Uniform2iv(index, count, v.as_ptr() as * const _),
+            IVec3(v) => gl::Uniform3iv(index, count, v.as_ptr() as * const _),
+            IVec4(v) => gl::Uniform4iv(index, count, v.as_ptr() as * const _),
+            UVec1(v) => gl::Uniform1uiv(index, count, v.as_ptr() as * const _),
+            UVec2(v) => gl::Uniform2uiv(index, count, v.as_ptr() as * const _),
+            UVec3(v) => gl::Uniform3uiv(index, count, v.as_ptr() as * const _),
+            UVec4(v) => gl::Uniform4uiv(index, count, v.as_ptr() as * const _),
+            BVec1(v) => set_bool_uniform(elements, 1, v, gl::Uniform1iv),
+            BVec2(v) => set_bool_uniform(elements, 2, v.iter().flatten(), gl::Uniform2iv),
+            BVec3(v) => set_bool_uniform(elements, 3, v.iter().flatten(), gl::Uniform3iv),
+            BVec4(v) => set_bool_uniform(elements, 4, v.iter().flatten(), gl::Uniform4iv),
+            DVec1(v) => gl::Uniform1dv(index, count, v.as_ptr() as * const _),
+            DVec2(v) => gl::Uniform2dv(index, count, v.as_ptr() as * const _),
+            DVec3(v) => gl::Uniform3dv(index, count, v.as_ptr() as * const _),
+            DVec4(v) => gl::Uniform4dv(index, count, v.as_ptr() as * const _),
+            Mat2(v)  => gl::UniformMatrix2fv(index, count,
+                                             gl::FALSE, v.as_ptr() as * const _),
+            Mat3(v)  => gl::UniformMatrix3fv(index, count,
+                                             gl::FALSE, v.as_ptr() as * const _),
+            Mat4(v)  => gl::UniformMatrix4fv(index, count,
+                                             gl::FALSE, v.as_ptr() as * const _),
+            Mat2x3(v) => gl::UniformMatrix2x3fv(index, count,
+                                                gl::FALSE, v.as_ptr() as * const _),
+            Mat2x4(v) => gl::UniformMatrix2x4fv(index, count,
+                                                gl::FALSE, v.as_ptr() as * const _),
+            Mat3x2(v) => gl::UniformMatrix3x2fv(index, count,
+                                                gl::FALSE, v.as_ptr() as * const _),
+            Mat3x4(v) => gl::UniformMatrix3x4fv(index, count,
+                                                gl::FALSE, v.as_ptr() as * const _),
+            Mat4x2(v) => gl::UniformMatrix4x2fv(index, count,
+                                                gl::FALSE, v.as_ptr() as * const _),
+            Mat4x3(v) => gl::UniformMatrix4x3fv(index, count,
+                                                gl::FALSE, v.as_ptr() as * const _),
+            DMat2(v) => gl::UniformMatrix2dv(index, count,
+                                             gl::FALSE, v.as_ptr() as * const _),
+            DMat3(v) => gl::UniformMatrix3dv(index, count,
+                                             gl::FALSE, v.as_ptr() as * const _),
+            DMat4(v) => gl::UniformMatrix4dv(index, count,
+                                             gl::FALSE, v.as_ptr() as * const _),
+            DMat2x3(v) => gl::UniformMatrix2x3dv(index, count,
+                                                 gl::FALSE, v.as_ptr() as * const _),
+            DMat2x4(v) => gl::UniformMatrix2x4dv(index, count,
+                                                 gl::FALSE, v.as_ptr() as * const _),
+            DMat3x2(v) => gl::UniformMatrix3x2dv(index, count,
+                                                 gl::FALSE, v.as_ptr() as * const _),
+            DMat3x4(v) => gl::UniformMatrix3x4dv(index, count,
+                                                 gl::FALSE, v.as_ptr() as * const _),
+            DMat4x2(v) => gl::UniformMatrix4x2dv(index, count,
+                                                 gl::FALSE, v.as_ptr() as * const _),
+            DMat4x3(v) => gl::UniformMatrix4x3dv(index, count,
+                                                 gl::FALSE, v.as_ptr() as * const _),
+            Tex(t)   => {
+                bind_texture(*unit, t)?;
+                gl::Uniform1i(index, *unit as GLint);
+                check_error!();
+                *unit += 1;
+            }
         }
+        check_error!();
     }
+    Ok(())
 }
 
-pub fn setup_uniforms(uniforms: &[uniform::Uniform], shader: &shader::Program) ->
-    Result<(), GLenum>
+/// Like `setup_uniforms`, but leaves binding each texture to its unit to
+/// `bind_texture`.
+pub fn setup_uniforms_with<'a, F>(uniforms: &[uniform::Uniform<'a>],
+                                  shader: &shader::Program,
+                                  mut bind_texture: F) -> Result<(), Error>
+    where F: FnMut(GLuint, &texture::TextureRef<'a>) -> Result<(), Error>
 {
     let mut unit = 0;
     for u in uniforms {
-        let index = shader.get_uniform_location(u.name)?;
-        let count = u.value.get_uniform_count();
-        unsafe {
-            use uniform::UniformEnum::*;
-            match u.value {
-                Vec1(ref v) => gl::Uniform1fv(index, count, v.as_ptr() as * const _),
-                Vec2(ref v) => gl::Uniform2fv(index, count, v.as_ptr() as * const _),
-                Vec3(ref v) => gl::Uniform3fv(index, count, v.as_ptr() as * const _),
-                Vec4(ref v) => gl::Uniform4fv(index, count, v.as_ptr() as * const _),
-                Mat2(ref v) => gl::UniformMatrix2fv(index, count,
-                                                    gl::FALSE, v.as_ptr() as * const _),
-                Mat3(ref v) => gl::UniformMatrix3fv(index, count,
-                                                    gl::FALSE, v.as_ptr() as * const _),
-                Mat4(ref v) => gl::UniformMatrix4fv(index, count,
-                                                    gl::FALSE, v.as_ptr() as * const _),
-                Tex(ref t)  => {
-                    gl::ActiveTexture(gl::TEXTURE0 + unit);
-                    check_error!();
-                    t.bind()?;
-                    gl::Uniform1i(index, unit as GLint);
-                    check_error!();
-                    unit += 1;
-                }
+        match shader.find_uniform(u.name)? {
+            Some((_, active, left)) => {
+                check_uniform(shader, u.name, &u.value, active, left)?;
+                set_uniform(element_locations(active, left), &u.value, &mut unit,
+                            &mut bind_texture)?;
             }
-            check_error!();
+            None => unknown_uniform(shader, u.name)?,
         }
     }
     Ok(())
 }
 
-pub fn vertex_buffer_data<VAF: VertexAttribFields>
-    (data: &[VAF], target: GLenum, usage: GLenum) -> Result<(),GLenum>
+/// Replaces the contents of `vbo` with `data`, as `set_data` does, after
+/// checking that the layout's stride is the size of `VAF`.
+pub fn vertex_buffer_data<VAF: VertexAttribFields + Pod>
+    (vbo: &VBORef<VAF>, data: &[VAF], usage: BufferUsage) -> Result<(),Error>
 {
-    unsafe {
-        gl::BufferData(target,
-                       (data.len() * VAF::stride() as usize) as GLsizeiptr,
-                       data.as_ptr() as *const _,
-                       usage);
-        check_error!();
+    if VAF::stride() as usize != ::std::mem::size_of::<VAF>() {
+        return Err(Error::Validation(format!(
+            "vertex layout has a stride of {} bytes but its type is {} bytes",
+            VAF::stride(), ::std::mem::size_of::<VAF>())))
     }
-    Ok(())
+    vbo.set_data(data, usage)
 }
 
-pub fn build_cubemap<'a>(cubemap_images: &Vec<image::RgbaImage>,) ->
-    Result<texture::TextureRef<'a>,GLenum>
+#[allow(clippy::ptr_arg)]
+pub fn build_cubemap<'a>(cubemap_images: &Vec<image::RgbaImage>) ->
+    Result<texture::TextureRef<'a>,Error>
 {
     // We require them to all be the same size and square
     let size = {
         let mut sz = 0;
-        for im in cubemap_images.iter() {
-            if sz != 0 && im.dimensions().0 != sz { return Err(gl::INVALID_OPERATION) }
-            sz = im.dimensions().0;
-            if sz != im.dimensions().1 { return Err(gl::INVALID_OPERATION) }
+        for (i, im) in cubemap_images.iter().enumerate() {
+            let (w, h) = im.dimensions();
+            if sz != 0 && w != sz {
+                return Err(Error::Validation(format!(
+                    "cubemap face {} is {} pixels wide, expected {}", i, w, sz)))
+            }
+            sz = w;
+            if sz != h {
+                return Err(Error::Validation(format!(
+                    "cubemap face {} is {}x{}, expected a square image", i, w, h)))
+            }
+        }
+        if sz == 0 {
+            return Err(Error::Validation("cubemap faces are empty".to_string()))
         }
-        if sz == 0 { return Err(gl::INVALID_OPERATION) }
         sz
     };
     let cubemap_texture = {
         texture::TextureRef::unbind_target(gl::TEXTURE_CUBE_MAP)?;
         let result = texture::TextureRef::new(gl::TEXTURE_CUBE_MAP)?;
         result.bind()?;
-        for (i, ref im) in cubemap_images.into_iter().enumerate() {
+        for (i, im) in cubemap_images.iter().enumerate() {
             // TODO: add a bind_point concept? basically a texture
             // that has no drop? So far this is the only place in the
             // code that would use it.  So it seems poorly motivated
@@ -415,7 +1014,7 @@ pub fn build_cubemap<'a>(cubemap_images: &Vec<image::RgbaImage>,) ->
     Ok(cubemap_texture)
 }
 
-pub fn enable(cap: GLenum) -> Result<(), GLenum>
+pub fn enable(cap: GLenum) -> Result<(), Error>
 {
     unsafe {
         gl::Enable(cap);
@@ -424,7 +1023,7 @@ pub fn enable(cap: GLenum) -> Result<(), GLenum>
     Ok(())
 }
 
-pub fn disable(cap: GLenum) -> Result<(), GLenum>
+pub fn disable(cap: GLenum) -> Result<(), Error>
 {
     unsafe {
         gl::Disable(cap);
@@ -433,7 +1032,7 @@ pub fn disable(cap: GLenum) -> Result<(), GLenum>
     Ok(())
 }
 
-pub fn blend_func(sfactor: GLenum, dfactor: GLenum) -> Result<(), GLenum>
+pub fn blend_func(sfactor: GLenum, dfactor: GLenum) -> Result<(), Error>
 {
     unsafe {
         gl::BlendFunc(sfactor, dfactor);
@@ -442,7 +1041,7 @@ pub fn blend_func(sfactor: GLenum, dfactor: GLenum) -> Result<(), GLenum>
     Ok(())
 }
 
-pub fn depth_func(func: GLenum) -> Result<(), GLenum>
+pub fn depth_func(func: GLenum) -> Result<(), Error>
 {
     unsafe {
         gl::DepthFunc(func);
@@ -451,7 +1050,7 @@ pub fn depth_func(func: GLenum) -> Result<(), GLenum>
     Ok(())
 }
 
-pub fn draw_arrays(mode: GLenum, first: GLint, count: GLsizei) -> Result<(), GLenum>
+pub fn draw_arrays(mode: GLenum, first: GLint, count: GLsizei) -> Result<(), Error>
 {
     unsafe {
         gl::DrawArrays(mode, first, count);
@@ -460,8 +1059,75 @@ pub fn draw_arrays(mode: GLenum, first: GLint, count: GLsizei) -> Result<(), GLe
     Ok(())
 }
 
+pub fn draw_arrays_instanced(mode: GLenum, first: GLint, count: GLsizei,
+                             instances: GLsizei) -> Result<(), Error>
+{
+    unsafe {
+        gl::DrawArraysInstanced(mode, first, count, instances);
+        check_error!();
+    }
+    Ok(())
+}
+
+// Binds `ebo` for an indexed draw and returns its index count.
+fn bind_indices<I: IndexType>(ebo: &EBORef<I>) -> Result<GLsizei, Error>
+{
+    ebo.bind()?;
+    Ok(ebo.len() as GLsizei)
+}
+
+/// Draws every index in `ebo`. This binds `ebo`, which makes it the
+/// element buffer of the bound vertex array.
+pub fn draw_elements<I: IndexType>(mode: GLenum, ebo: &EBORef<I>) -> Result<(), Error>
+{
+    let count = bind_indices(ebo)?;
+    unsafe {
+        gl::DrawElements(mode, count, I::get_gl_type(), ::std::ptr::null());
+        check_error!();
+    }
+    Ok(())
+}
+
+pub fn draw_elements_instanced<I: IndexType>(mode: GLenum, ebo: &EBORef<I>,
+                                             instances: GLsizei) -> Result<(), Error>
+{
+    let count = bind_indices(ebo)?;
+    unsafe {
+        gl::DrawElementsInstanced(mode, count, I::get_gl_type(), ::std::ptr::null(),
+                                  instances);
+        check_error!();
+    }
+    Ok(())
+}
+
+/// Like `draw_elements`, with `base_vertex` added to every index.
+pub fn draw_elements_base_vertex<I: IndexType>(mode: GLenum, ebo: &EBORef<I>,
+                                               base_vertex: GLint) -> Result<(), Error>
+{
+    let count = bind_indices(ebo)?;
+    unsafe {
+        gl::DrawElementsBaseVertex(mode, count, I::get_gl_type(), ::std::ptr::null(),
+                                   base_vertex);
+        check_error!();
+    }
+    Ok(())
+}
+
+/// Like `draw_elements`, promising GL that every index is in
+/// `start..=end`.
+pub fn draw_range_elements<I: IndexType>(mode: GLenum, ebo: &EBORef<I>,
+                                         start: GLuint, end: GLuint) -> Result<(), Error>
+{
+    let count = bind_indices(ebo)?;
+    unsafe {
+        gl::DrawRangeElements(mode, start, end, count, I::get_gl_type(), ::std::ptr::null());
+        check_error!();
+    }
+    Ok(())
+}
+
 pub fn clear_color(red: GLfloat, green: GLfloat, blue: GLfloat, alpha: GLfloat) ->
-    Result<(), GLenum>
+    Result<(), Error>
 {
     unsafe {
         gl::ClearColor(red, green, blue, alpha);
@@ -470,7 +1136,7 @@ pub fn clear_color(red: GLfloat, green: GLfloat, blue: GLfloat, alpha: GLfloat)
     Ok(())
 }
 
-pub fn clear(mask: GLbitfield) -> Result<(), GLenum>
+pub fn clear(mask: GLbitfield) -> Result<(), Error>
 {
     unsafe {
         gl::Clear(mask);
@@ -478,3 +1144,573 @@ pub fn clear(mask: GLbitfield) -> Result<(), GLenum>
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::{Arg, Mock};
+    use shader::{Program, ShaderSrc};
+    use texture::TextureRef;
+    use uniform::UniformEnum;
+
+    #[repr(C)]
+    #[derive(Copy,Clone)]
+    struct Vertex {
+        pos: [GLfloat; 3],
+        uv:  [GLfloat; 2],
+    }
+
+    unsafe impl Pod for Vertex {}
+
+    implement_vertex_attrib!(Vertex, pos, uv);
+
+    fn program(mock: &Mock) -> (Program, GLuint)
+    {
+        let p  = Program::new(ShaderSrc { vertex: "", fragment: "" }).unwrap();
+        let id = mock.objects().iter()
+            .find(|o| o.0 == "glCreateProgram").unwrap().1;
+        mock.clear_calls();
+        (p, id)
+    }
+
+    #[test]
+    fn implement_vertex_attrib_computes_layout()
+    {
+        assert_eq!(Vertex::names(), &["pos", "uv"]);
+        assert_eq!(Vertex::sizes(), &[3, 2]);
+        assert_eq!(Vertex::types(), &[gl::FLOAT, gl::FLOAT]);
+        assert_eq!(Vertex::stride(), 20);
+        assert_eq!(Vertex::pointers(), &[::std::ptr::null(), 12 as *const GLvoid]);
+    }
+
+    // Written out by hand, with only the methods that have no default.
+    struct HandWritten;
+
+    impl VertexAttribFields for HandWritten {
+        fn names() -> &'static [&'static str] { &["pos", "id"] }
+        fn sizes() -> &'static [GLint] { &[2, 1] }
+        fn types() -> &'static [GLenum] { &[gl::FLOAT, gl::INT] }
+        fn normalizeds() -> &'static [GLboolean] { &[gl::FALSE, gl::FALSE] }
+        fn stride() -> GLsizei { 12 }
+        fn pointers() -> &'static [*const GLvoid]
+        {
+            &[::std::ptr::null(), 8 as *const GLvoid]
+        }
+    }
+
+    #[test]
+    fn kinds_default_to_those_of_the_types()
+    {
+        let kinds = attrib_columns::<HandWritten>(&[Some(0), Some(1)]).unwrap()
+            .iter()
+            .map(|c| c.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(kinds, vec![VertexAttribKind::Float, VertexAttribKind::Integer]);
+    }
+
+    struct Packed10;
+
+    impl VertexAttribFields for Packed10 {
+        fn names() -> &'static [&'static str] { &["fixed", "normal", "basis"] }
+        fn sizes() -> &'static [GLint] { &[2, 4, 4] }
+        fn columns() -> &'static [GLint] { &[1, 1, 2] }
+        fn types() -> &'static [GLenum]
+        {
+            &[gl::FIXED, gl::INT_2_10_10_10_REV, gl::INT_2_10_10_10_REV]
+        }
+        fn normalizeds() -> &'static [GLboolean] { &[gl::FALSE, gl::TRUE, gl::TRUE] }
+        fn stride() -> GLsizei { 16 }
+        fn pointers() -> &'static [*const GLvoid]
+        {
+            &[::std::ptr::null(), 8 as *const GLvoid, 12 as *const GLvoid]
+        }
+    }
+
+    #[test]
+    fn only_matrices_need_the_size_of_their_type()
+    {
+        assert_eq!(size_of_gl_type(gl::FIXED), Some(4));
+        assert_eq!(size_of_gl_type(gl::UNSIGNED_INT_2_10_10_10_REV), None);
+        let columns = attrib_columns::<Packed10>(&[Some(0), Some(1), None]).unwrap();
+        assert_eq!(columns.iter().map(|c| (c.location, c.offset)).collect::<Vec<_>>(),
+                   vec![(0, 0), (1, 8)]);
+        match attrib_columns::<Packed10>(&[Some(0), Some(1), Some(2)]) {
+            Err(Error::Validation(msg)) => assert!(msg.contains("`basis`"), "{}", msg),
+            r => panic!("unexpected result: {:?}", r.map(|c| c.len())),
+        }
+    }
+
+    #[test]
+    fn setup_vertex_attrib_points_each_attribute()
+    {
+        let mock = Mock::install();
+        let (program, id) = program(&mock);
+        mock.set_attribute_location("pos", 2);
+        mock.set_attribute_location("uv", 0);
+        setup_vertex_attrib::<Vertex>(&program).unwrap();
+        assert_eq!(mock.calls(), vec![
+            gl_call!("glGetAttribLocation", id, "pos"),
+            gl_call!("glGetAttribLocation", id, "uv"),
+            gl_call!("glVertexAttribPointer", 2u32, 3, gl::FLOAT, gl::FALSE, 20, Arg::Ptr(0)),
+            gl_call!("glEnableVertexAttribArray", 2u32),
+            gl_call!("glVertexAttribDivisor", 2u32, 0u32),
+            gl_call!("glVertexAttribPointer", 0u32, 2, gl::FLOAT, gl::FALSE, 20, Arg::Ptr(12)),
+            gl_call!("glEnableVertexAttribArray", 0u32),
+            gl_call!("glVertexAttribDivisor", 0u32, 0u32),
+        ]);
+    }
+
+    #[repr(C)]
+    struct Skinned {
+        joints:  [GLubyte; 4],
+        weights: [Half; 4],
+        id:      GLuint,
+        precise: [GLdouble; 2],
+    }
+
+    implement_vertex_attrib!(Skinned, joints, weights, id, precise);
+
+    #[test]
+    fn setup_vertex_attrib_picks_pointer_function_by_type()
+    {
+        let mock = Mock::install();
+        let (program, _) = program(&mock);
+        setup_vertex_attrib::<Skinned>(&program).unwrap();
+        let pointers = mock.calls().into_iter()
+            .filter(|c| c.name.ends_with("Pointer"))
+            .collect::<Vec<_>>();
+        assert_eq!(pointers, vec![
+            gl_call!("glVertexAttribIPointer", 0u32, 4, gl::UNSIGNED_BYTE, 32, Arg::Ptr(0)),
+            gl_call!("glVertexAttribPointer", 1u32, 4, gl::HALF_FLOAT, gl::FALSE, 32,
+                     Arg::Ptr(4)),
+            gl_call!("glVertexAttribIPointer", 2u32, 1, gl::UNSIGNED_INT, 32, Arg::Ptr(12)),
+            gl_call!("glVertexAttribLPointer", 3u32, 2, gl::DOUBLE, 32, Arg::Ptr(16)),
+        ]);
+    }
+
+    #[repr(C)]
+    struct Packed {
+        pos:   [GLfloat; 3],
+        color: [GLubyte; 4],
+    }
+
+    implement_vertex_attrib!(Packed {
+        pos:   "a_position", false, auto, 5;
+        color: "a_color", true, Float, auto;
+    });
+
+    #[test]
+    fn implement_vertex_attrib_applies_field_options()
+    {
+        let mock = Mock::install();
+        let (program, id) = program(&mock);
+        mock.set_attribute_location("a_color", 1);
+        setup_vertex_attrib::<Packed>(&program).unwrap();
+        assert_eq!(mock.calls(), vec![
+            gl_call!("glGetAttribLocation", id, "a_color"),
+            gl_call!("glVertexAttribPointer", 5u32, 3, gl::FLOAT, gl::FALSE, 16, Arg::Ptr(0)),
+            gl_call!("glEnableVertexAttribArray", 5u32),
+            gl_call!("glVertexAttribDivisor", 5u32, 0u32),
+            gl_call!("glVertexAttribPointer", 1u32, 4, gl::UNSIGNED_BYTE, gl::TRUE, 16,
+                     Arg::Ptr(12)),
+            gl_call!("glEnableVertexAttribArray", 1u32),
+            gl_call!("glVertexAttribDivisor", 1u32, 0u32),
+        ]);
+    }
+
+    #[repr(C)]
+    struct Instance {
+        model: [[GLfloat; 4]; 3],
+        tint:  [GLfloat; 3],
+    }
+
+    implement_vertex_attrib!(Instance, model, tint);
+
+    #[test]
+    fn matrix_attributes_take_a_location_per_column()
+    {
+        let mock = Mock::install();
+        let (program, _) = program(&mock);
+        mock.set_attribute_location("model", 2);
+        mock.set_attribute_location("tint", 0);
+        setup_vertex_attrib::<Instance>(&program).unwrap();
+        let pointers = mock.calls().into_iter()
+            .filter(|c| c.name == "glVertexAttribPointer")
+            .collect::<Vec<_>>();
+        assert_eq!(pointers, vec![
+            gl_call!("glVertexAttribPointer", 2u32, 4, gl::FLOAT, gl::FALSE, 60, Arg::Ptr(0)),
+            gl_call!("glVertexAttribPointer", 3u32, 4, gl::FLOAT, gl::FALSE, 60, Arg::Ptr(16)),
+            gl_call!("glVertexAttribPointer", 4u32, 4, gl::FLOAT, gl::FALSE, 60, Arg::Ptr(32)),
+            gl_call!("glVertexAttribPointer", 0u32, 3, gl::FLOAT, gl::FALSE, 60, Arg::Ptr(48)),
+        ]);
+    }
+
+    #[test]
+    fn setup_instance_attrib_sets_divisor_per_column()
+    {
+        let mock = Mock::install();
+        let (program, _) = program(&mock);
+        mock.set_attribute_location("model", 1);
+        mock.set_attribute_location("tint", 5);
+        setup_instance_attrib::<Instance>(&program, 2).unwrap();
+        let divisors = mock.calls().into_iter()
+            .filter(|c| c.name == "glVertexAttribDivisor")
+            .collect::<Vec<_>>();
+        assert_eq!(divisors, vec![
+            gl_call!("glVertexAttribDivisor", 1u32, 2u32),
+            gl_call!("glVertexAttribDivisor", 2u32, 2u32),
+            gl_call!("glVertexAttribDivisor", 3u32, 2u32),
+            gl_call!("glVertexAttribDivisor", 5u32, 2u32),
+        ]);
+    }
+
+    #[test]
+    fn missing_attributes_do_not_shift_the_rest()
+    {
+        let mock = Mock::install();
+        let (program, _) = program(&mock);
+        mock.set_attribute_location("pos", -1);
+        mock.set_attribute_location("uv", 0);
+        setup_vertex_attrib::<Vertex>(&program).unwrap();
+        let pointers = mock.calls().into_iter()
+            .filter(|c| c.name == "glVertexAttribPointer")
+            .collect::<Vec<_>>();
+        assert_eq!(pointers, vec![
+            gl_call!("glVertexAttribPointer", 0u32, 2, gl::FLOAT, gl::FALSE, 20, Arg::Ptr(12)),
+        ]);
+    }
+
+    #[test]
+    fn strict_setup_rejects_missing_attributes()
+    {
+        let mock = Mock::install();
+        let (program, _) = program(&mock);
+        mock.set_attribute_location("uv", -1);
+        match setup_vertex_attrib_strict::<Vertex>(&program) {
+            Err(Error::Validation(ref e)) if e.contains("`uv`") => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn layout_locations_follow_fixed_locations_and_columns()
+    {
+        assert_eq!(layout_locations::<Instance>().unwrap(), vec![("model", 0), ("tint", 3)]);
+        assert_eq!(layout_locations::<Packed>().unwrap(),
+                   vec![("a_position", 5), ("a_color", 6)]);
+    }
+
+    #[repr(C)]
+    struct Clashing {
+        model: [[GLfloat; 4]; 3],
+        tint:  [GLfloat; 3],
+    }
+
+    implement_vertex_attrib!(Clashing {
+        model: "model", false, auto, auto;
+        tint:  "tint", false, auto, 2;
+    });
+
+    #[test]
+    fn layout_locations_reject_overlaps()
+    {
+        match layout_locations::<Clashing>() {
+            Err(Error::Validation(msg)) =>
+                assert!(msg.contains("`model` and `tint`") && msg.contains("location 2"),
+                        "{}", msg),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        let mock = Mock::install();
+        assert!(setup_vertex_layout::<Clashing>().is_err());
+        assert_eq!(mock.calls(), vec![]);
+    }
+
+    #[test]
+    fn vertex_buffer_data_sizes_the_buffer()
+    {
+        let _mock = Mock::install();
+        let vbo = VBORef::<Vertex>::new().unwrap();
+        let vertices = [Vertex { pos: [1.0, 2.0, 3.0], uv: [4.0, 5.0] }; 3];
+        vertex_buffer_data(&vbo, &vertices, BufferUsage::DynamicDraw).unwrap();
+        assert_eq!(vbo.len(), 3);
+        assert_eq!(vbo.usage(), BufferUsage::DynamicDraw);
+        assert_eq!(vbo.read_back().unwrap()[2].uv, [4.0, 5.0]);
+        vbo.update(2, &[Vertex { pos: [0.0; 3], uv: [6.0, 7.0] }]).unwrap();
+        assert_eq!(vbo.read_back().unwrap()[2].uv, [6.0, 7.0]);
+    }
+
+    #[test]
+    fn setup_vertex_layout_asks_no_program()
+    {
+        let mock = Mock::install();
+        setup_vertex_layout::<Packed>().unwrap();
+        assert_eq!(mock.calls().iter().map(|c| c.name).collect::<Vec<_>>(), vec![
+            "glVertexAttribPointer", "glEnableVertexAttribArray", "glVertexAttribDivisor",
+            "glVertexAttribPointer", "glEnableVertexAttribArray", "glVertexAttribDivisor",
+        ]);
+    }
+
+    #[test]
+    fn streams_point_into_their_own_buffers()
+    {
+        let mock = Mock::install();
+        let (program, id) = program(&mock);
+        let positions = VBORef::<[GLfloat; 3]>::new().unwrap();
+        let normals   = VBORef::<[GLfloat; 3]>::new().unwrap();
+        let joints    = VBORef::<[GLushort; 4]>::new().unwrap();
+        mock.set_attribute_location("normal", 4);
+        mock.clear_calls();
+        setup_attrib_stream(&positions, 0).unwrap();
+        setup_named_attrib_stream(&program, "normal", &normals).unwrap();
+        setup_attrib_stream(&joints, 2).unwrap();
+        assert_eq!(mock.calls(), vec![
+            gl_call!("glBindBuffer", gl::ARRAY_BUFFER, positions.id()),
+            gl_call!("glVertexAttribPointer", 0u32, 3, gl::FLOAT, gl::FALSE, 12, Arg::Ptr(0)),
+            gl_call!("glEnableVertexAttribArray", 0u32),
+            gl_call!("glVertexAttribDivisor", 0u32, 0u32),
+            gl_call!("glGetAttribLocation", id, "normal"),
+            gl_call!("glBindBuffer", gl::ARRAY_BUFFER, normals.id()),
+            gl_call!("glVertexAttribPointer", 4u32, 3, gl::FLOAT, gl::FALSE, 12, Arg::Ptr(0)),
+            gl_call!("glEnableVertexAttribArray", 4u32),
+            gl_call!("glVertexAttribDivisor", 4u32, 0u32),
+            gl_call!("glBindBuffer", gl::ARRAY_BUFFER, joints.id()),
+            gl_call!("glVertexAttribIPointer", 2u32, 4, gl::UNSIGNED_SHORT, 8, Arg::Ptr(0)),
+            gl_call!("glEnableVertexAttribArray", 2u32),
+            gl_call!("glVertexAttribDivisor", 2u32, 0u32),
+        ]);
+    }
+
+    #[test]
+    fn setup_uniforms_binds_textures_to_units()
+    {
+        let mock = Mock::install();
+        mock.add_active_uniform("color", gl::FLOAT_VEC3, 1);
+        mock.add_active_uniform("albedo", gl::SAMPLER_2D, 1);
+        let (program, _) = program(&mock);
+        let albedo = TextureRef::new(gl::TEXTURE_2D).unwrap();
+        let tex_id = mock.objects().last().unwrap().1;
+        mock.clear_calls();
+        let color = [1.0, 0.5, 0.25];
+        setup_uniforms(&uniforms!{ color: color, albedo: albedo, }, &program).unwrap();
+        assert_eq!(mock.calls(), vec![
+            gl_call!("glUniform3fv", 0, 1, Arg::Floats(vec![1.0, 0.5, 0.25])),
+            gl_call!("glActiveTexture", gl::TEXTURE0),
+            gl_call!("glBindTexture", gl::TEXTURE_2D, tex_id),
+            gl_call!("glUniform1i", 1, 0),
+        ]);
+    }
+
+    #[test]
+    fn setup_uniforms_reports_gl_errors()
+    {
+        let mock = Mock::install();
+        mock.add_active_uniform("color", gl::FLOAT_VEC3, 1);
+        let (program, _) = program(&mock);
+        mock.fail_next("glUniform3fv", gl::INVALID_OPERATION);
+        let color = [1.0, 0.5, 0.25];
+        match setup_uniforms(&uniforms!{ color: color, }, &program) {
+            Err(Error::GL { code: gl::INVALID_OPERATION, .. }) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn setup_uniforms_covers_every_kind()
+    {
+        let mock = Mock::install();
+        mock.add_active_uniform("count", gl::INT, 1);
+        mock.add_active_uniform("mask", gl::UNSIGNED_INT_VEC2, 1);
+        mock.add_active_uniform("flags", gl::BOOL_VEC3, 1);
+        mock.add_active_uniform("origin", gl::DOUBLE_VEC2, 1);
+        mock.add_active_uniform("basis", gl::FLOAT_MAT2x3, 1);
+        mock.add_active_uniform("lights[0]", gl::FLOAT_VEC4, 4);
+        let (program, _) = program(&mock);
+        let lights = [[1.0, 0.0, 0.0, 1.0], [0.0, 1.0, 0.0, 1.0]];
+        let origin = [0.5, -0.5];
+        let basis  = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        setup_uniforms(&uniforms!{
+            count:  2i32,
+            mask:   [1u32, 3],
+            flags:  [true, false, true],
+            origin: UniformEnum::DVec2(&[origin]),
+            basis:  basis,
+            lights: &lights[..],
+        }, &program).unwrap();
+        assert_eq!(mock.calls(), vec![
+            gl_call!("glUniform1iv", 0, 1, Arg::Ints(vec![2])),
+            gl_call!("glUniform2uiv", 1, 1, Arg::Ints(vec![1, 3])),
+            gl_call!("glUniform3iv", 2, 1, Arg::Ints(vec![1, 0, 1])),
+            gl_call!("glUniform2dv", 3, 1, Arg::Doubles(vec![0.5, -0.5])),
+            gl_call!("glUniformMatrix2x3fv", 4, 1, gl::FALSE,
+                     Arg::Floats(vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0])),
+            gl_call!("glUniform4fv", 5, 2,
+                     Arg::Floats(vec![1.0, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0])),
+        ]);
+    }
+
+    #[test]
+    fn bool_arrays_are_uploaded_in_batches()
+    {
+        let mock = Mock::install();
+        mock.add_active_uniform("masks[0]", gl::BOOL_VEC3, 20);
+        // Element locations need not be consecutive.
+        mock.set_uniform_location("masks[0]", 2);
+        mock.set_uniform_location("masks[16]", 40);
+        let (program, _) = program(&mock);
+        let masks = (0..20).map(|i| [i % 2 == 0, true, false]).collect::<Vec<_>>();
+        setup_uniforms(&uniforms!{ masks: &masks[..], }, &program).unwrap();
+        let ints = |elements: ::std::ops::Range<usize>| Arg::Ints(
+            masks[elements].iter().flatten().map(|&b| b as i64).collect());
+        // 48 ints hold 16 vec3s.
+        assert_eq!(mock.calls(), vec![
+            gl_call!("glUniform3iv", 2, 16, ints(0..16)),
+            gl_call!("glUniform3iv", 40, 4, ints(16..20)),
+        ]);
+    }
+
+    #[test]
+    fn setup_uniforms_rejects_overlong_arrays()
+    {
+        let mock = Mock::install();
+        mock.add_active_uniform("weights[0]", gl::FLOAT, 2);
+        let (program, _) = program(&mock);
+        let weights = [0.25f32, 0.5, 0.25];
+        match setup_uniforms(&uniforms!{ weights: &weights[..], }, &program) {
+            Err(Error::Validation(msg)) =>
+                assert!(msg.contains("room for 2") && msg.contains("given 3"), "{}", msg),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn setup_uniforms_rejects_mismatched_types()
+    {
+        let mock = Mock::install();
+        mock.add_active_uniform("mvp", gl::FLOAT_MAT4, 1);
+        mock.add_active_uniform("shadow", gl::SAMPLER_CUBE, 1);
+        let (program, _) = program(&mock);
+        let color = [1.0, 0.5, 0.25];
+        match setup_uniforms(&uniforms!{ mvp: color, }, &program) {
+            Err(Error::Validation(msg)) =>
+                assert!(msg.contains("`mvp`") && msg.contains("declared mat4") &&
+                        msg.contains("given vec3"), "{}", msg),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        let flat = TextureRef::new(gl::TEXTURE_2D).unwrap();
+        match setup_uniforms(&uniforms!{ shadow: flat, }, &program) {
+            Err(Error::Validation(msg)) =>
+                assert!(msg.contains("samplerCube") && msg.contains("sampler2D"), "{}", msg),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert!(!mock.calls().iter().any(|c| c.name.starts_with("glUniform")));
+    }
+
+    #[test]
+    fn unknown_uniforms_follow_the_policy()
+    {
+        use error::{set_drop_error_policy, take_drop_errors, DropErrorPolicy};
+        use uniform::{set_unknown_uniform_policy, UnknownUniformPolicy};
+        let mock = Mock::install();
+        let (program, _) = program(&mock);
+        let color = [1.0, 0.5, 0.25];
+        setup_uniforms(&uniforms!{ color: color, }, &program).unwrap();
+        assert_eq!(mock.calls(), vec![]);
+        set_unknown_uniform_policy(UnknownUniformPolicy::Error);
+        match setup_uniforms(&uniforms!{ color: color, }, &program) {
+            Err(Error::Validation(msg)) => assert!(msg.contains("`color`"), "{}", msg),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        set_unknown_uniform_policy(UnknownUniformPolicy::Warn);
+        set_drop_error_policy(DropErrorPolicy::Collect);
+        setup_uniforms(&uniforms!{ color: color, }, &program).unwrap();
+        match take_drop_errors().as_slice() {
+            [Error::Validation(msg)] => assert!(msg.contains("`color`"), "{}", msg),
+            errs => panic!("unexpected warnings: {:?}", errs),
+        }
+        set_drop_error_policy(DropErrorPolicy::Log);
+        set_unknown_uniform_policy(UnknownUniformPolicy::Ignore);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn check_error_as_string_reports_the_error_name()
+    {
+        fn enable_as_string(cap: GLenum) -> Result<(), String>
+        {
+            unsafe {
+                gl::Enable(cap);
+                check_error_as_string!();
+            }
+            Ok(())
+        }
+        let mock = Mock::install();
+        mock.fail_next("glEnable", gl::INVALID_ENUM);
+        assert_eq!(enable_as_string(0), Err("GL_INVALID_ENUM".to_string()));
+    }
+
+    #[test]
+    fn build_cubemap_rejects_mismatched_faces()
+    {
+        let mock = Mock::install();
+        let mut faces = vec![image::RgbaImage::new(4, 4); 6];
+        faces[3] = image::RgbaImage::new(2, 2);
+        match build_cubemap(&faces) {
+            Err(Error::Validation(msg)) => assert!(msg.contains("face 3"), "{}", msg),
+            r => panic!("unexpected result: {:?}", r.map(|_| ())),
+        }
+        assert_eq!(mock.calls(), vec![]);
+    }
+
+    #[test]
+    fn build_cubemap_uploads_every_face()
+    {
+        let mock = Mock::install();
+        let faces = vec![image::RgbaImage::new(4, 4); 6];
+        build_cubemap(&faces).unwrap();
+        let targets = mock.calls().into_iter()
+            .filter(|c| c.name == "glTexImage2D")
+            .map(|c| c.args[0].clone())
+            .collect::<Vec<_>>();
+        let expected = (0..6)
+            .map(|i| Arg::from(gl::TEXTURE_CUBE_MAP_POSITIVE_X + i))
+            .collect::<Vec<_>>();
+        assert_eq!(targets, expected);
+    }
+
+    #[test]
+    fn draw_arrays_instanced_passes_the_instance_count()
+    {
+        let mock = Mock::install();
+        draw_arrays_instanced(gl::TRIANGLE_STRIP, 2, 4, 3).unwrap();
+        assert_eq!(mock.calls(), vec![
+            gl_call!("glDrawArraysInstanced", gl::TRIANGLE_STRIP, 2, 4, 3),
+        ]);
+        mock.fail_next("glDrawArraysInstanced", gl::INVALID_VALUE);
+        match draw_arrays_instanced(gl::TRIANGLE_STRIP, 0, 4, -1) {
+            Err(Error::GL { code: gl::INVALID_VALUE, .. }) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn draw_elements_counts_and_types_indices()
+    {
+        let mock = Mock::install();
+        let ebo = EBORef::new_with_data(&[0u16, 1, 2, 2, 1, 3], BufferUsage::StaticDraw)
+            .unwrap();
+        mock.clear_calls();
+        draw_elements(gl::TRIANGLES, &ebo).unwrap();
+        draw_range_elements(gl::TRIANGLES, &ebo, 0, 3).unwrap();
+        draw_elements_base_vertex(gl::TRIANGLES, &ebo, 4).unwrap();
+        assert_eq!(mock.calls(), vec![
+            gl_call!("glBindBuffer", gl::ELEMENT_ARRAY_BUFFER, ebo.id()),
+            gl_call!("glDrawElements", gl::TRIANGLES, 6, gl::UNSIGNED_SHORT, Arg::Ptr(0)),
+            gl_call!("glBindBuffer", gl::ELEMENT_ARRAY_BUFFER, ebo.id()),
+            gl_call!("glDrawRangeElements", gl::TRIANGLES, 0u32, 3u32, 6,
+                     gl::UNSIGNED_SHORT, Arg::Ptr(0)),
+            gl_call!("glBindBuffer", gl::ELEMENT_ARRAY_BUFFER, ebo.id()),
+            gl_call!("glDrawElementsBaseVertex", gl::TRIANGLES, 6, gl::UNSIGNED_SHORT,
+                     Arg::Ptr(0), 4),
+        ]);
+    }
+}
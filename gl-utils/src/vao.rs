@@ -1,6 +1,11 @@
 use gl;
 use gl::types::*;
 use util::*;
+use buffer::Pod;
+use vbo::VBORef;
+use buffer::BufferTarget;
+use context::{Slot, changing};
+use error::{Error, clear_pending_errors, log_pending_errors, report_drop_error};
 use std::rc::Rc;
 
 #[derive(Debug,Clone)]
@@ -14,7 +19,7 @@ struct VAOHandle {
 }
 
 impl VAOHandle {
-    fn new() -> Result<Self, GLenum>
+    fn new() -> Result<Self, Error>
     {
         unsafe {
             let mut vao = VAOHandle { id: 0 };
@@ -23,44 +28,212 @@ impl VAOHandle {
             Ok(vao)
         }
     }
+
+    fn delete(&mut self) -> Result<(), Error>
+    {
+        if self.id == 0 {
+            return Ok(())
+        }
+        unsafe {
+            clear_pending_errors();
+            gl::DeleteVertexArrays(1, &self.id);
+            self.id = 0;
+            check_error!();
+            Ok(())
+        }
+    }
+}
+
+// The element array binding is part of the vertex array.
+fn changing_vertex_array()
+{
+    changing(Slot::VertexArray);
+    changing(Slot::Buffer(BufferTarget::ElementArray));
 }
 
 impl VAORef {
-    pub fn new() -> Result<Self, GLenum>
+    pub fn new() -> Result<Self, Error>
     {
         let h = VAOHandle::new()?;
         Ok(VAORef { handle: Rc::new(h) })
     }
 
-    pub fn bind(&self) -> Result<(), GLenum>
+    /// Deletes the vertex array, returning any error instead of handing it
+    /// to the drop error policy. Clones share the vertex array, so while
+    /// others are alive this only releases this one and the last clone
+    /// deletes it.
+    pub fn delete(self) -> Result<(), Error>
+    {
+        match Rc::try_unwrap(self.handle) {
+            Ok(mut h) => h.delete(),
+            Err(_)    => Ok(()),
+        }
+    }
+
+    pub fn id(&self) -> GLuint
+    {
+        self.handle.id
+    }
+
+    pub fn bind(&self) -> Result<(), Error>
     {
         unsafe {
+            changing_vertex_array();
             gl::BindVertexArray(self.handle.id);
             check_error!();
             Ok(())
         }
     }
 
-    pub fn unbind() -> Result<(), GLenum>
+    pub fn unbind() -> Result<(), Error>
     {
         unsafe {
+            changing_vertex_array();
             gl::BindVertexArray(0);
             check_error!();
             Ok(())
         }
     }
+
+    /// Describes the attributes of `VAF`, at their `layout_locations`, as
+    /// read from vertex buffer binding point `binding`. Buffers can then
+    /// be swapped in with `bind_vertex_buffer` without repeating this.
+    ///
+    /// Needs GL 4.3 or ARB_vertex_attrib_binding, and leaves this vertex
+    /// array bound.
+    pub fn attrib_format<VAF: VertexAttribFields>(&self, binding: GLuint) -> Result<(), Error>
+    {
+        let locations = layout_locations::<VAF>()?
+            .into_iter()
+            .map(|(_, l)| Some(l))
+            .collect::<Vec<_>>();
+        self.bind()?;
+        for c in attrib_columns::<VAF>(&locations)? {
+            let offset = c.offset as GLuint;
+            unsafe {
+                match c.kind {
+                    VertexAttribKind::Float   =>
+                        gl::VertexAttribFormat(c.location, c.size, c.gl_type, c.normalized,
+                                               offset),
+                    VertexAttribKind::Integer =>
+                        gl::VertexAttribIFormat(c.location, c.size, c.gl_type, offset),
+                    VertexAttribKind::Double  =>
+                        gl::VertexAttribLFormat(c.location, c.size, c.gl_type, offset),
+                }
+                check_error!();
+                gl::VertexAttribBinding(c.location, binding);
+                check_error!();
+                gl::EnableVertexAttribArray(c.location);
+                check_error!();
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes binding point `binding` read `vbo` as a buffer of `VAF`, the
+    /// layout given to `attrib_format`, starting at vertex `first`. The
+    /// stride comes from `VAF`, so `vbo` may also be an untyped `VBORef`.
+    /// Leaves this vertex array bound.
+    pub fn bind_vertex_buffer<VAF: VertexAttribFields>(&self, binding: GLuint,
+                                                       vbo: &VBORef<impl Pod>, first: usize)
+        -> Result<(), Error>
+    {
+        let stride = VAF::stride() as usize;
+        self.bind()?;
+        unsafe {
+            gl::BindVertexBuffer(binding, vbo.id(), (first * stride) as GLintptr,
+                                 stride as GLsizei);
+            check_error!();
+            Ok(())
+        }
+    }
+
+    /// Advances binding point `binding` once every `divisor` instances
+    /// instead of once per vertex; 0 goes back to per vertex. Leaves this
+    /// vertex array bound.
+    pub fn binding_divisor(&self, binding: GLuint, divisor: GLuint) -> Result<(), Error>
+    {
+        self.bind()?;
+        unsafe {
+            gl::VertexBindingDivisor(binding, divisor);
+            check_error!();
+            Ok(())
+        }
+    }
 }
+
 impl Drop for VAOHandle {
     fn drop(&mut self)
     {
-        unsafe {
-            gl::DeleteBuffers(1, &mut self.id);
-            // We should use check_error here, but the type of drop
-            // won't allow it. We have to panic instead.
-            let err = gl::GetError();
-            if err != gl::NO_ERROR {
-                panic!("VAO DeleteBuffers returned: {}", gl_error_str(err));
-            }
+        log_pending_errors();
+        if let Err(e) = self.delete() {
+            report_drop_error(e);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buffer::BufferUsage;
+    use mock::Mock;
+
+    #[test]
+    fn drop_deletes_vertex_array()
+    {
+        let mock = Mock::install();
+        let id = VAORef::new().unwrap().handle.id;
+        assert_eq!(mock.objects(),
+                   vec![("glGenVertexArrays", id), ("glDeleteVertexArrays", id)]);
+    }
+
+    #[repr(C)]
+    #[derive(Copy,Clone)]
+    struct Vertex {
+        pos:   [GLfloat; 3],
+        joint: GLuint,
+    }
+
+    unsafe impl Pod for Vertex {}
+
+    implement_vertex_attrib!(Vertex, pos, joint);
+
+    #[test]
+    fn attrib_format_then_swap_buffers()
+    {
+        let mock = Mock::install();
+        let vao = VAORef::new().unwrap();
+        let (a, b) = (VBORef::<Vertex>::new().unwrap(), VBORef::<Vertex>::new().unwrap());
+        mock.clear_calls();
+        vao.attrib_format::<Vertex>(1).unwrap();
+        vao.bind_vertex_buffer::<Vertex>(1, &a, 0).unwrap();
+        vao.bind_vertex_buffer::<Vertex>(1, &b, 2).unwrap();
+        assert_eq!(mock.calls(), vec![
+            gl_call!("glBindVertexArray", vao.id()),
+            gl_call!("glVertexAttribFormat", 0u32, 3, gl::FLOAT, gl::FALSE, 0u32),
+            gl_call!("glVertexAttribBinding", 0u32, 1u32),
+            gl_call!("glEnableVertexAttribArray", 0u32),
+            gl_call!("glVertexAttribIFormat", 1u32, 1, gl::UNSIGNED_INT, 12u32),
+            gl_call!("glVertexAttribBinding", 1u32, 1u32),
+            gl_call!("glEnableVertexAttribArray", 1u32),
+            gl_call!("glBindVertexArray", vao.id()),
+            gl_call!("glBindVertexBuffer", 1u32, a.id(), 0, 16),
+            gl_call!("glBindVertexArray", vao.id()),
+            gl_call!("glBindVertexBuffer", 1u32, b.id(), 32, 16),
+        ]);
+    }
+
+    #[test]
+    fn untyped_buffers_bind_with_the_stride_of_the_layout()
+    {
+        let mock = Mock::install();
+        let vao = VAORef::new().unwrap();
+        let bytes: VBORef = VBORef::new_with_data(&[0; 64], BufferUsage::StaticDraw).unwrap();
+        mock.clear_calls();
+        vao.bind_vertex_buffer::<Vertex>(0, &bytes, 3).unwrap();
+        assert_eq!(mock.calls(), vec![
+            gl_call!("glBindVertexArray", vao.id()),
+            gl_call!("glBindVertexBuffer", 0u32, bytes.id(), 48, 16),
+        ]);
+    }
+}
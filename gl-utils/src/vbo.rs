@@ -1,66 +1,94 @@
 use gl;
-use gl::types::*;
-use util::*;
-use std::rc::Rc;
+use buffer::{Buffer, BufferTarget, BufferUsage, Pod};
+use context::{Slot, changing};
+use error::Error;
+use std::fmt;
+use std::ops::Deref;
 
-#[derive(Debug,Clone)]
-pub struct VBORef {
-    handle: Rc<VBOHandle>,
-}
+/// A `Buffer` of vertices, bound to `ARRAY_BUFFER`. Untyped vertex data
+/// can be kept as bytes, the default.
+pub struct VBORef<T: Pod = u8>(Buffer<T>);
+
+impl<T: Pod> VBORef<T> {
+    pub fn new() -> Result<Self, Error>
+    {
+        Ok(VBORef(Buffer::new(BufferTarget::Array)?))
+    }
 
-#[derive(Debug)]
-struct VBOHandle {
-    id: GLuint,
+    pub fn new_with_data(data: &[T], usage: BufferUsage) -> Result<Self, Error>
+    {
+        Ok(VBORef(Buffer::new_with_data(BufferTarget::Array, data, usage)?))
+    }
+
+    /// Deletes the GL object, reporting any error instead of deferring to
+    /// the drop error policy. If other clones of this reference are still
+    /// alive only this reference is released and the object lives on.
+    pub fn delete(self) -> Result<(), Error>
+    {
+        self.0.delete()
+    }
 }
 
-impl VBOHandle {
-    fn new() -> Result<Self, GLenum>
+// Not generic, so that `VBORef::unbind()` needs no type annotation.
+impl VBORef {
+    pub fn unbind() -> Result<(), Error>
     {
         unsafe {
-            let mut vbo = VBOHandle { id: 0 };
-            gl::GenBuffers(1, &mut vbo.id);
+            changing(Slot::Buffer(BufferTarget::Array));
+            gl::BindBuffer(BufferTarget::Array.to_gl(), 0);
             check_error!();
-            Ok(vbo)
+            Ok(())
         }
     }
 }
 
-impl VBORef {
-    pub fn new() -> Result<Self, GLenum>
+impl<T: Pod> Deref for VBORef<T> {
+    type Target = Buffer<T>;
+
+    fn deref(&self) -> &Buffer<T>
     {
-        let h = VBOHandle::new()?;
-        Ok(VBORef { handle: Rc::new(h) })
+        &self.0
     }
+}
 
-    pub fn bind(&self, target: GLenum) -> Result<(), GLenum>
+impl<T: Pod> Clone for VBORef<T> {
+    fn clone(&self) -> Self
     {
-        unsafe {
-            gl::BindBuffer(target, self.handle.id);
-            check_error!();
-            Ok(())
-        }
+        VBORef(self.0.clone())
     }
+}
 
-    pub fn unbind(target: GLenum) -> Result<(), GLenum>
+impl<T: Pod> fmt::Debug for VBORef<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
     {
-        unsafe {
-            gl::BindBuffer(target, 0);
-            check_error!();
-            Ok(())
-        }
+        f.debug_tuple("VBORef").field(&self.0).finish()
     }
 }
-impl Drop for VBOHandle {
-    fn drop(&mut self)
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::Mock;
+
+    #[test]
+    fn drop_deletes_buffer()
     {
-        unsafe {
-            gl::DeleteBuffers(1, &mut self.id);
-            // We should use check_error here, but the type of drop
-            // won't allow it. We have to panic instead.
-            let err = gl::GetError();
-            if err != gl::NO_ERROR {
-                panic!("VBO DeleteBuffers returned: {}", gl_error_str(err));
-            }
-        }
+        let mock = Mock::install();
+        let id = VBORef::<u8>::new().unwrap().id();
+        assert_eq!(mock.objects(),
+                   vec![("glGenBuffers", id), ("glDeleteBuffers", id)]);
+    }
+
+    #[test]
+    fn delete_waits_for_last_reference()
+    {
+        let mock = Mock::install();
+        let vbo = VBORef::<u8>::new().unwrap();
+        let id  = vbo.id();
+        vbo.clone().delete().unwrap();
+        assert_eq!(mock.objects(), vec![("glGenBuffers", id)]);
+        vbo.delete().unwrap();
+        assert_eq!(mock.objects(),
+                   vec![("glGenBuffers", id), ("glDeleteBuffers", id)]);
     }
 }
@@ -0,0 +1,464 @@
+use gl;
+use gl::types::*;
+use util::*;
+use buffer::{BufferHandle, Pod};
+use ebo::{EBORef, IndexType};
+use error::Error;
+use shader::Program;
+use vao::VAORef;
+use vbo::VBORef;
+use std::rc::Rc;
+
+/// One column of an attribute as a `VertexArray` was set up to read it.
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub struct AttribBinding {
+    pub column:  AttribColumn,
+    /// The buffer it is read from.
+    pub buffer:  GLuint,
+    pub stride:  GLsizei,
+    /// 0 for per vertex, otherwise advanced every `divisor` instances.
+    pub divisor: GLuint,
+}
+
+/// A vertex array that keeps the buffers it reads alive and remembers
+/// the layout it was set up with. Made with a `VertexArrayBuilder`.
+#[derive(Debug,Clone)]
+pub struct VertexArray {
+    vao:        VAORef,
+    buffers:    Vec<Rc<BufferHandle>>,
+    indices:    Option<Rc<BufferHandle>>,
+    index_type: Option<GLenum>,
+    layout:     Vec<AttribBinding>,
+}
+
+impl VertexArray {
+    /// Starts a vertex array whose attributes go at their
+    /// `layout_locations`, for programs linked with those locations.
+    pub fn builder() -> Result<VertexArrayBuilder<'static>, Error>
+    {
+        VertexArrayBuilder::new(None)
+    }
+
+    /// Starts a vertex array whose attributes go wherever `program` has
+    /// them. Attributes it does not use are skipped.
+    pub fn builder_for(program: &Program) -> Result<VertexArrayBuilder<'_>, Error>
+    {
+        VertexArrayBuilder::new(Some(program))
+    }
+
+    pub fn vao(&self) -> &VAORef
+    {
+        &self.vao
+    }
+
+    pub fn id(&self) -> GLuint
+    {
+        self.vao.id()
+    }
+
+    pub fn bind(&self) -> Result<(), Error>
+    {
+        self.vao.bind()
+    }
+
+    /// Every enabled attribute column, in the order they were set up.
+    pub fn layout(&self) -> &[AttribBinding]
+    {
+        &self.layout
+    }
+
+    /// The ids of the attached vertex buffers, in the order they were
+    /// added.
+    pub fn buffers(&self) -> Vec<GLuint>
+    {
+        self.buffers.iter().map(|b| b.id).collect()
+    }
+
+    pub fn index_buffer(&self) -> Option<GLuint>
+    {
+        self.indices.as_ref().map(|b| b.id)
+    }
+
+    /// The GL type of the attached indices, if any.
+    pub fn index_type(&self) -> Option<GLenum>
+    {
+        self.index_type
+    }
+
+    // The GL type of the attached indices, bound with the vertex array.
+    fn bind_indexed(&self) -> Result<GLenum, Error>
+    {
+        let index_type = self.index_type.ok_or_else(|| Error::Validation(format!(
+            "vertex array {} has no index buffer", self.id())))?;
+        self.bind()?;
+        Ok(index_type)
+    }
+
+    /// Draws the first `count` indices of the attached index buffer. It is
+    /// bound along with the vertex array, so it is not bound again.
+    pub fn draw_elements(&self, mode: GLenum, count: GLsizei) -> Result<(), Error>
+    {
+        let index_type = self.bind_indexed()?;
+        unsafe {
+            gl::DrawElements(mode, count, index_type, ::std::ptr::null());
+            check_error!();
+        }
+        Ok(())
+    }
+
+    /// Like `draw_elements`, `instances` times.
+    pub fn draw_elements_instanced(&self, mode: GLenum, count: GLsizei, instances: GLsizei)
+        -> Result<(), Error>
+    {
+        let index_type = self.bind_indexed()?;
+        unsafe {
+            gl::DrawElementsInstanced(mode, count, index_type, ::std::ptr::null(), instances);
+            check_error!();
+        }
+        Ok(())
+    }
+
+    // Reads `columns` from `vbo`, with the vertex array already bound.
+    fn attach<T: Pod>(&mut self, vbo: &VBORef<T>, columns: Vec<AttribColumn>,
+                      divisor: GLuint) -> Result<(), Error>
+    {
+        let stride = ::std::mem::size_of::<T>() as GLsizei;
+        vbo.bind()?;
+        setup_attrib_columns(&columns, stride, divisor)?;
+        let buffer = vbo.id();
+        self.layout.extend(columns.into_iter().map(|column| AttribBinding {
+            column, buffer, stride, divisor,
+        }));
+        self.buffers.push(vbo.handle.clone());
+        Ok(())
+    }
+
+    /// Adds the attributes of `J` that `program` uses, read from `vbo` one
+    /// `J` per `divisor` instances, and keeps `vbo` alive with the rest.
+    /// Leaves no vertex array bound.
+    pub fn add_instances<J>(&mut self, program: &Program, vbo: &VBORef<J>, divisor: GLuint)
+        -> Result<(), Error>
+        where J: VertexAttribFields + Pod
+    {
+        let columns = attrib_columns::<J>(&program_locations::<J>(program, false)?)?;
+        self.bind()?;
+        let attached = self.attach(vbo, columns, divisor);
+        VAORef::unbind()?;
+        attached
+    }
+
+    /// Checks that no location is fed twice, that every named attribute
+    /// is where `program` expects it, and that every active attribute of
+    /// `program` is fed.
+    pub fn validate(&self, program: &Program) -> Result<(), Error>
+    {
+        for (i, b) in self.layout.iter().enumerate() {
+            if let Some(other) = self.layout[..i].iter()
+                .find(|o| o.column.location == b.column.location)
+            {
+                return Err(Error::Validation(format!(
+                    "`{}` and `{}` both feed location {}",
+                    other.column.name, b.column.name, b.column.location)))
+            }
+        }
+        for (i, b) in self.layout.iter().enumerate() {
+            let name = b.column.name;
+            // Only the first column of each attribute has its location.
+            if name.is_empty() || self.layout[..i].iter().any(|o| o.column.name == name) {
+                continue
+            }
+            match program.attribute(name).map_or(-1, |a| a.location) {
+                -1 => (),
+                l if l as GLuint == b.column.location => (),
+                l  => return Err(Error::Validation(format!(
+                    "program {} has `{}` at location {}, but it is fed to location {}",
+                    program.id(), name, l, b.column.location))),
+            }
+        }
+        for a in program.active_attributes() {
+            // Built-ins such as `gl_VertexID` have no location.
+            if a.location >= 0 &&
+                !self.layout.iter().any(|b| b.column.location == a.location as GLuint)
+            {
+                return Err(Error::Validation(format!(
+                    "attribute `{}` of program {} at location {} is not fed",
+                    a.name, program.id(), a.location)))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sets up a `VertexArray` one buffer at a time. The vertex array stays
+/// bound until `build`, or until a step fails.
+#[derive(Debug)]
+pub struct VertexArrayBuilder<'a> {
+    program: Option<&'a Program>,
+    array:   VertexArray,
+}
+
+impl<'a> VertexArrayBuilder<'a> {
+    fn new(program: Option<&'a Program>) -> Result<Self, Error>
+    {
+        let vao = VAORef::new()?;
+        vao.bind()?;
+        Ok(VertexArrayBuilder {
+            program,
+            array: VertexArray {
+                vao,
+                buffers:    Vec::new(),
+                indices:    None,
+                index_type: None,
+                layout:     Vec::new(),
+            },
+        })
+    }
+
+    fn locations<VAF: VertexAttribFields>(&self) -> Result<Vec<Option<GLuint>>, Error>
+    {
+        match self.program {
+            Some(program) => program_locations::<VAF>(program, false),
+            None          => Ok(layout_locations::<VAF>()?
+                                .into_iter()
+                                .map(|(_, l)| Some(l))
+                                .collect()),
+        }
+    }
+
+    fn attach<T: Pod>(mut self, vbo: &VBORef<T>, columns: Vec<AttribColumn>,
+                      divisor: GLuint) -> Result<Self, Error>
+    {
+        unbound_on_error(self.array.attach(vbo, columns, divisor))?;
+        Ok(self)
+    }
+
+    /// Reads the attributes of `V` from `vbo`, one `V` per vertex.
+    pub fn vertices<V>(self, vbo: &VBORef<V>) -> Result<Self, Error>
+        where V: VertexAttribFields + Pod
+    {
+        self.instances(vbo, 0)
+    }
+
+    /// Reads the attributes of `V` from `vbo`, one `V` per `divisor`
+    /// instances.
+    pub fn instances<V>(self, vbo: &VBORef<V>, divisor: GLuint) -> Result<Self, Error>
+        where V: VertexAttribFields + Pod
+    {
+        let columns = unbound_on_error(
+            self.locations::<V>().and_then(|l| attrib_columns::<V>(&l)))?;
+        self.attach(vbo, columns, divisor)
+    }
+
+    /// Reads attribute `name`, at `location`, from a tightly packed stream
+    /// of `T`.
+    pub fn stream<T>(self, name: &'static str, location: GLuint, vbo: &VBORef<T>)
+        -> Result<Self, Error>
+        where T: HasVertexAttribSize + HasGLType + Pod
+    {
+        let columns = unbound_on_error(stream_columns::<T>(name, location))?;
+        self.attach(vbo, columns, 0)
+    }
+
+    /// Like `stream`, at the location the program has `name` at, if it
+    /// uses it. Needs a builder made with `builder_for`.
+    pub fn named_stream<T>(self, name: &'static str, vbo: &VBORef<T>) -> Result<Self, Error>
+        where T: HasVertexAttribSize + HasGLType + Pod
+    {
+        let location = unbound_on_error(match self.program {
+            Some(program) => program.get_attribute_location(name),
+            None          => Err(Error::Validation(format!(
+                "no program to look up attribute `{}` in", name))),
+        })?;
+        if location < 0 {
+            return Ok(self)
+        }
+        self.stream(name, location as GLuint, vbo)
+    }
+
+    /// Attaches the index buffer, replacing any attached before.
+    pub fn indices<I: IndexType>(mut self, ebo: &EBORef<I>) -> Result<Self, Error>
+    {
+        unbound_on_error(ebo.bind())?;
+        self.array.indices    = Some(ebo.handle.clone());
+        self.array.index_type = Some(I::get_gl_type());
+        Ok(self)
+    }
+
+    /// Unbinds the vertex array and returns it.
+    pub fn build(self) -> Result<VertexArray, Error>
+    {
+        VAORef::unbind()?;
+        Ok(self.array)
+    }
+}
+
+// Unbinds the vertex array being built if `result` is an error, so that
+// later binds cannot change it.
+fn unbound_on_error<T>(result: Result<T, Error>) -> Result<T, Error>
+{
+    if result.is_err() {
+        // The step's error is the one worth reporting.
+        let _ = VAORef::unbind();
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shader::ShaderSrc;
+    use mock::{Arg, Call, Mock};
+
+    #[repr(C)]
+    #[derive(Copy,Clone)]
+    struct Vertex {
+        pos:    [GLfloat; 3],
+        normal: [GLfloat; 3],
+    }
+
+    unsafe impl Pod for Vertex {}
+
+    implement_vertex_attrib!(Vertex, pos, normal);
+
+    #[test]
+    fn records_layout_and_keeps_buffers_alive()
+    {
+        let mock = Mock::install();
+        let vbo  = VBORef::<Vertex>::new().unwrap();
+        let uvs  = VBORef::<[GLfloat; 2]>::new().unwrap();
+        let ebo  = EBORef::<u16>::new().unwrap();
+        let (vbo_id, uvs_id, ebo_id) = (vbo.id(), uvs.id(), ebo.id());
+        let array = VertexArray::builder().unwrap()
+            .vertices(&vbo).unwrap()
+            .stream("uv", 2, &uvs).unwrap()
+            .indices(&ebo).unwrap()
+            .build().unwrap();
+        drop((vbo, uvs, ebo));
+        assert!(!mock.objects().iter().any(|&(f, _)| f == "glDeleteBuffers"));
+
+        let layout = array.layout().iter()
+            .map(|b| (b.column.name, b.column.location, b.column.offset, b.buffer, b.stride))
+            .collect::<Vec<_>>();
+        assert_eq!(layout, vec![("pos",    0, 0,  vbo_id, 24),
+                                ("normal", 1, 12, vbo_id, 24),
+                                ("uv",     2, 0,  uvs_id, 8)]);
+        assert_eq!(array.buffers(), vec![vbo_id, uvs_id]);
+        assert_eq!(array.index_buffer(), Some(ebo_id));
+        assert_eq!(array.index_type(), Some(gl::UNSIGNED_SHORT));
+
+        drop(array);
+        let deleted = mock.objects().iter()
+            .filter(|&&(f, _)| f == "glDeleteBuffers")
+            .count();
+        assert_eq!(deleted, 3);
+    }
+
+    #[test]
+    fn draws_without_rebinding_the_indices()
+    {
+        let mock = Mock::install();
+        let vbo = VBORef::<Vertex>::new().unwrap();
+        let ebo = EBORef::<u8>::new().unwrap();
+        let array = VertexArray::builder().unwrap().vertices(&vbo).unwrap().build().unwrap();
+        match array.draw_elements(gl::TRIANGLES, 3) {
+            Err(Error::Validation(msg)) => assert!(msg.contains("no index buffer"), "{}", msg),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        let array = VertexArray::builder().unwrap()
+            .vertices(&vbo).unwrap()
+            .indices(&ebo).unwrap()
+            .build().unwrap();
+        mock.clear_calls();
+        array.draw_elements(gl::TRIANGLES, 3).unwrap();
+        array.draw_elements_instanced(gl::POINTS, 1, 4).unwrap();
+        assert_eq!(mock.calls(), vec![
+            gl_call!("glBindVertexArray", array.id()),
+            gl_call!("glDrawElements", gl::TRIANGLES, 3, gl::UNSIGNED_BYTE, Arg::Ptr(0)),
+            gl_call!("glBindVertexArray", array.id()),
+            gl_call!("glDrawElementsInstanced", gl::POINTS, 1, gl::UNSIGNED_BYTE,
+                     Arg::Ptr(0), 4),
+        ]);
+    }
+
+    fn last_bound(mock: &Mock) -> Call
+    {
+        mock.calls().into_iter().rev().find(|c| c.name == "glBindVertexArray").unwrap()
+    }
+
+    #[test]
+    fn failed_steps_unbind_the_vertex_array()
+    {
+        let mock = Mock::install();
+        let vbo = VBORef::<Vertex>::new().unwrap();
+        let uvs = VBORef::<[GLfloat; 2]>::new().unwrap();
+        mock.clear_calls();
+        mock.fail_next("glVertexAttribPointer", gl::INVALID_VALUE);
+        assert!(VertexArray::builder().unwrap().vertices(&vbo).is_err());
+        assert_eq!(last_bound(&mock), gl_call!("glBindVertexArray", 0u32));
+        mock.clear_calls();
+        match VertexArray::builder().unwrap().named_stream("uv", &uvs) {
+            Err(Error::Validation(_)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert_eq!(last_bound(&mock), gl_call!("glBindVertexArray", 0u32));
+    }
+
+    #[test]
+    fn validate_against_program()
+    {
+        let mock = Mock::install();
+        mock.add_active_attribute("pos", gl::FLOAT_VEC3, 1);
+        mock.add_active_attribute("normal", gl::FLOAT_VEC3, 1);
+        let program = Program::new_with_attrib_locations(
+            ShaderSrc { vertex: "", fragment: "" }, &[("pos", 0), ("normal", 3)]).unwrap();
+        let vbo = VBORef::<Vertex>::new().unwrap();
+        let array = VertexArray::builder().unwrap()
+            .vertices(&vbo).unwrap()
+            .build().unwrap();
+        match array.validate(&program) {
+            Err(Error::Validation(_)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        let array = VertexArray::builder_for(&program).unwrap()
+            .vertices(&vbo).unwrap()
+            .build().unwrap();
+        assert_eq!(array.layout()[1].column.location, 3);
+        assert_eq!(array.validate(&program), Ok(()));
+    }
+
+    #[test]
+    fn unfed_active_attributes_are_invalid()
+    {
+        let mock = Mock::install();
+        mock.set_attribute_location("pos", 0);
+        mock.set_attribute_location("normal", 1);
+        mock.set_attribute_location("uv", 2);
+        mock.add_active_attribute("uv", gl::FLOAT_VEC2, 1);
+        let program = Program::new(ShaderSrc { vertex: "", fragment: "" }).unwrap();
+        let vbo = VBORef::<Vertex>::new().unwrap();
+        let array = VertexArray::builder().unwrap()
+            .vertices(&vbo).unwrap()
+            .build().unwrap();
+        match array.validate(&program) {
+            Err(Error::Validation(msg)) => assert!(msg.contains("`uv`"), "{}", msg),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn overlapping_locations_are_invalid()
+    {
+        let _mock = Mock::install();
+        let program = Program::new(ShaderSrc { vertex: "", fragment: "" }).unwrap();
+        let vbo = VBORef::<Vertex>::new().unwrap();
+        let uvs = VBORef::<[GLfloat; 2]>::new().unwrap();
+        let array = VertexArray::builder().unwrap()
+            .vertices(&vbo).unwrap()
+            .stream("uv", 1, &uvs).unwrap()
+            .build().unwrap();
+        match array.validate(&program) {
+            Err(Error::Validation(_)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+}
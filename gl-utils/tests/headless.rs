@@ -0,0 +1,655 @@
+#![cfg(feature = "headless")]
+
+extern crate gl;
+#[macro_use]
+extern crate gl_utils;
+
+use gl::types::*;
+use gl_utils::Error;
+use gl_utils::buffer::{BufferUsage, Pod};
+use gl_utils::ebo::EBORef;
+use gl_utils::mesh::Mesh;
+use gl_utils::headless::{DEFAULT_SIZE, HeadlessContext, with_headless_context,
+                         with_headless_context_version};
+use gl_utils::shader::{Program, ShaderSrc};
+use gl_utils::ssbo::ShaderStorageBuffer;
+use gl_utils::std140::Std140;
+use gl_utils::texture::{TexMagFilter, TexMinFilter, TexParameter, TextureRef};
+use gl_utils::ubo::UniformBuffer;
+use gl_utils::util::*;
+use gl_utils::vao::VAORef;
+use gl_utils::vbo::VBORef;
+
+// A full screen triangle generated from gl_VertexID, so no vertex buffer
+// is needed.
+const FULLSCREEN_VERTEX: &str = "
+    #version 330 core
+    out vec2 uv;
+    void main() {
+        uv = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+        gl_Position = vec4(uv * 2.0 - 1.0, 0.0, 1.0);
+    }";
+
+const SAMPLE_FRAGMENT: &str = "
+    #version 330 core
+    in vec2 uv;
+    uniform sampler2D tex;
+    out vec4 color;
+    void main() {
+        color = texture(tex, uv);
+    }";
+
+const GREEN_FRAGMENT: &str = "
+    #version 330 core
+    out vec4 color;
+    void main() {
+        color = vec4(0.0, 1.0, 0.0, 1.0);
+    }";
+
+#[test]
+fn compiles_and_links_real_shaders()
+{
+    with_headless_context(|_| {
+        Program::new(ShaderSrc {
+            vertex:   FULLSCREEN_VERTEX,
+            fragment: SAMPLE_FRAGMENT,
+        }).unwrap();
+    })
+}
+
+#[test]
+fn reflects_the_linked_interface()
+{
+    with_headless_context(|_| {
+        let program = Program::new(ShaderSrc {
+            vertex:   "
+                #version 330 core
+                layout(location = 2) in vec3 pos;
+                uniform mat4 mvp;
+                uniform vec4 lights[4];
+                void main() {
+                    gl_Position = mvp * vec4(pos, 1.0) + lights[3];
+                }",
+            fragment: SAMPLE_FRAGMENT.replace("in vec2 uv;", "vec2 uv = vec2(0.5);").as_str(),
+        }).unwrap();
+        let pos = program.attribute("pos").unwrap();
+        assert_eq!((pos.gl_type, pos.size, pos.location), (gl::FLOAT_VEC3, 1, 2));
+        let lights = program.uniform("lights").unwrap();
+        assert_eq!((lights.gl_type, lights.size), (gl::FLOAT_VEC4, 4));
+        assert_eq!(lights.location, program.get_uniform_location("lights").unwrap());
+        assert_eq!(program.uniform("mvp").map(|u| u.gl_type), Some(gl::FLOAT_MAT4));
+        assert_eq!(program.uniform("tex").map(|u| u.gl_type), Some(gl::SAMPLER_2D));
+        assert_eq!(program.active_uniforms().len(), 3);
+    })
+}
+
+#[test]
+fn reports_the_driver_compile_log()
+{
+    with_headless_context(|_| {
+        let r = Program::new(ShaderSrc {
+            vertex:   FULLSCREEN_VERTEX,
+            fragment: "#version 330 core\nvoid main() { undeclared = 1.0; }",
+        });
+        match r {
+            Err(Error::ShaderCompile { stage: gl::FRAGMENT_SHADER, ref log })
+                if log.contains("undeclared") => (),
+            r => panic!("unexpected result: {:?}", r.map(|_| ())),
+        }
+    })
+}
+
+#[test]
+fn failed_context_creation_releases_egl()
+{
+    match HeadlessContext::with_version(DEFAULT_SIZE, DEFAULT_SIZE, 9, 9).map(|_| ()) {
+        Err(Error::Context(msg)) => assert!(msg.contains("eglCreateContext"), "{}", msg),
+        r => panic!("unexpected result: {:?}", r),
+    }
+    with_headless_context(|ctx| assert_eq!(ctx.width(), DEFAULT_SIZE));
+}
+
+#[test]
+fn clear_color_reads_back()
+{
+    with_headless_context(|ctx| {
+        clear_color(0.0, 1.0, 0.0, 1.0).unwrap();
+        clear(gl::COLOR_BUFFER_BIT).unwrap();
+        let pixels = ctx.read_pixels().unwrap();
+        assert!(pixels.pixels().all(|p| p.0 == [0, 255, 0, 255]));
+    })
+}
+
+#[test]
+fn uploaded_texture_is_sampled()
+{
+    with_headless_context(|ctx| {
+        let program = Program::new(ShaderSrc {
+            vertex:   FULLSCREEN_VERTEX,
+            fragment: SAMPLE_FRAGMENT,
+        }).unwrap();
+        // Left column red, right column blue.
+        let texels: [[u8; 4]; 4] = [[255, 0, 0, 255], [0, 0, 255, 255],
+                                    [255, 0, 0, 255], [0, 0, 255, 255]];
+        let tex = TextureRef::new(gl::TEXTURE_2D).unwrap();
+        tex.bind().unwrap();
+        tex.image_2d(0, gl::RGBA8 as GLint, 2, 2, 0, gl::RGBA, gl::UNSIGNED_BYTE,
+                     texels.as_ptr()).unwrap();
+        tex.parameter(TexParameter::MinFilter(TexMinFilter::Nearest)).unwrap();
+        tex.parameter(TexParameter::MagFilter(TexMagFilter::Nearest)).unwrap();
+        let vao = VAORef::new().unwrap();
+        vao.bind().unwrap();
+        program.use_program().unwrap();
+        setup_uniforms(&uniforms!{ tex: tex, }, &program).unwrap();
+        draw_arrays(gl::TRIANGLES, 0, 3).unwrap();
+
+        let pixels = ctx.read_pixels().unwrap();
+        let (w, h) = (ctx.width() as u32, ctx.height() as u32);
+        assert_eq!(pixels.get_pixel(0, h / 2).0, [255, 0, 0, 255]);
+        assert_eq!(pixels.get_pixel(w - 1, h / 2).0, [0, 0, 255, 255]);
+    })
+}
+
+#[test]
+fn int_and_array_uniforms_reach_the_shader()
+{
+    with_headless_context(|ctx| {
+        let program = Program::new(ShaderSrc {
+            vertex:   FULLSCREEN_VERTEX,
+            fragment: "
+                #version 330 core
+                uniform int count;
+                uniform vec4 lights[16];
+                uniform bool dim;
+                out vec4 color;
+                void main() {
+                    vec4 sum = vec4(0.0);
+                    for (int i = 0; i < count; i++) {
+                        sum += lights[i];
+                    }
+                    color = dim ? sum * 0.5 : sum;
+                }",
+        }).unwrap();
+        let mut lights = [[0.0f32; 4]; 16];
+        lights[0] = [0.5, 0.0, 0.0, 0.5];
+        lights[1] = [0.5, 0.0, 1.0, 0.5];
+        lights[2] = [0.0, 1.0, 0.0, 0.0];
+        let vao = VAORef::new().unwrap();
+        vao.bind().unwrap();
+        program.use_program().unwrap();
+        setup_uniforms(&uniforms!{ count: 2, lights: &lights[..], dim: false, }, &program)
+            .unwrap();
+        draw_arrays(gl::TRIANGLES, 0, 3).unwrap();
+        let pixels = ctx.read_pixels().unwrap();
+        assert_eq!(pixels.get_pixel(0, 0).0, [255, 0, 255, 255]);
+    })
+}
+
+#[test]
+fn buffer_update_reads_back()
+{
+    with_headless_context(|_| {
+        let vbo = VBORef::new_with_data(&[[0.0f32, 1.0], [2.0, 3.0], [4.0, 5.0]],
+                                        BufferUsage::DynamicDraw).unwrap();
+        vbo.update(2, &[[6.0, 7.0]]).unwrap();
+        assert_eq!(vbo.read_back().unwrap(), vec![[0.0, 1.0], [2.0, 3.0], [6.0, 7.0]]);
+        vbo.resize(1).unwrap();
+        assert_eq!(vbo.read_back().unwrap().len(), 1);
+    })
+}
+
+#[test]
+fn draw_elements_uses_the_index_buffer()
+{
+    with_headless_context(|ctx| {
+        let program = Program::new(ShaderSrc {
+            vertex:   FULLSCREEN_VERTEX,
+            fragment: GREEN_FRAGMENT,
+        }).unwrap();
+        let vao = VAORef::new().unwrap();
+        vao.bind().unwrap();
+        program.use_program().unwrap();
+        // With no vertex buffer gl_VertexID is the index itself.
+        let ebo = EBORef::new_with_data(&[2u8, 1, 0], BufferUsage::StaticDraw).unwrap();
+        clear_color(0.0, 0.0, 0.0, 1.0).unwrap();
+        clear(gl::COLOR_BUFFER_BIT).unwrap();
+        draw_elements(gl::TRIANGLES, &ebo).unwrap();
+        let pixels = ctx.read_pixels().unwrap();
+        assert!(pixels.pixels().all(|p| p.0 == [0, 255, 0, 255]));
+    })
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct TaggedVertex {
+    pos: [GLfloat; 2],
+    tag: GLuint,
+}
+
+unsafe impl Pod for TaggedVertex {}
+
+implement_vertex_attrib!(TaggedVertex, pos, tag);
+
+#[test]
+fn integer_attributes_reach_the_shader_unconverted()
+{
+    with_headless_context(|ctx| {
+        let program = Program::new(ShaderSrc {
+            vertex: "
+                #version 330 core
+                in vec2 pos;
+                in uint tag;
+                flat out uint v_tag;
+                void main() {
+                    v_tag = tag;
+                    gl_Position = vec4(pos, 0.0, 1.0);
+                }",
+            fragment: "
+                #version 330 core
+                flat in uint v_tag;
+                out vec4 color;
+                void main() {
+                    color = v_tag == 7u ? vec4(0.0, 1.0, 0.0, 1.0) : vec4(1.0, 0.0, 0.0, 1.0);
+                }",
+        }).unwrap();
+        let vertices = [TaggedVertex { pos: [-1.0, -1.0], tag: 7 },
+                        TaggedVertex { pos: [ 3.0, -1.0], tag: 7 },
+                        TaggedVertex { pos: [-1.0,  3.0], tag: 7 }];
+        let mesh: Mesh<TaggedVertex> = Mesh::new(&program, gl::TRIANGLES, &vertices, &[0, 1, 2],
+                                                 BufferUsage::StaticDraw).unwrap();
+        mesh.draw(&program, &[]).unwrap();
+        let pixels = ctx.read_pixels().unwrap();
+        assert!(pixels.pixels().all(|p| p.0 == [0, 255, 0, 255]));
+    })
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct MatrixVertex {
+    pos:    [GLfloat; 2],
+    colors: [[GLfloat; 2]; 2],
+}
+
+unsafe impl Pod for MatrixVertex {}
+
+implement_vertex_attrib!(MatrixVertex, pos, colors);
+
+#[test]
+fn matrix_attributes_fill_every_column()
+{
+    with_headless_context(|ctx| {
+        let program = Program::new(ShaderSrc {
+            vertex: "
+                #version 330 core
+                in vec2 pos;
+                in mat2 colors;
+                out vec4 v_color;
+                void main() {
+                    v_color = vec4(colors[0], colors[1]);
+                    gl_Position = vec4(pos, 0.0, 1.0);
+                }",
+            fragment: "
+                #version 330 core
+                in vec4 v_color;
+                out vec4 color;
+                void main() {
+                    color = v_color;
+                }",
+        }).unwrap();
+        let colors = [[0.0, 1.0], [0.0, 1.0]];
+        let vertices = [MatrixVertex { pos: [-1.0, -1.0], colors },
+                        MatrixVertex { pos: [ 3.0, -1.0], colors },
+                        MatrixVertex { pos: [-1.0,  3.0], colors }];
+        let mesh: Mesh<MatrixVertex> = Mesh::new(&program, gl::TRIANGLES, &vertices, &[0, 1, 2],
+                                                 BufferUsage::StaticDraw).unwrap();
+        mesh.draw(&program, &[]).unwrap();
+        let pixels = ctx.read_pixels().unwrap();
+        assert!(pixels.pixels().all(|p| p.0 == [0, 255, 0, 255]));
+    })
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct Position {
+    pos: [GLfloat; 2],
+}
+
+unsafe impl Pod for Position {}
+
+implement_vertex_attrib!(Position, pos);
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct Offset {
+    offset: [GLfloat; 2],
+}
+
+unsafe impl Pod for Offset {}
+
+implement_vertex_attrib!(Offset, offset);
+
+#[test]
+fn instances_read_their_own_attributes()
+{
+    with_headless_context(|ctx| {
+        let program = Program::new(ShaderSrc {
+            vertex: "
+                #version 330 core
+                in vec2 pos;
+                in vec2 offset;
+                void main() {
+                    gl_Position = vec4(pos + offset, 0.0, 1.0);
+                }",
+            fragment: GREEN_FRAGMENT,
+        }).unwrap();
+        // The left half of the screen, moved right by the second instance.
+        let quad = [Position { pos: [-1.0, -1.0] }, Position { pos: [0.0, -1.0] },
+                    Position { pos: [-1.0,  1.0] }, Position { pos: [0.0,  1.0] }];
+        let mesh: Mesh<Position> = Mesh::new(&program, gl::TRIANGLES, &quad,
+                                             &[0, 1, 2, 2, 1, 3],
+                                             BufferUsage::StaticDraw).unwrap();
+        let offsets = VBORef::new_with_data(&[Offset { offset: [0.0, 0.0] },
+                                              Offset { offset: [1.0, 0.0] }],
+                                            BufferUsage::StaticDraw).unwrap();
+        mesh.vao().bind().unwrap();
+        offsets.bind().unwrap();
+        setup_instance_attrib::<Offset>(&program, 1).unwrap();
+
+        clear_color(0.0, 0.0, 0.0, 1.0).unwrap();
+        clear(gl::COLOR_BUFFER_BIT).unwrap();
+        mesh.draw_instanced(&program, &[], 2).unwrap();
+        let pixels = ctx.read_pixels().unwrap();
+        assert!(pixels.pixels().all(|p| p.0 == [0, 255, 0, 255]));
+    })
+}
+
+#[test]
+fn per_vertex_setup_resets_the_divisor()
+{
+    with_headless_context(|ctx| {
+        let program = Program::new(ShaderSrc {
+            vertex: "
+                #version 330 core
+                in vec2 pos;
+                in vec2 offset;
+                void main() {
+                    gl_Position = vec4(pos + offset, 0.0, 1.0);
+                }",
+            fragment: GREEN_FRAGMENT,
+        }).unwrap();
+        // A strip over the left half of the screen.
+        let strip = VBORef::new_with_data(&[Position { pos: [-1.0, -1.0] },
+                                            Position { pos: [ 0.0, -1.0] },
+                                            Position { pos: [-1.0,  1.0] },
+                                            Position { pos: [ 0.0,  1.0] }],
+                                          BufferUsage::StaticDraw).unwrap();
+        let vao = VAORef::new().unwrap();
+        vao.bind().unwrap();
+        strip.bind().unwrap();
+        setup_vertex_attrib::<Position>(&program).unwrap();
+        program.use_program().unwrap();
+
+        // The second instance moves the strip right.
+        let per_instance = VBORef::new_with_data(&[Offset { offset: [0.0, 0.0] },
+                                                   Offset { offset: [1.0, 0.0] }],
+                                                 BufferUsage::StaticDraw).unwrap();
+        per_instance.bind().unwrap();
+        setup_instance_attrib::<Offset>(&program, 1).unwrap();
+        clear(gl::COLOR_BUFFER_BIT).unwrap();
+        draw_arrays_instanced(gl::TRIANGLE_STRIP, 0, 4, 2).unwrap();
+        assert!(ctx.read_pixels().unwrap().pixels().all(|p| p.0 == [0, 255, 0, 255]));
+
+        // Per vertex, the right edge moves right. Were the divisor still 1,
+        // every vertex would read the first offset and leave the strip put.
+        let per_vertex = VBORef::new_with_data(&[Offset { offset: [0.0, 0.0] },
+                                                 Offset { offset: [1.0, 0.0] },
+                                                 Offset { offset: [0.0, 0.0] },
+                                                 Offset { offset: [1.0, 0.0] }],
+                                               BufferUsage::StaticDraw).unwrap();
+        per_vertex.bind().unwrap();
+        setup_vertex_attrib::<Offset>(&program).unwrap();
+        clear(gl::COLOR_BUFFER_BIT).unwrap();
+        draw_arrays(gl::TRIANGLE_STRIP, 0, 4).unwrap();
+        assert!(ctx.read_pixels().unwrap().pixels().all(|p| p.0 == [0, 255, 0, 255]));
+    })
+}
+
+#[test]
+fn one_layout_serves_several_programs()
+{
+    with_headless_context(|ctx| {
+        let explicit = Program::new(ShaderSrc {
+            vertex: "
+                #version 330 core
+                layout(location = 0) in vec2 pos;
+                void main() {
+                    gl_Position = vec4(pos, 0.0, 1.0);
+                }",
+            fragment: GREEN_FRAGMENT,
+        }).unwrap();
+        let bound = Program::new_with_attrib_locations(ShaderSrc {
+            vertex: "
+                #version 330 core
+                in vec2 unused;
+                in vec2 pos;
+                void main() {
+                    gl_Position = vec4(pos + unused * 0.0, 0.0, 1.0);
+                }",
+            fragment: GREEN_FRAGMENT,
+        }, &[("unused", 1), ("pos", 0)]).unwrap();
+
+        let vao = VAORef::new().unwrap();
+        vao.bind().unwrap();
+        let vbo = VBORef::new_with_data(&[Position { pos: [-1.0, -1.0] },
+                                          Position { pos: [ 3.0, -1.0] },
+                                          Position { pos: [-1.0,  3.0] }],
+                                        BufferUsage::StaticDraw).unwrap();
+        vbo.bind().unwrap();
+        setup_vertex_layout::<Position>().unwrap();
+        for program in &[explicit, bound] {
+            clear_color(0.0, 0.0, 0.0, 1.0).unwrap();
+            clear(gl::COLOR_BUFFER_BIT).unwrap();
+            program.use_program().unwrap();
+            draw_arrays(gl::TRIANGLES, 0, 3).unwrap();
+            let pixels = ctx.read_pixels().unwrap();
+            assert!(pixels.pixels().all(|p| p.0 == [0, 255, 0, 255]));
+        }
+    })
+}
+
+#[test]
+fn vertex_buffers_swap_under_one_format()
+{
+    with_headless_context_version(4, 3, |ctx| {
+        let program = Program::new(ShaderSrc {
+            vertex: "
+                #version 330 core
+                layout(location = 0) in vec2 pos;
+                void main() {
+                    gl_Position = vec4(pos, 0.0, 1.0);
+                }",
+            fragment: GREEN_FRAGMENT,
+        }).unwrap();
+        let vao = VAORef::new().unwrap();
+        vao.attrib_format::<Position>(0).unwrap();
+        // Off screen, then covering it.
+        let away = VBORef::new_with_data(&[Position { pos: [5.0, 5.0] }; 3],
+                                         BufferUsage::StaticDraw).unwrap();
+        let full = VBORef::new_with_data(&[Position { pos: [-1.0, -1.0] },
+                                           Position { pos: [ 3.0, -1.0] },
+                                           Position { pos: [-1.0,  3.0] }],
+                                         BufferUsage::StaticDraw).unwrap();
+        program.use_program().unwrap();
+        clear_color(0.0, 0.0, 0.0, 1.0).unwrap();
+        clear(gl::COLOR_BUFFER_BIT).unwrap();
+        vao.bind_vertex_buffer::<Position>(0, &away, 0).unwrap();
+        draw_arrays(gl::TRIANGLES, 0, 3).unwrap();
+        assert!(ctx.read_pixels().unwrap().pixels().all(|p| p.0 == [0, 0, 0, 255]));
+        vao.bind_vertex_buffer::<Position>(0, &full, 0).unwrap();
+        draw_arrays(gl::TRIANGLES, 0, 3).unwrap();
+        assert!(ctx.read_pixels().unwrap().pixels().all(|p| p.0 == [0, 255, 0, 255]));
+    })
+}
+
+#[test]
+fn separate_streams_feed_one_vertex_array()
+{
+    with_headless_context(|ctx| {
+        let program = Program::new(ShaderSrc {
+            vertex: "
+                #version 330 core
+                in vec2 pos;
+                in vec4 tint;
+                out vec4 v_tint;
+                void main() {
+                    v_tint = tint;
+                    gl_Position = vec4(pos, 0.0, 1.0);
+                }",
+            fragment: "
+                #version 330 core
+                in vec4 v_tint;
+                out vec4 color;
+                void main() {
+                    color = v_tint;
+                }",
+        }).unwrap();
+        let positions = VBORef::new_with_data(&[[-1.0f32, -1.0], [3.0, -1.0], [-1.0, 3.0]],
+                                              BufferUsage::StaticDraw).unwrap();
+        let tints = VBORef::new_with_data(&[[0.0f32, 1.0, 0.0, 1.0]; 3],
+                                          BufferUsage::StaticDraw).unwrap();
+        let vao = VAORef::new().unwrap();
+        vao.bind().unwrap();
+        setup_named_attrib_stream(&program, "pos", &positions).unwrap();
+        setup_named_attrib_stream(&program, "tint", &tints).unwrap();
+        program.use_program().unwrap();
+        draw_arrays(gl::TRIANGLES, 0, 3).unwrap();
+        let pixels = ctx.read_pixels().unwrap();
+        assert!(pixels.pixels().all(|p| p.0 == [0, 255, 0, 255]));
+    })
+}
+
+struct Material {
+    tint:     [GLfloat; 3],
+    strength: GLfloat,
+    weights:  [[GLfloat; 1]; 2],
+    base:     [GLfloat; 4],
+}
+
+implement_std140!(Material { tint: 0; strength: 12; weights: 16; base: 48; });
+
+#[test]
+fn uniform_buffer_matches_the_std140_block()
+{
+    with_headless_context(|ctx| {
+        let program = Program::new(ShaderSrc {
+            vertex:   FULLSCREEN_VERTEX,
+            fragment: "
+                #version 330 core
+                layout(std140) uniform Material {
+                    vec3 tint;
+                    float strength;
+                    float weights[2];
+                    vec4 base;
+                };
+                out vec4 color;
+                void main() {
+                    color = base + vec4(tint * strength * weights[1], 0.0);
+                }",
+        }).unwrap();
+        let mut material = Material {
+            tint:     [1.0, 0.0, 0.0],
+            strength: 0.5,
+            weights:  [[0.0], [2.0]],
+            base:     [0.0, 0.0, 1.0, 1.0],
+        };
+        assert_eq!(program.uniform_block_data_size("Material").unwrap(),
+                   Some(Material::STD140_SIZE));
+        let ubo = UniformBuffer::new(&material, BufferUsage::DynamicDraw).unwrap();
+        ubo.check_block(&program, "Material").unwrap();
+        match setup_uniforms(&uniforms!{ strength: 1.0f32, }, &program) {
+            Err(Error::Validation(msg)) => assert!(msg.contains("UniformBuffer"), "{}", msg),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        ubo.bind_base(3).unwrap();
+        program.bind_uniform_block("Material", 3).unwrap();
+        let vao = VAORef::new().unwrap();
+        vao.bind().unwrap();
+        program.use_program().unwrap();
+        draw_arrays(gl::TRIANGLES, 0, 3).unwrap();
+        assert_eq!(ctx.read_pixels().unwrap().get_pixel(0, 0).0, [255, 0, 255, 255]);
+
+        material.base = [0.0, 1.0, 0.0, 1.0];
+        ubo.update(&material).unwrap();
+        draw_arrays(gl::TRIANGLES, 0, 3).unwrap();
+        assert_eq!(ctx.read_pixels().unwrap().get_pixel(0, 0).0, [255, 255, 0, 255]);
+    })
+}
+
+struct Emitter {
+    origin: [GLfloat; 3],
+}
+
+implement_std430!(Emitter, origin);
+
+#[derive(Debug, PartialEq)]
+struct Particle {
+    velocity: [GLfloat; 2],
+    age:      GLfloat,
+}
+
+implement_std430!(Particle { velocity: 0; age: 8; });
+
+#[test]
+fn shader_storage_buffers_are_read_and_written()
+{
+    with_headless_context_version(4, 3, |ctx| {
+        let program = Program::new(ShaderSrc {
+            vertex:   FULLSCREEN_VERTEX.replace("330", "430").as_str(),
+            fragment: "
+                #version 430 core
+                struct Particle {
+                    vec2 velocity;
+                    float age;
+                };
+                layout(std430) buffer Particles {
+                    vec3 origin;
+                    Particle particles[];
+                };
+                layout(std430) buffer Counters {
+                    uint hits;
+                };
+                out vec4 color;
+                void main() {
+                    atomicAdd(hits, 1u);
+                    particles[0].velocity = vec2(float(particles.length()), 0.5);
+                    color = vec4(origin.x, particles[particles.length() - 1].age, 0.0, 1.0);
+                }",
+        }).unwrap();
+        let particles = ShaderStorageBuffer::with_elements(
+            &Emitter { origin: [1.0, 0.0, 0.0] },
+            &[Particle { velocity: [0.0; 2], age: 0.0 },
+              Particle { velocity: [0.0; 2], age: 0.0 },
+              Particle { velocity: [0.0; 2], age: 1.0 }],
+            BufferUsage::DynamicCopy).unwrap();
+        let counters = ShaderStorageBuffer::new(&0u32, BufferUsage::DynamicCopy).unwrap();
+        particles.check_block(&program, "Particles").unwrap();
+        counters.check_block(&program, "Counters").unwrap();
+        particles.bind_base(0).unwrap();
+        counters.bind_base(5).unwrap();
+        program.bind_shader_storage_block("Particles", 0).unwrap();
+        program.bind_shader_storage_block("Counters", 5).unwrap();
+
+        let vao = VAORef::new().unwrap();
+        vao.bind().unwrap();
+        program.use_program().unwrap();
+        draw_arrays(gl::TRIANGLES, 0, 3).unwrap();
+        unsafe { gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT) };
+        assert_eq!(ctx.read_pixels().unwrap().get_pixel(0, 0).0, [255, 255, 0, 255]);
+        assert_eq!(counters.read().unwrap(), (ctx.width() * ctx.height()) as GLuint);
+        assert_eq!(particles.read_elements().unwrap(),
+                   vec![Particle { velocity: [3.0, 0.5], age: 0.0 },
+                        Particle { velocity: [0.0; 2],   age: 0.0 },
+                        Particle { velocity: [0.0; 2],   age: 1.0 }]);
+        assert_eq!(particles.read().unwrap().origin, [1.0, 0.0, 0.0]);
+    })
+}